@@ -126,7 +126,7 @@ fn main() -> Result<(), ()> {
         renderer.clear(0.2, 0.3, 0.3, 1.0);
         shader.apply();
         renderer.draw_quad(&q, &tex);
-
+        renderer.flush();
 
         windowed_context.swap_buffers().unwrap();
     });
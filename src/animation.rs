@@ -0,0 +1,534 @@
+use crate::spritesheet::{SpriteSheet, TagDirection};
+use std::collections::HashMap;
+
+/**
+ * Frames shorter than this are stretched to it, so zero durations can't stall update()
+*/
+const MIN_FRAME_DURATION: f32 = 0.001;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PlayMode {
+    /**
+     * Plays through once and stops on the last frame
+    */
+    Once,
+    Loop,
+    /**
+     * Plays forward then backward, repeating. End frames aren't repeated at the turns
+    */
+    PingPong,
+    /**
+     * Plays backward from the last frame, repeating
+    */
+    Reverse,
+    /**
+     * Plays backward once and stops on the first frame
+    */
+    ReverseOnce,
+    /**
+     * PingPong starting from the last frame going backward
+    */
+    PingPongReverse,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AnimationFrame {
+    /**
+     * Whatever the frame refers to, usually an index into SpriteSheet::frames()
+    */
+    pub index: usize,
+    /**
+     * Seconds
+    */
+    pub duration: f32,
+}
+
+/**
+ * Named clip, a list of frames with durations plus events fired when certain frames are reached
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Animation {
+    pub name: String,
+    pub frames: Vec<AnimationFrame>,
+    pub mode: PlayMode,
+    /**
+     * (position in frames, event name)
+    */
+    pub events: Vec<(usize, String)>,
+}
+
+impl Animation {
+    pub fn new(name: &str, frames: Vec<AnimationFrame>, mode: PlayMode) -> Self {
+        Animation { name: name.to_string(), frames, mode, events: Vec::new() }
+    }
+
+    /**
+     * Clip where every frame lasts frame_duration seconds
+    */
+    pub fn uniform(name: &str, indices: &[usize], frame_duration: f32, mode: PlayMode) -> Self {
+        let frames = indices.iter().map(|i| AnimationFrame { index: *i, duration: frame_duration }).collect();
+        Animation::new(name, frames, mode)
+    }
+
+    /**
+     * Clip from an Aseprite frame tag, using each frame's exported duration or default_duration
+     * when it has none. Frame indices point into sheet.frames()
+    */
+    pub fn from_tag(sheet: &SpriteSheet, tag: &str, default_duration: f32) -> Result<Self, String> {
+        let t = sheet.tag(tag).ok_or_else(|| format!("AnimationError: sprite sheet has no tag '{}'", tag))?;
+        let frames = (t.from..=t.to)
+            .map(|i| {
+                let d = sheet.frames()[i].duration;
+                AnimationFrame { index: i, duration: if d > 0. { d } else { default_duration } }
+            })
+            .collect();
+        let mode = match t.direction {
+            TagDirection::Forward => PlayMode::Loop,
+            TagDirection::Reverse => PlayMode::Reverse,
+            TagDirection::PingPong => PlayMode::PingPong,
+            TagDirection::PingPongReverse => PlayMode::PingPongReverse,
+        };
+        Ok(Animation::new(tag, frames, mode))
+    }
+
+    /**
+     * Fires event when playback reaches the frame at position (into frames)
+    */
+    pub fn add_event(&mut self, position: usize, name: &str) -> &mut Self {
+        self.events.push((position, name.to_string()));
+        self
+    }
+
+    /**
+     * Seconds for a single pass through every frame
+    */
+    pub fn duration(&self) -> f32 {
+        self.frames.iter().map(|f| f.duration.max(MIN_FRAME_DURATION)).sum()
+    }
+
+    fn start_position(&self) -> usize {
+        match self.mode {
+            PlayMode::Reverse | PlayMode::ReverseOnce | PlayMode::PingPongReverse => self.frames.len().saturating_sub(1),
+            _ => 0
+        }
+    }
+
+    fn start_direction(&self) -> i32 {
+        if self.mode == PlayMode::PingPongReverse { -1 } else { 1 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AnimationEvent {
+    /**
+     * Event added with Animation::add_event(), frame is the position in the clip
+    */
+    Frame { clip: String, name: String, frame: usize },
+    /**
+     * Looping clip wrapped around (or ping pong clip got back to where it started)
+    */
+    Looped { clip: String },
+    /**
+     * Once / ReverseOnce clip reached its end
+    */
+    Finished { clip: String },
+}
+
+/**
+ * Steps through named clips using the delta time passed to update(), e.g. from FrameTimer::elapsed().
+ * Holds no GL state, so it can live next to sprites and drive them with set_source_rect()
+*/
+#[derive(Debug, Clone)]
+pub struct AnimationPlayer {
+    clips: HashMap<String, Animation>,
+    current: Option<String>,
+    position: usize,
+    direction: i32,
+    time: f32,
+    speed: f32,
+    playing: bool,
+    finished: bool,
+    /**
+     * Events for the first frame are fired on the update() after play()
+    */
+    entered: bool,
+}
+
+impl AnimationPlayer {
+    pub fn new() -> Self {
+        AnimationPlayer {
+            clips: HashMap::new(),
+            current: None,
+            position: 0,
+            direction: 1,
+            time: 0.,
+            speed: 1.,
+            playing: false,
+            finished: false,
+            entered: false,
+        }
+    }
+
+    /**
+     * Adds clip, replacing any clip with the same name. Replacing the playing clip keeps the
+     * position, clamped to the new clip's frames
+    */
+    pub fn add_clip(&mut self, clip: Animation) -> &mut Self {
+        self.clips.insert(clip.name.clone(), clip);
+        self
+    }
+
+    pub fn clip(&self, name: &str) -> Option<&Animation> { self.clips.get(name) }
+
+    /**
+     * Frames can be changed while the clip plays, update() clamps the position to whatever is left
+    */
+    pub fn clip_mut(&mut self, name: &str) -> Option<&mut Animation> { self.clips.get_mut(name) }
+
+    /**
+     * Switches to the named clip from its start. Playing the clip that is already
+     * playing does nothing, so this can be called every frame
+    */
+    pub fn play(&mut self, name: &str) -> Result<(), String> {
+        if self.current.as_deref() == Some(name) && self.playing {
+            return Ok(());
+        }
+        let clip = self.clips.get(name).ok_or_else(|| format!("AnimationError: no clip named '{}'", name))?;
+        if clip.frames.is_empty() {
+            return Err(format!("AnimationError: clip '{}' has no frames", name));
+        }
+
+        self.position = clip.start_position();
+        self.direction = clip.start_direction();
+        self.current = Some(name.to_string());
+        self.restart_state();
+        Ok(())
+    }
+
+    /**
+     * Plays the current clip again from its start
+    */
+    pub fn restart(&mut self) {
+        if let Some((position, direction)) = self.current_clip().map(|c| (c.start_position(), c.start_direction())) {
+            self.position = position;
+            self.direction = direction;
+            self.restart_state();
+        }
+    }
+
+    pub fn pause(&mut self) { self.playing = false; }
+
+    pub fn resume(&mut self) {
+        if self.current.is_some() && !self.finished {
+            self.playing = true;
+        }
+    }
+
+    /**
+     * Stops playback and forgets the current clip
+    */
+    pub fn stop(&mut self) {
+        self.current = None;
+        self.playing = false;
+        self.finished = false;
+    }
+
+    /**
+     * Multiplier on delta time, clamped to 0 or more
+    */
+    pub fn set_speed(&mut self, speed: f32) { self.speed = speed.max(0.); }
+    pub fn speed(&self) -> f32 { self.speed }
+
+    pub fn is_playing(&self) -> bool { self.playing }
+    pub fn is_finished(&self) -> bool { self.finished }
+
+    pub fn current_clip(&self) -> Option<&Animation> {
+        self.current.as_ref().and_then(|c| self.clips.get(c))
+    }
+
+    /**
+     * Position in the current clip's frames
+    */
+    pub fn position(&self) -> usize { self.position }
+
+    /**
+     * AnimationFrame::index of the frame being shown, None when the current clip has no frame
+     * at position (it was shortened through clip_mut() and update() hasn't run since)
+    */
+    pub fn frame_index(&self) -> Option<usize> {
+        self.current_clip().and_then(|c| c.frames.get(self.position)).map(|f| f.index)
+    }
+
+    /**
+     * Advances playback by dt seconds (scaled by speed), returning events in the order they happened
+    */
+    pub fn update(&mut self, dt: f32) -> Vec<AnimationEvent> {
+        let mut events = Vec::new();
+        let name = match self.current.clone() {
+            Some(c) if self.playing => c,
+            _ => return events
+        };
+        let clip = match self.clips.get(&name) {
+            Some(c) if !c.frames.is_empty() => c,
+            _ => return events
+        };
+        // The clip may have been replaced or shortened since the last update
+        self.position = self.position.min(clip.frames.len() - 1);
+
+        if !self.entered {
+            self.entered = true;
+            push_frame_events(clip, self.position, &mut events);
+        }
+
+        self.time += dt * self.speed;
+        loop {
+            let duration = clip.frames[self.position].duration.max(MIN_FRAME_DURATION);
+            if self.time < duration {
+                break;
+            }
+            self.time -= duration;
+
+            if !advance(clip, &mut self.position, &mut self.direction, &mut events) {
+                self.time = 0.;
+                self.playing = false;
+                self.finished = true;
+                events.push(AnimationEvent::Finished { clip: name });
+                break;
+            }
+            push_frame_events(clip, self.position, &mut events);
+        }
+        events
+    }
+
+    fn restart_state(&mut self) {
+        self.time = 0.;
+        self.playing = true;
+        self.finished = false;
+        self.entered = false;
+    }
+}
+
+impl Default for AnimationPlayer {
+    fn default() -> Self { AnimationPlayer::new() }
+}
+
+fn push_frame_events(clip: &Animation, position: usize, events: &mut Vec<AnimationEvent>) {
+    for (frame, name) in clip.events.iter().filter(|(f, _)| *f == position) {
+        events.push(AnimationEvent::Frame { clip: clip.name.clone(), name: name.clone(), frame: *frame });
+    }
+}
+
+/**
+ * Moves to the next frame for the clip's mode, false when a one shot clip has ended
+*/
+fn advance(clip: &Animation, position: &mut usize, direction: &mut i32, events: &mut Vec<AnimationEvent>) -> bool {
+    let last = clip.frames.len() - 1;
+    let looped = || AnimationEvent::Looped { clip: clip.name.clone() };
+
+    match clip.mode {
+        PlayMode::Once => {
+            if *position == last { return false; }
+            *position += 1;
+        },
+        PlayMode::ReverseOnce => {
+            if *position == 0 { return false; }
+            *position -= 1;
+        },
+        PlayMode::Loop => {
+            *position = if *position == last { 0 } else { *position + 1 };
+            if *position == 0 { events.push(looped()); }
+        },
+        PlayMode::Reverse => {
+            *position = if *position == 0 { last } else { *position - 1 };
+            if *position == last { events.push(looped()); }
+        },
+        PlayMode::PingPong | PlayMode::PingPongReverse => {
+            if last == 0 {
+                events.push(looped());
+                return true;
+            }
+            let next = *position as i64 + *direction as i64;
+            if next < 0 || next > last as i64 {
+                *direction = -*direction;
+            }
+            *position = (*position as i64 + *direction as i64) as usize;
+            if *position == clip.start_position() { events.push(looped()); }
+        },
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphics::Texture;
+    use std::rc::Rc;
+
+    // Durations are powers of two so sums stay exact
+    const FRAME: f32 = 0.25;
+
+    fn player(mode: PlayMode, frame_count: usize) -> AnimationPlayer {
+        let indices: Vec<usize> = (0..frame_count).map(|i| i * 10).collect();
+        let mut p = AnimationPlayer::new();
+        p.add_clip(Animation::uniform("clip", &indices, FRAME, mode));
+        p.play("clip").unwrap();
+        p
+    }
+
+    /**
+     * Positions after each of steps updates of one frame
+    */
+    fn positions(p: &mut AnimationPlayer, steps: usize) -> Vec<usize> {
+        (0..steps).map(|_| { p.update(FRAME); p.position() }).collect()
+    }
+
+    fn looped() -> AnimationEvent { AnimationEvent::Looped { clip: "clip".to_string() } }
+    fn finished() -> AnimationEvent { AnimationEvent::Finished { clip: "clip".to_string() } }
+    fn frame(name: &str, frame: usize) -> AnimationEvent {
+        AnimationEvent::Frame { clip: "clip".to_string(), name: name.to_string(), frame }
+    }
+
+    #[test]
+    fn once() {
+        let mut p = player(PlayMode::Once, 3);
+        assert_eq!(p.frame_index(), Some(0));
+        assert_eq!(positions(&mut p, 2), vec![1, 2]);
+        assert_eq!(p.update(FRAME), vec![finished()]);
+        assert!(p.is_finished() && !p.is_playing());
+        assert_eq!(p.frame_index(), Some(20));
+        assert!(p.update(FRAME).is_empty());
+    }
+
+    #[test]
+    fn looping() {
+        let mut p = player(PlayMode::Loop, 3);
+        assert_eq!(positions(&mut p, 4), vec![1, 2, 0, 1]);
+        assert!(p.is_playing());
+    }
+
+    #[test]
+    fn ping_pong() {
+        let mut p = player(PlayMode::PingPong, 3);
+        assert_eq!(positions(&mut p, 6), vec![1, 2, 1, 0, 1, 2]);
+
+        let mut single = player(PlayMode::PingPong, 1);
+        assert_eq!(single.update(FRAME), vec![looped()]);
+    }
+
+    #[test]
+    fn reverse() {
+        let mut p = player(PlayMode::Reverse, 3);
+        assert_eq!(p.position(), 2);
+        assert_eq!(positions(&mut p, 4), vec![1, 0, 2, 1]);
+    }
+
+    #[test]
+    fn reverse_once() {
+        let mut p = player(PlayMode::ReverseOnce, 3);
+        assert_eq!(positions(&mut p, 2), vec![1, 0]);
+        assert_eq!(p.update(FRAME), vec![finished()]);
+        assert_eq!(p.position(), 0);
+    }
+
+    #[test]
+    fn ping_pong_reverse() {
+        let mut p = player(PlayMode::PingPongReverse, 3);
+        assert_eq!(p.position(), 2);
+        assert_eq!(positions(&mut p, 3), vec![1, 0, 1]);
+        assert_eq!(p.update(FRAME), vec![looped()]);
+        assert_eq!(p.position(), 2);
+    }
+
+    #[test]
+    fn speed_scales_time() {
+        let mut p = player(PlayMode::Loop, 4);
+        p.set_speed(2.);
+        p.update(FRAME);
+        assert_eq!(p.position(), 2);
+
+        p.set_speed(0.);
+        p.update(10.);
+        assert_eq!(p.position(), 2);
+
+        p.set_speed(-1.);
+        assert_eq!(p.speed(), 0.);
+    }
+
+    #[test]
+    fn large_dt_carries_over() {
+        let mut p = player(PlayMode::Loop, 3);
+        // Two and a third trips around, plus half a frame left over
+        let events = p.update(FRAME * 7. + FRAME * 0.5);
+        assert_eq!(events, vec![looped(), looped()]);
+        assert_eq!(p.position(), 1);
+        p.update(FRAME * 0.5);
+        assert_eq!(p.position(), 2);
+    }
+
+    #[test]
+    fn event_order() {
+        let mut p = AnimationPlayer::new();
+        let mut clip = Animation::uniform("clip", &[0, 1, 2], FRAME, PlayMode::Loop);
+        clip.add_event(0, "start").add_event(2, "end");
+        p.add_clip(clip);
+        p.play("clip").unwrap();
+
+        // First frame's events fire on the first update, even with no time passed
+        assert_eq!(p.update(0.), vec![frame("start", 0)]);
+        assert_eq!(p.update(FRAME * 3.), vec![frame("end", 2), looped(), frame("start", 0)]);
+
+        p.clip_mut("clip").unwrap().mode = PlayMode::Once;
+        assert_eq!(p.update(FRAME * 3.), vec![frame("end", 2), finished()]);
+    }
+
+    #[test]
+    fn play_errors() {
+        let mut p = AnimationPlayer::new();
+        p.add_clip(Animation::new("empty", Vec::new(), PlayMode::Loop));
+        assert!(p.play("missing").is_err());
+        assert!(p.play("empty").is_err());
+        assert_eq!(p.frame_index(), None);
+    }
+
+    #[test]
+    fn shrinking_the_playing_clip() {
+        let mut p = player(PlayMode::Loop, 3);
+        positions(&mut p, 2);
+        p.clip_mut("clip").unwrap().frames.truncate(1);
+        assert_eq!(p.frame_index(), None);
+        p.update(0.);
+        assert_eq!((p.position(), p.frame_index()), (0, Some(0)));
+
+        p.add_clip(Animation::uniform("clip", &[5, 6], FRAME, PlayMode::Loop));
+        assert_eq!(positions(&mut p, 2), vec![1, 0]);
+
+        p.clip_mut("clip").unwrap().frames.clear();
+        assert!(p.update(FRAME).is_empty());
+        assert_eq!(p.frame_index(), None);
+    }
+
+    #[test]
+    fn from_aseprite_tag() {
+        let json = r#"{
+            "frames": [
+                { "filename": "a", "frame": { "x": 0, "y": 0, "w": 8, "h": 8 }, "duration": 100 },
+                { "filename": "b", "frame": { "x": 8, "y": 0, "w": 8, "h": 8 }, "duration": 0 },
+                { "filename": "c", "frame": { "x": 16, "y": 0, "w": 8, "h": 8 }, "duration": 250 }
+            ],
+            "meta": { "frameTags": [
+                { "name": "bounce", "from": 0, "to": 2, "direction": "pingpong_reverse" },
+                { "name": "walk", "from": 1, "to": 2, "direction": "forward" }
+            ] }
+        }"#;
+        let sheet = SpriteSheet::from_json(json, Rc::new(Texture::unloaded(24, 8))).unwrap();
+
+        let bounce = Animation::from_tag(&sheet, "bounce", 0.5).unwrap();
+        assert_eq!(bounce.mode, PlayMode::PingPongReverse);
+        let durations: Vec<f32> = bounce.frames.iter().map(|f| f.duration).collect();
+        assert_eq!(durations, vec![0.1, 0.5, 0.25]);
+
+        let walk = Animation::from_tag(&sheet, "walk", 0.5).unwrap();
+        assert_eq!((walk.mode, walk.frames[0].index), (PlayMode::Loop, 1));
+        assert!(Animation::from_tag(&sheet, "run", 0.5).is_err());
+    }
+}
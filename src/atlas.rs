@@ -0,0 +1,437 @@
+use crate::graphics::{Texture, TextureFormat};
+use crate::sys::{Quad, Rectui};
+use crate::vertex::*;
+use image::RgbaImage;
+use nalgebra_glm as glm;
+use std::collections::HashMap;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct SkylineNode {
+    x: u32,
+    y: u32,
+    w: u32,
+}
+
+/**
+ * Skyline bottom-left rect packer. Keeps the top edge of everything packed so far as a list of
+ * horizontal segments and places each rect where it ends up lowest. Origin is top left. No GL calls,
+ * so it can be used on its own for CPU side packing
+*/
+#[derive(Debug, Clone)]
+pub struct SkylinePacker {
+    width: u32,
+    height: u32,
+    skyline: Vec<SkylineNode>,
+    used_area: u64,
+}
+
+impl SkylinePacker {
+    pub fn new(width: u32, height: u32) -> Self {
+        SkylinePacker { width, height, skyline: vec![SkylineNode { x: 0, y: 0, w: width }], used_area: 0 }
+    }
+
+    /**
+     * Enlarges the packing area, keeping everything packed so far in place
+    */
+    pub fn grow(&mut self, width: u32, height: u32) {
+        if width > self.width {
+            self.skyline.push(SkylineNode { x: self.width, y: 0, w: width - self.width });
+            self.width = width;
+        }
+        self.height = self.height.max(height);
+    }
+
+    pub fn width(&self) -> u32 { self.width }
+    pub fn height(&self) -> u32 { self.height }
+
+    /**
+     * Fraction of the area covered by packed rects, 0 to 1
+    */
+    pub fn occupancy(&self) -> f32 {
+        self.used_area as f32 / (self.width as u64 * self.height as u64).max(1) as f32
+    }
+
+    /**
+     * Finds space for a w x h rect, None when it doesn't fit anywhere
+    */
+    pub fn pack(&mut self, w: u32, h: u32) -> Option<Rectui> {
+        if w == 0 || h == 0 {
+            return Some(Rectui::new(0, 0, w, h));
+        }
+
+        // Lowest top edge wins, ties go to the narrowest segment to reduce wasted space
+        let mut best: Option<(usize, u32, u32)> = None;
+        for i in 0..self.skyline.len() {
+            if let Some(y) = self.fits(i, w, h) {
+                let better = match best {
+                    Some((_, best_y, best_w)) => y < best_y || (y == best_y && self.skyline[i].w < best_w),
+                    None => true
+                };
+                if better {
+                    best = Some((i, y, self.skyline[i].w));
+                }
+            }
+        }
+
+        let (index, y, _) = best?;
+        let rect = Rectui::new(self.skyline[index].x, y, w, h);
+        self.insert_node(index, rect);
+        self.used_area += w as u64 * h as u64;
+        Some(rect)
+    }
+
+    /**
+     * Top of a w wide rect whose left edge sits on node index, if it stays inside the bounds
+    */
+    fn fits(&self, index: usize, w: u32, h: u32) -> Option<u32> {
+        let x = self.skyline[index].x;
+        if x + w > self.width {
+            return None;
+        }
+
+        let (mut remaining, mut y, mut i) = (w as i64, 0, index);
+        while remaining > 0 {
+            let node = self.skyline.get(i)?;
+            y = y.max(node.y);
+            if y + h > self.height {
+                return None;
+            }
+            remaining -= node.w as i64;
+            i += 1;
+        }
+        Some(y)
+    }
+
+    fn insert_node(&mut self, index: usize, rect: Rectui) {
+        self.skyline.insert(index, SkylineNode { x: rect.x, y: rect.y + rect.h, w: rect.w });
+
+        // Trim or remove the nodes now covered by the new one
+        let right = rect.x + rect.w;
+        let i = index + 1;
+        while i < self.skyline.len() {
+            let node = self.skyline[i];
+            if node.x >= right {
+                break;
+            }
+            let node_right = node.x + node.w;
+            if node_right <= right {
+                self.skyline.remove(i);
+            } else {
+                self.skyline[i].x = right;
+                self.skyline[i].w = node_right - right;
+                break;
+            }
+        }
+
+        // Merge neighbours at the same height
+        let mut i = 0;
+        while i + 1 < self.skyline.len() {
+            if self.skyline[i].y == self.skyline[i + 1].y {
+                self.skyline[i].w += self.skyline[i + 1].w;
+                self.skyline.remove(i + 1);
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
+
+/**
+ * Result of TextureAtlasBuilder::pack(), the composed image and where each named image ended up
+*/
+pub struct PackedAtlas {
+    pub image: RgbaImage,
+    pub regions: HashMap<String, Rectui>,
+}
+
+/**
+ * Collects images and packs them into a single texture so they can share draws.
+ * padding is the transparent gap left between images and extrude repeats each image's edge
+ * pixels outward, which stops linear filtering and mipmaps from sampling neighbouring images
+*/
+pub struct TextureAtlasBuilder {
+    images: Vec<(String, RgbaImage)>,
+    padding: u32,
+    extrude: u32,
+    max_size: u32,
+    power_of_two: bool,
+}
+
+impl TextureAtlasBuilder {
+    pub const DEFAULT_MAX_SIZE: u32 = 4096;
+
+    pub fn new() -> Self {
+        TextureAtlasBuilder { images: Vec::new(), padding: 1, extrude: 0, max_size: Self::DEFAULT_MAX_SIZE, power_of_two: true }
+    }
+
+    pub fn padding(mut self, padding: u32) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    pub fn extrude(mut self, extrude: u32) -> Self {
+        self.extrude = extrude;
+        self
+    }
+
+    pub fn max_size(mut self, max_size: u32) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /**
+     * Whether the atlas dimensions are rounded up to powers of two, on by default
+    */
+    pub fn power_of_two(mut self, power_of_two: bool) -> Self {
+        self.power_of_two = power_of_two;
+        self
+    }
+
+    pub fn add_image(&mut self, name: &str, image: RgbaImage) -> &mut Self {
+        self.images.push((name.to_string(), image));
+        self
+    }
+
+    pub fn add_file(&mut self, name: &str, filename: &str) -> Result<&mut Self, String> {
+        let im = image::open(filename)
+            .map_err(|e| format!("Error loading file: {} :: ImageError: {}", filename, e))?;
+        Ok(self.add_image(name, im.to_rgba8()))
+    }
+
+    /**
+     * Packs every image, starting with the smallest size that could hold them all and doubling
+     * the shorter side until everything fits or max_size is reached. Does not touch OpenGL
+    */
+    pub fn pack(&self) -> Result<PackedAtlas, String> {
+        if let Some((name, _)) = self.images.iter()
+            .find(|(n, _)| self.images.iter().filter(|(other, _)| other == n).count() > 1) {
+            return Err(format!("AtlasError: duplicate image name '{}'", name));
+        }
+
+        let border = self.extrude * 2 + self.padding;
+        let cell = |im: &RgbaImage| (im.width() + border, im.height() + border);
+
+        // Tallest first packs noticeably tighter with a skyline
+        let mut order: Vec<usize> = (0..self.images.len()).collect();
+        order.sort_by_key(|i| {
+            let im = &self.images[*i].1;
+            (std::cmp::Reverse(im.height()), std::cmp::Reverse(im.width()))
+        });
+
+        let area: u64 = self.images.iter().map(|(_, im)| { let (w, h) = cell(im); w as u64 * h as u64 }).sum();
+        let widest = self.images.iter().map(|(_, im)| cell(im).0).max().unwrap_or(1);
+        let tallest = self.images.iter().map(|(_, im)| cell(im).1).max().unwrap_or(1);
+
+        let round = |n: u32| if self.power_of_two { n.next_power_of_two() } else { n };
+        let side = (area as f64).sqrt().ceil() as u32;
+        let (mut w, mut h) = (round(side.max(widest)), round(side.max(tallest)));
+
+        loop {
+            if w > self.max_size || h > self.max_size {
+                return Err(format!("AtlasError: {} images don't fit in {}x{}", self.images.len(), self.max_size, self.max_size));
+            }
+            // Padding is only needed between images, so the last row and column can use it
+            let mut packer = SkylinePacker::new(w + self.padding, h + self.padding);
+            let placed: Option<Vec<(usize, Rectui)>> = order.iter()
+                .map(|i| { let (cw, ch) = cell(&self.images[*i].1); packer.pack(cw, ch).map(|r| (*i, r)) })
+                .collect();
+
+            if let Some(placed) = placed {
+                return Ok(self.compose(w, h, &placed));
+            }
+            // Clamp to max_size once so non power of two limits still get tried
+            if w <= h {
+                w = (w * 2).min(self.max_size.max(w + 1));
+            } else {
+                h = (h * 2).min(self.max_size.max(h + 1));
+            }
+        }
+    }
+
+    /**
+     * Packs and uploads the atlas as a single texture
+    */
+    pub fn build(&self) -> Result<TextureAtlas, String> {
+        let packed = self.pack()?;
+        Ok(TextureAtlas::from_packed(packed))
+    }
+
+    fn compose(&self, width: u32, height: u32, placed: &[(usize, Rectui)]) -> PackedAtlas {
+        let mut image = RgbaImage::new(width, height);
+        let mut regions = HashMap::with_capacity(placed.len());
+        let e = self.extrude as i64;
+
+        for (i, cell) in placed.iter() {
+            let (name, im) = &self.images[*i];
+            let (x0, y0) = (cell.x + self.extrude, cell.y + self.extrude);
+            let (iw, ih) = (im.width() as i64, im.height() as i64);
+            regions.insert(name.clone(), Rectui::new(x0, y0, im.width(), im.height()));
+            if iw == 0 || ih == 0 {
+                // No edge pixels to extrude
+                continue;
+            }
+
+            // Extruded border clamps to the nearest edge pixel
+            for y in -e..ih + e {
+                for x in -e..iw + e {
+                    let src = im.get_pixel(x.clamp(0, iw - 1) as u32, y.clamp(0, ih - 1) as u32);
+                    let (dx, dy) = (x0 as i64 + x, y0 as i64 + y);
+                    if dx >= 0 && dy >= 0 && (dx as u32) < width && (dy as u32) < height {
+                        image.put_pixel(dx as u32, dy as u32, *src);
+                    }
+                }
+            }
+        }
+        PackedAtlas { image, regions }
+    }
+}
+
+impl Default for TextureAtlasBuilder {
+    fn default() -> Self { TextureAtlasBuilder::new() }
+}
+
+/**
+ * Single texture holding many images. Regions are in pixels with origin at the top left,
+ * the same space Quad::new() takes texture rects in
+*/
+pub struct TextureAtlas {
+    texture: Texture,
+    regions: HashMap<String, Rectui>,
+}
+
+impl TextureAtlas {
+    pub fn from_packed(packed: PackedAtlas) -> Self {
+        // Flipped to match Texture::from_file(), so regions work with calc_texture_coords()
+        let im = image::imageops::flip_vertical(&packed.image);
+        let (w, h) = (im.width(), im.height());
+        TextureAtlas {
+            texture: Texture::from_memory(im.into_raw(), w, h, TextureFormat::Rgba),
+            regions: packed.regions,
+        }
+    }
+
+    pub fn texture(&self) -> &Texture { &self.texture }
+    pub fn regions(&self) -> &HashMap<String, Rectui> { &self.regions }
+
+    pub fn region(&self, name: &str) -> Option<Rectui> {
+        self.regions.get(name).copied()
+    }
+
+    /**
+     * Quad showing the named image with texture coordinates already normalized to the atlas
+    */
+    pub fn quad(&self, name: &str, pos: glm::Vec2, size: glm::Vec2, rotation_degrees: f32) -> Option<Quad> {
+        let region = self.region(name)?;
+        let mut q = Quad::new(pos, size, rotation_degrees, region);
+        let bounds = glm::vec2(self.texture.size.x as f32, self.texture.size.y as f32);
+        q.verts.iter_mut().normalize_texture_coords(bounds);
+        Some(q)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    fn overlaps(a: &Rectui, b: &Rectui) -> bool {
+        a.x < b.x + b.w && b.x < a.x + a.w && a.y < b.y + b.h && b.y < a.y + a.h
+    }
+
+    fn assert_disjoint(rects: &[Rectui], width: u32, height: u32) {
+        for (i, a) in rects.iter().enumerate() {
+            assert!(a.x + a.w <= width && a.y + a.h <= height, "{:?} is out of bounds", a);
+            for b in rects[i + 1..].iter() {
+                assert!(!overlaps(a, b), "{:?} overlaps {:?}", a, b);
+            }
+        }
+    }
+
+    fn solid(w: u32, h: u32, color: [u8; 4]) -> RgbaImage {
+        RgbaImage::from_pixel(w, h, Rgba(color))
+    }
+
+    #[test]
+    fn skyline_rects_do_not_overlap() {
+        let mut packer = SkylinePacker::new(128, 128);
+        let mut rects = Vec::new();
+        for i in 0..40u32 {
+            if let Some(r) = packer.pack(5 + (i * 7) % 23, 3 + (i * 11) % 19) {
+                rects.push(r);
+            }
+        }
+        assert!(rects.len() > 20);
+        assert_disjoint(&rects, 128, 128);
+        assert!(packer.occupancy() > 0. && packer.occupancy() <= 1.);
+    }
+
+    #[test]
+    fn skyline_full_and_grow() {
+        let mut packer = SkylinePacker::new(32, 32);
+        let mut rects: Vec<Rectui> = (0..4).map(|_| packer.pack(16, 16).unwrap()).collect();
+        assert_eq!(packer.pack(16, 16), None);
+        assert_eq!(packer.pack(33, 1), None);
+
+        packer.grow(64, 32);
+        rects.push(packer.pack(16, 16).unwrap());
+        rects.push(packer.pack(16, 32).unwrap());
+        assert_disjoint(&rects, 64, 32);
+    }
+
+    #[test]
+    fn padding_and_extrude_placement() {
+        let mut builder = TextureAtlasBuilder::new().padding(2).extrude(1).power_of_two(false);
+        builder.add_image("red", solid(4, 4, [255, 0, 0, 255]))
+            .add_image("blue", solid(4, 4, [0, 0, 255, 255]));
+        let packed = builder.pack().unwrap();
+
+        let (red, blue) = (packed.regions["red"], packed.regions["blue"]);
+        assert_eq!((red.w, red.h), (4, 4));
+        // Each region is surrounded by its extruded border plus padding
+        let grown = |r: Rectui| Rectui::new(r.x - 1, r.y - 1, r.w + 2 + 2, r.h + 2 + 2);
+        assert!(!overlaps(&grown(red), &grown(blue)));
+
+        let px = |x: u32, y: u32| *packed.image.get_pixel(x, y);
+        assert_eq!(px(red.x - 1, red.y - 1), Rgba([255, 0, 0, 255]));
+        assert_eq!(px(red.x + red.w, red.y + 2), Rgba([255, 0, 0, 255]));
+        assert_eq!(px(blue.x - 1, blue.y + blue.h), Rgba([0, 0, 255, 255]));
+        assert_eq!(px(blue.x + blue.w + 1, blue.y), Rgba([0, 0, 0, 0]));
+    }
+
+    #[test]
+    fn grows_in_powers_of_two() {
+        let mut builder = TextureAtlasBuilder::new().padding(0);
+        for i in 0..5 {
+            builder.add_image(&i.to_string(), solid(50, 50, [255; 4]));
+        }
+        let packed = builder.pack().unwrap();
+        // 128x128 only holds four, the shorter side doubles
+        assert_eq!(packed.image.dimensions(), (256, 128));
+        let rects: Vec<Rectui> = packed.regions.values().copied().collect();
+        assert_disjoint(&rects, 256, 128);
+    }
+
+    #[test]
+    fn too_big_for_max_size() {
+        let mut builder = TextureAtlasBuilder::new().max_size(64);
+        builder.add_image("big", solid(100, 10, [255; 4]));
+        let err = builder.pack().err().unwrap();
+        assert!(err.starts_with("AtlasError:"), "{}", err);
+    }
+
+    #[test]
+    fn duplicate_names() {
+        let mut builder = TextureAtlasBuilder::new();
+        builder.add_image("a", solid(2, 2, [255; 4])).add_image("a", solid(3, 3, [255; 4]));
+        assert_eq!(builder.pack().err().unwrap(), "AtlasError: duplicate image name 'a'");
+    }
+
+    #[test]
+    fn empty_image_with_extrude() {
+        let mut builder = TextureAtlasBuilder::new().extrude(2);
+        builder.add_image("empty", RgbaImage::new(0, 5)).add_image("dot", solid(1, 1, [255; 4]));
+        let packed = builder.pack().unwrap();
+        assert_eq!(packed.regions["empty"].w, 0);
+        assert_eq!(packed.regions["dot"].w, 1);
+    }
+}
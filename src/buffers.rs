@@ -7,7 +7,7 @@ use std::mem;
 #[derive(Debug, Copy, Clone)]
 pub enum DataType {
     Byte = gl::BYTE as isize,
-    // UByte = gl::UBYTE as isize,
+    UByte = gl::UNSIGNED_BYTE as isize,
     Short = gl::SHORT as isize,
     // UShort = gl::USHORT as isize,
     Int = gl::INT as isize,
@@ -113,24 +113,64 @@ impl ElementBuffer {
     }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RenderBufferFormat {
+    Depth24 = gl::DEPTH_COMPONENT24 as isize,
+    Depth24Stencil8 = gl::DEPTH24_STENCIL8 as isize,
+    Rgb8 = gl::RGB8 as isize,
+    Rgba8 = gl::RGBA8 as isize,
+    Rgba16F = gl::RGBA16F as isize,
+    Rgba32F = gl::RGBA32F as isize,
+}
+
+impl RenderBufferFormat {
+    pub fn is_depth(&self) -> bool {
+        matches!(self, RenderBufferFormat::Depth24 | RenderBufferFormat::Depth24Stencil8)
+    }
+
+    pub fn attachment(&self) -> u32 {
+        match self {
+            RenderBufferFormat::Depth24 => gl::DEPTH_ATTACHMENT,
+            RenderBufferFormat::Depth24Stencil8 => gl::DEPTH_STENCIL_ATTACHMENT,
+            _ => gl::COLOR_ATTACHMENT0
+        }
+    }
+}
+
 pub struct RenderBuffer {
-    id: u32
+    id: u32,
+    pub format: RenderBufferFormat,
+    pub samples: u32,
 }
 
 impl RenderBuffer {
+    /**
+     * Depth24 Stencil8 renderbuffer
+    */
     pub fn new(width: i32, height: i32) -> Self {
-        let id = gl_gen_buffer();
-        let rb = RenderBuffer { id };
+        RenderBuffer::with_format(width, height, RenderBufferFormat::Depth24Stencil8, 0)
+    }
+
+    /**
+     * samples > 0 allocates multisampled storage
+    */
+    pub fn with_format(width: i32, height: i32, format: RenderBufferFormat, samples: u32) -> Self {
+        let id = gl_gen_renderbuffer();
+        let rb = RenderBuffer { id, format, samples };
         rb.apply();
 
         unsafe {
-            opengl().RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH24_STENCIL8, width, height);
+            if samples > 0 {
+                opengl().RenderbufferStorageMultisample(gl::RENDERBUFFER, samples as i32, format as u32, width, height);
+            } else {
+                opengl().RenderbufferStorage(gl::RENDERBUFFER, format as u32, width, height);
+            }
         };
         rb
     }
 
     pub fn apply(&self) {
-        unsafe { opengl().BindBuffer(gl::RENDERBUFFER, self.id) };
+        unsafe { opengl().BindRenderbuffer(gl::RENDERBUFFER, self.id) };
     }
 }
 
@@ -161,10 +201,25 @@ impl FrameBuffer {
         };
     }
 
+    pub fn id(&self) -> u32 { self.id }
+
+    /**
+     * Attaches renderbuffer to the depth/stencil attachment or COLOR_ATTACHMENT0, depending on its format
+    */
     pub fn attach_render_buffer(&self, rb: &RenderBuffer) {
         FrameBuffer::apply(self);
         unsafe {
-            opengl().FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::DEPTH_STENCIL_ATTACHMENT, gl::RENDERBUFFER, rb.id)
+            opengl().FramebufferRenderbuffer(gl::FRAMEBUFFER, rb.format.attachment(), gl::RENDERBUFFER, rb.id)
+        };
+    }
+
+    /**
+     * Attaches color renderbuffer to COLOR_ATTACHMENT0 + attachment_num
+    */
+    pub fn attach_render_buffer_n(&self, rb: &RenderBuffer, attachment_num: u32) {
+        FrameBuffer::apply(self);
+        unsafe {
+            opengl().FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0 + attachment_num, gl::RENDERBUFFER, rb.id)
         };
     }
 
@@ -178,6 +233,71 @@ impl FrameBuffer {
             opengl().FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0 + attachment_num, gl::TEXTURE_2D, texture.id(), 0);
         }
     }
+
+    pub fn attach_depth_texture(&self, texture: &graphics::Texture) {
+        FrameBuffer::apply(self);
+        unsafe {
+            opengl().FramebufferTexture2D(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, gl::TEXTURE_2D, texture.id(), 0);
+        }
+    }
+
+    /**
+     * Disables color reads and writes, needed for depth-only framebuffers to be complete
+    */
+    pub fn disable_color(&self) {
+        FrameBuffer::apply(self);
+        unsafe {
+            opengl().DrawBuffer(gl::NONE);
+            opengl().ReadBuffer(gl::NONE);
+        }
+    }
+
+    /**
+     * Routes fragment shader outputs 0..count to COLOR_ATTACHMENT0..count with glDrawBuffers
+    */
+    pub fn set_draw_buffers(&self, count: u32) {
+        FrameBuffer::apply(self);
+        let attachments: Vec<u32> = (0..count).map(|i| gl::COLOR_ATTACHMENT0 + i).collect();
+        unsafe { opengl().DrawBuffers(count as i32, attachments.as_ptr()) };
+    }
+
+    pub fn check_status(&self) -> Result<(), String> {
+        FrameBuffer::apply(self);
+        let status = unsafe { opengl().CheckFramebufferStatus(gl::FRAMEBUFFER) };
+        if status != gl::FRAMEBUFFER_COMPLETE {
+            return Err(format!("OpenGL :: Framebuffer is not complete. Status: 0x{:X}", status));
+        }
+        Ok(())
+    }
+
+    /**
+     * Copies src into dst with glBlitFramebuffer. Used to resolve multisampled framebuffers, 
+     * mask is any of gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT
+    */
+    pub fn blit(src: &FrameBuffer, dst: &FrameBuffer, width: i32, height: i32, mask: u32) {
+        unsafe {
+            let gl = opengl();
+            gl.BindFramebuffer(gl::READ_FRAMEBUFFER, src.id);
+            gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, dst.id);
+            gl.BlitFramebuffer(0, 0, width, height, 0, 0, width, height, mask, gl::NEAREST);
+            gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+    }
+
+    /**
+     * Blits a single color attachment from src into the same attachment on dst
+    */
+    pub fn blit_attachment(src: &FrameBuffer, dst: &FrameBuffer, attachment_num: u32, width: i32, height: i32) {
+        unsafe {
+            let gl = opengl();
+            gl.BindFramebuffer(gl::READ_FRAMEBUFFER, src.id);
+            gl.ReadBuffer(gl::COLOR_ATTACHMENT0 + attachment_num);
+            gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, dst.id);
+            gl.DrawBuffer(gl::COLOR_ATTACHMENT0 + attachment_num);
+            gl.BlitFramebuffer(0, 0, width, height, 0, 0, width, height, gl::COLOR_BUFFER_BIT, gl::NEAREST);
+            gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+    }
 }
 
 pub struct VertexBuffer {
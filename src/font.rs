@@ -0,0 +1,597 @@
+use crate::graphics::{Shader, Texture};
+use crate::sys::{Quad, Rectui};
+use crate::vertex::*;
+use nalgebra_glm as glm;
+use std::collections::HashMap;
+use std::path::Path;
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum TextAlign {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+/**
+ * Single character from a BMFont file. Offsets and advance are in pixels, with y offset
+ * measured down from the top of the line
+*/
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Glyph {
+    pub id: u32,
+    pub rect: Rectui,
+    pub offset: glm::TVec2<i32>,
+    pub advance: i32,
+    pub page: u32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DistanceFieldType {
+    Sdf,
+    /**
+     * Pseudo SDF, single channel like Sdf
+    */
+    Psdf,
+    Msdf,
+    /**
+     * Msdf with a true SDF in alpha, drawn as Msdf
+    */
+    Mtsdf,
+}
+
+impl DistanceFieldType {
+    /**
+     * Whether the distance is the median of rgb rather than alpha
+    */
+    pub fn is_multichannel(&self) -> bool {
+        *self == DistanceFieldType::Msdf || *self == DistanceFieldType::Mtsdf
+    }
+}
+
+/**
+ * Set on fonts generated as distance fields, e.g. by msdf-bmfont or Hiero.
+ * range is the distance in pixels covered by the field
+*/
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DistanceField {
+    pub field_type: DistanceFieldType,
+    pub range: f32,
+}
+
+/**
+ * Per draw settings for distance field text, see Shader::sdf_text(). Widths and softness are in
+ * distance units, where 0.5 is the full spread of the field (e.g. 0.25 with a 8px spread is a 4px outline
+ * at the size the field was generated at). Effects can't extend past the field's spread, and the shadow
+ * offset (in texture coordinates) should stay inside the padding around each glyph
+*/
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TextEffects {
+    pub outline_color: glm::Vec4,
+    pub outline_width: f32,
+    pub shadow_color: glm::Vec4,
+    pub shadow_offset: glm::Vec2,
+    pub shadow_softness: f32,
+    pub glow_color: glm::Vec4,
+    pub glow_width: f32,
+}
+
+impl TextEffects {
+    pub fn with_outline(mut self, color: glm::Vec4, width: f32) -> Self {
+        self.outline_color = color;
+        self.outline_width = width;
+        self
+    }
+
+    pub fn with_shadow(mut self, color: glm::Vec4, offset: glm::Vec2, softness: f32) -> Self {
+        self.shadow_color = color;
+        self.shadow_offset = offset;
+        self.shadow_softness = softness;
+        self
+    }
+
+    pub fn with_glow(mut self, color: glm::Vec4, width: f32) -> Self {
+        self.glow_color = color;
+        self.glow_width = width;
+        self
+    }
+
+    pub fn apply_to(&self, shader: &Shader, multichannel: bool) {
+        let v4 = |v: &glm::Vec4| (v.x, v.y, v.z, v.w);
+        shader.set_uniform_i("u_msdf", multichannel as i32);
+        shader.set_uniform_4f("u_outline_color", v4(&self.outline_color));
+        shader.set_uniform_f("u_outline_width", self.outline_width);
+        shader.set_uniform_4f("u_shadow_color", v4(&self.shadow_color));
+        shader.set_uniform_2f("u_shadow_offset", (self.shadow_offset.x, self.shadow_offset.y));
+        shader.set_uniform_f("u_shadow_softness", self.shadow_softness);
+        shader.set_uniform_4f("u_glow_color", v4(&self.glow_color));
+        shader.set_uniform_f("u_glow_width", self.glow_width);
+    }
+}
+
+impl Default for TextEffects {
+    fn default() -> Self {
+        let clear = glm::vec4(0., 0., 0., 0.);
+        TextEffects {
+            outline_color: clear, outline_width: 0.,
+            shadow_color: clear, shadow_offset: glm::vec2(0., 0.), shadow_softness: 0.,
+            glow_color: clear, glow_width: 0.,
+        }
+    }
+}
+
+/**
+ * Everything in a .fnt file, without the page textures loaded
+*/
+#[derive(Debug, Clone, Default)]
+pub struct FontDesc {
+    pub face: String,
+    pub size: i32,
+    pub line_height: u32,
+    pub base: u32,
+    pub scale_width: u32,
+    pub scale_height: u32,
+    pub pages: Vec<String>,
+    pub glyphs: HashMap<u32, Glyph>,
+    pub kerning: HashMap<(u32, u32), i32>,
+    pub distance_field: Option<DistanceField>,
+}
+
+/**
+ * AngelCode BMFont bitmap font. Supports the text and binary (version 3) .fnt formats
+*/
+pub struct Font {
+    pub desc: FontDesc,
+    pub pages: Vec<Texture>,
+}
+
+impl Font {
+
+    /**
+     * Loads .fnt file and its page textures, which are looked up relative to the .fnt file
+    */
+    pub fn from_file(filename: &str) -> Result<Self, String> {
+        let data = std::fs::read(filename).map_err(|e| format!("Error loading file: {} :: {}", filename, e))?;
+        let desc = FontDesc::from_bytes(&data).map_err(|e| format!("Error loading file: {} :: {}", filename, e))?;
+
+        let dir = Path::new(filename).parent().unwrap_or_else(|| Path::new(""));
+        let pages = desc.pages.iter()
+            .map(|p| Texture::from_file(&dir.join(p).to_string_lossy()))
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Font::new(desc, pages)
+    }
+
+    pub fn new(desc: FontDesc, pages: Vec<Texture>) -> Result<Self, String> {
+        if pages.len() < desc.pages.len() {
+            return Err(format!("FontError: font '{}' needs {} pages, got {}", desc.face, desc.pages.len(), pages.len()));
+        }
+        Ok(Font { desc, pages })
+    }
+
+    pub fn line_height(&self) -> f32 { self.desc.line_height as f32 }
+
+    pub fn glyph(&self, c: char) -> Option<&Glyph> {
+        self.desc.glyphs.get(&(c as u32))
+    }
+
+    pub fn kerning(&self, first: char, second: char) -> i32 {
+        self.desc.kerning.get(&(first as u32, second as u32)).copied().unwrap_or(0)
+    }
+
+    /**
+     * Width of a single line in pixels, ignoring line breaks
+    */
+    pub fn line_width(&self, line: &str) -> f32 {
+        let mut width = 0;
+        let mut prev: Option<char> = None;
+        for c in line.chars() {
+            if let Some(g) = self.glyph(c).or_else(|| self.glyph('?')) {
+                width += g.advance + prev.map(|p| self.kerning(p, c)).unwrap_or(0);
+            }
+            prev = Some(c);
+        }
+        width as f32
+    }
+
+    /**
+     * Size of the text block in pixels, lines are split on '\n'
+    */
+    pub fn measure(&self, text: &str) -> glm::Vec2 {
+        let lines = text.split('\n');
+        let (mut width, mut count) = (0f32, 0);
+        for line in lines {
+            width = width.max(self.line_width(line));
+            count += 1;
+        }
+        glm::vec2(width, count as f32 * self.line_height())
+    }
+
+    /**
+     * Builds a textured quad per visible glyph. pos is the top of the first line, and
+     * depending on align the left edge, center or right edge of every line. World space is y up, so
+     * lines go down in y. Quads are returned together with the page they sample from
+    */
+    pub fn layout(&self, text: &str, pos: glm::Vec2, align: TextAlign, color: &glm::Vec4) -> Vec<(u32, Quad)> {
+        let mut quads = Vec::with_capacity(text.len());
+
+        for (line_num, line) in text.split('\n').enumerate() {
+            let x = match align {
+                TextAlign::Left => pos.x,
+                TextAlign::Center => pos.x - (self.line_width(line) * 0.5).floor(),
+                TextAlign::Right => pos.x - self.line_width(line),
+            };
+            let mut cursor = glm::vec2(x, pos.y - line_num as f32 * self.line_height());
+            let mut prev: Option<char> = None;
+
+            for c in line.chars() {
+                let g = match self.glyph(c).or_else(|| self.glyph('?')) {
+                    Some(g) => g,
+                    None => continue
+                };
+                cursor.x += prev.map(|p| self.kerning(p, c)).unwrap_or(0) as f32;
+                prev = Some(c);
+
+                if g.rect.w > 0 && g.rect.h > 0 {
+                    let size = glm::vec2(g.rect.w as f32, g.rect.h as f32);
+                    let top_left = cursor + glm::vec2(g.offset.x as f32, -g.offset.y as f32);
+                    let center = top_left + glm::vec2(size.x * 0.5, -size.y * 0.5);
+
+                    let mut q = Quad::new(center, size, 0., g.rect);
+                    let page = &self.pages[g.page as usize];
+                    q.verts.iter_mut().normalize_texture_coords(glm::vec2(page.size.x as f32, page.size.y as f32));
+                    q.verts.iter_mut().set_color(color);
+                    quads.push((g.page, q));
+                }
+                cursor.x += g.advance as f32;
+            }
+        }
+        quads
+    }
+
+    /**
+     * layout() as triangle lists, one per page, ready for Renderer::draw_triangles()
+    */
+    pub fn layout_triangles(&self, text: &str, pos: glm::Vec2, align: TextAlign, color: &glm::Vec4) -> Vec<Vec<Vertex2D>> {
+        let mut pages = vec![Vec::new(); self.pages.len()];
+        for (page, q) in self.layout(text, pos, align, color) {
+            let v = &q.verts;
+            // Quad verts are in triangle strip order
+            pages[page as usize].extend_from_slice(&[v[0], v[1], v[2], v[2], v[1], v[3]]);
+        }
+        pages
+    }
+}
+
+impl FontDesc {
+
+    /**
+     * Parses either format, binary files start with "BMF"
+    */
+    pub fn from_bytes(data: &[u8]) -> Result<Self, String> {
+        if data.starts_with(b"BMF") {
+            FontDesc::from_binary(data)
+        } else {
+            let text = std::str::from_utf8(data).map_err(|e| format!("FontError: {}", e))?;
+            FontDesc::from_text(text)
+        }
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        let mut desc = FontDesc::default();
+
+        for (line_num, line) in text.lines().enumerate() {
+            let mut tokens = tokenize_line(line);
+            if tokens.is_empty() {
+                continue;
+            }
+            let tag = tokens.remove(0).1;
+            let attrs: HashMap<&str, &str> = tokens.into_iter().collect();
+            let int = |key: &str| -> Result<i32, String> {
+                attrs.get(key).unwrap_or(&"0").parse::<i32>()
+                    .map_err(|_| format!("FontError: invalid value for '{}' on line {}", key, line_num + 1))
+            };
+
+            match tag {
+                "info" => {
+                    desc.face = attrs.get("face").unwrap_or(&"").to_string();
+                    desc.size = int("size")?;
+                },
+                "common" => {
+                    desc.line_height = int("lineHeight")? as u32;
+                    desc.base = int("base")? as u32;
+                    desc.scale_width = int("scaleW")? as u32;
+                    desc.scale_height = int("scaleH")? as u32;
+                    desc.pages.resize(int("pages")? as usize, String::new());
+                },
+                "page" => {
+                    let id = int("id")? as usize;
+                    if id >= desc.pages.len() {
+                        desc.pages.resize(id + 1, String::new());
+                    }
+                    desc.pages[id] = attrs.get("file").unwrap_or(&"").to_string();
+                },
+                "char" => {
+                    let g = Glyph {
+                        id: int("id")? as u32,
+                        rect: Rectui::new(int("x")? as u32, int("y")? as u32, int("width")? as u32, int("height")? as u32),
+                        offset: glm::vec2(int("xoffset")?, int("yoffset")?),
+                        advance: int("xadvance")?,
+                        page: int("page")? as u32,
+                    };
+                    desc.glyphs.insert(g.id, g);
+                },
+                "distanceField" => {
+                    let field_type = match attrs.get("fieldType").copied().unwrap_or("sdf") {
+                        "sdf" => DistanceFieldType::Sdf,
+                        "psdf" => DistanceFieldType::Psdf,
+                        "msdf" => DistanceFieldType::Msdf,
+                        "mtsdf" => DistanceFieldType::Mtsdf,
+                        other => return Err(format!("FontError: unknown distance field type '{}' on line {}", other, line_num + 1))
+                    };
+                    desc.distance_field = Some(DistanceField { field_type, range: int("distanceRange")? as f32 });
+                },
+                "kerning" => {
+                    desc.kerning.insert((int("first")? as u32, int("second")? as u32), int("amount")?);
+                },
+                _ => {}
+            }
+        }
+
+        desc.validate()?;
+        Ok(desc)
+    }
+
+    /**
+     * Binary format version 3, see http://www.angelcode.com/products/bmfont/doc/file_format.html
+    */
+    pub fn from_binary(data: &[u8]) -> Result<Self, String> {
+        if data.len() < 4 || &data[0..3] != b"BMF" {
+            return Err("FontError: missing BMF header".into());
+        }
+        if data[3] != 3 {
+            return Err(format!("FontError: unsupported binary version {}", data[3]));
+        }
+
+        let mut desc = FontDesc::default();
+        let mut r = ByteReader { data, pos: 4 };
+
+        while r.pos < data.len() {
+            let block_type = r.u8()?;
+            let block_size = r.u32()? as usize;
+            let block = r.bytes(block_size)?;
+            let mut b = ByteReader { data: block, pos: 0 };
+
+            match block_type {
+                1 => {
+                    desc.size = (b.u16()? as i16).abs() as i32;
+                    // bitField, charSet, stretchH, aa, padding[4], spacing[2], outline
+                    b.bytes(12)?;
+                    desc.face = b.string()?;
+                },
+                2 => {
+                    desc.line_height = b.u16()? as u32;
+                    desc.base = b.u16()? as u32;
+                    desc.scale_width = b.u16()? as u32;
+                    desc.scale_height = b.u16()? as u32;
+                    desc.pages.resize(b.u16()? as usize, String::new());
+                },
+                3 => {
+                    let mut pages = Vec::new();
+                    while b.pos < block.len() {
+                        pages.push(b.string()?);
+                    }
+                    desc.pages = pages;
+                },
+                4 => {
+                    while b.pos < block.len() {
+                        let id = b.u32()?;
+                        let rect = Rectui::new(b.u16()? as u32, b.u16()? as u32, b.u16()? as u32, b.u16()? as u32);
+                        let offset = glm::vec2(b.u16()? as i16 as i32, b.u16()? as i16 as i32);
+                        let advance = b.u16()? as i16 as i32;
+                        let page = b.u8()? as u32;
+                        b.u8()?; // channel
+                        desc.glyphs.insert(id, Glyph { id, rect, offset, advance, page });
+                    }
+                },
+                5 => {
+                    while b.pos < block.len() {
+                        let pair = (b.u32()?, b.u32()?);
+                        desc.kerning.insert(pair, b.u16()? as i16 as i32);
+                    }
+                },
+                _ => return Err(format!("FontError: unknown block type {}", block_type))
+            }
+        }
+
+        desc.validate()?;
+        Ok(desc)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.glyphs.is_empty() {
+            return Err("FontError: font has no characters".into());
+        }
+        match self.glyphs.values().find(|g| g.page as usize >= self.pages.len()) {
+            Some(g) => Err(format!("FontError: character {} uses missing page {}", g.id, g.page)),
+            None => Ok(())
+        }
+    }
+}
+
+/**
+ * Splits a text format line into (key, value) pairs, the first pair is ("", tag).
+ * Values can be quoted and contain spaces
+*/
+fn tokenize_line(line: &str) -> Vec<(&str, &str)> {
+    let mut tokens = Vec::new();
+    let mut rest = line.trim_start();
+
+    let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    if end == 0 {
+        return tokens;
+    }
+    tokens.push(("", &rest[..end]));
+    rest = rest[end..].trim_start();
+
+    while !rest.is_empty() {
+        let eq = match rest.find('=') {
+            Some(eq) => eq,
+            None => break
+        };
+        let key = rest[..eq].trim();
+        rest = &rest[eq + 1..];
+
+        let (value, remaining) = if let Some(quoted) = rest.strip_prefix('"') {
+            match quoted.find('"') {
+                Some(end) => (&quoted[..end], &quoted[end + 1..]),
+                None => (quoted, "")
+            }
+        } else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            (&rest[..end], &rest[end..])
+        };
+        tokens.push((key, value));
+        rest = remaining.trim_start();
+    }
+    tokens
+}
+
+struct ByteReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], String> {
+        if self.pos + count > self.data.len() {
+            return Err("FontError: unexpected end of file".into());
+        }
+        let b = &self.data[self.pos..self.pos + count];
+        self.pos += count;
+        Ok(b)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> { Ok(self.bytes(1)?[0]) }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let rest = &self.data[self.pos..];
+        let len = rest.iter().position(|b| *b == 0).ok_or("FontError: unterminated string")?;
+        let s = String::from_utf8_lossy(&rest[..len]).into_owned();
+        self.pos += len + 1;
+        Ok(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT_FNT: &str = r#"info face="Test Sans" size=16 bold=0
+common lineHeight=20 base=16 scaleW=64 scaleH=64 pages=1
+page id=0 file="test_0.png"
+chars count=2
+char id=65 x=0 y=0 width=8 height=10 xoffset=1 yoffset=2 xadvance=9 page=0 chnl=15
+char id=86 x=8 y=0 width=8 height=10 xoffset=0 yoffset=2 xadvance=8 page=0 chnl=15
+kerning first=65 second=86 amount=-2
+"#;
+
+    fn block(out: &mut Vec<u8>, block_type: u8, data: &[u8]) {
+        out.push(block_type);
+        out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        out.extend_from_slice(data);
+    }
+
+    fn binary_fnt() -> Vec<u8> {
+        let u16s = |v: &[i32]| v.iter().flat_map(|n| (*n as u16).to_le_bytes()).collect::<Vec<u8>>();
+        let mut out = b"BMF\x03".to_vec();
+
+        let mut info = u16s(&[-16]);
+        info.extend_from_slice(&[0; 12]);
+        info.extend_from_slice(b"Test Sans\0");
+        block(&mut out, 1, &info);
+        block(&mut out, 2, &[u16s(&[20, 16, 64, 64, 1]), vec![0; 5]].concat());
+        block(&mut out, 3, b"test_0.png\0");
+
+        let mut chars = Vec::new();
+        for (id, x, xoffset, advance) in [(65u32, 0, 1, 9), (86, 8, 0, 8)] {
+            chars.extend_from_slice(&id.to_le_bytes());
+            chars.extend(u16s(&[x, 0, 8, 10, xoffset, 2, advance]));
+            chars.extend_from_slice(&[0, 15]);
+        }
+        block(&mut out, 4, &chars);
+
+        let mut kerning = [65u32.to_le_bytes(), 86u32.to_le_bytes()].concat();
+        kerning.extend(u16s(&[-2]));
+        block(&mut out, 5, &kerning);
+        out
+    }
+
+    fn check(desc: &FontDesc) {
+        assert_eq!(desc.face, "Test Sans");
+        assert_eq!(desc.size, 16);
+        assert_eq!((desc.line_height, desc.base, desc.scale_width, desc.scale_height), (20, 16, 64, 64));
+        assert_eq!(desc.pages, vec!["test_0.png".to_string()]);
+        assert_eq!(desc.glyphs[&65], Glyph { id: 65, rect: Rectui::new(0, 0, 8, 10), offset: glm::vec2(1, 2), advance: 9, page: 0 });
+        assert_eq!(desc.glyphs[&86].rect, Rectui::new(8, 0, 8, 10));
+        assert_eq!(desc.kerning[&(65, 86)], -2);
+    }
+
+    #[test]
+    fn parses_text_format() {
+        check(&FontDesc::from_bytes(TEXT_FNT.as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn parses_binary_format() {
+        check(&FontDesc::from_bytes(&binary_fnt()).unwrap());
+
+        let data = binary_fnt();
+        assert!(FontDesc::from_binary(&data[..data.len() - 3]).is_err());
+        let mut version = data.clone();
+        version[3] = 2;
+        assert!(FontDesc::from_binary(&version).is_err());
+    }
+
+    #[test]
+    fn parses_distance_field() {
+        let text = format!("{}distanceField fieldType=msdf distanceRange=4\n", TEXT_FNT);
+        let desc = FontDesc::from_text(&text).unwrap();
+        assert_eq!(desc.distance_field, Some(DistanceField { field_type: DistanceFieldType::Msdf, range: 4. }));
+        assert!(FontDesc::from_text(&format!("{}distanceField fieldType=nope\n", TEXT_FNT)).is_err());
+    }
+
+    #[test]
+    fn rejects_missing_pages_and_empty_fonts() {
+        assert!(FontDesc::from_text("info face=x size=1\n").is_err());
+        assert!(FontDesc::from_text(&TEXT_FNT.replace("page=0 chnl", "page=3 chnl")).is_err());
+        assert!(FontDesc::from_text(&TEXT_FNT.replace("xadvance=9", "xadvance=x")).is_err());
+    }
+
+    #[test]
+    fn tokenizes_quoted_values() {
+        let tokens = tokenize_line(r#"page id=0 file="my font.png" x=1"#);
+        assert_eq!(tokens, vec![("", "page"), ("id", "0"), ("file", "my font.png"), ("x", "1")]);
+        assert_eq!(tokenize_line(r#"info face="open"#), vec![("", "info"), ("face", "open")]);
+        assert!(tokenize_line("   ").is_empty());
+    }
+
+    #[test]
+    fn measures_with_kerning() {
+        let desc = FontDesc::from_text(TEXT_FNT).unwrap();
+        let font = Font::new(desc, vec![Texture::unloaded(64, 64)]).unwrap();
+        assert_eq!(font.line_width("AV"), 9. - 2. + 8.);
+        assert_eq!(font.measure("AV\nA").y, 40.);
+        assert!(Font::new(FontDesc::from_text(TEXT_FNT).unwrap(), vec![]).is_err());
+    }
+}
@@ -1,11 +1,13 @@
 use crate::opengl::*;
 use crate::buffers::*;
 use crate::sys::*;
+use crate::state::RenderState;
 use std::ops::*;
 use num::Num;
 use nalgebra_glm as glm;
 
 use std::collections::HashMap;
+use std::rc::Rc;
 
 #[allow(unused_imports)]
 use image::{open, DynamicImage};
@@ -22,6 +24,58 @@ pub enum TextureFormat {
     Rgba = gl::RGBA as isize,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TextureFilter {
+    Nearest = gl::NEAREST as isize,
+    Linear = gl::LINEAR as isize,
+}
+
+/**
+ * Storage format for textures used as render targets
+*/
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ColorFormat {
+    Rgb8,
+    Rgba8,
+    Rgba16F,
+    Rgba32F,
+}
+
+impl ColorFormat {
+    pub fn internal_format(&self) -> u32 {
+        match self {
+            ColorFormat::Rgb8 => gl::RGB8,
+            ColorFormat::Rgba8 => gl::RGBA8,
+            ColorFormat::Rgba16F => gl::RGBA16F,
+            ColorFormat::Rgba32F => gl::RGBA32F,
+        }
+    }
+
+    pub fn pixel_format(&self) -> u32 {
+        match self {
+            ColorFormat::Rgb8 => gl::RGB,
+            _ => gl::RGBA
+        }
+    }
+
+    pub fn data_type(&self) -> u32 {
+        match self {
+            ColorFormat::Rgb8 | ColorFormat::Rgba8 => gl::UNSIGNED_BYTE,
+            ColorFormat::Rgba16F => gl::HALF_FLOAT,
+            ColorFormat::Rgba32F => gl::FLOAT,
+        }
+    }
+
+    pub fn render_buffer_format(&self) -> RenderBufferFormat {
+        match self {
+            ColorFormat::Rgb8 => RenderBufferFormat::Rgb8,
+            ColorFormat::Rgba8 => RenderBufferFormat::Rgba8,
+            ColorFormat::Rgba16F => RenderBufferFormat::Rgba16F,
+            ColorFormat::Rgba32F => RenderBufferFormat::Rgba32F,
+        }
+    }
+}
+
 pub struct Texture {
     id: u32,
     unit: u32,
@@ -36,17 +90,19 @@ impl Texture {
             Ok(d) => d,
             Err(e) => return Err(format!("Error loading file: {} :: ImageError: {}", filename, e))
         };
-        let im = flip_vertical(&im);
-        let layout = im.sample_layout();
-
-        let format = match layout.channels {
-            3 => TextureFormat::Rgb,
-            4 => TextureFormat::Rgba,
-            _ => TextureFormat::Alpha
+        // Luma and luma alpha formats don't exist in the core profile, so anything that isn't rgb goes up as rgba
+        let (w, h, data, format) = match im {
+            DynamicImage::ImageRgb8(rgb) => {
+                let rgb = flip_vertical(&rgb);
+                (rgb.width(), rgb.height(), rgb.into_raw(), TextureFormat::Rgb)
+            },
+            other => {
+                let rgba = flip_vertical(&other.to_rgba8());
+                (rgba.width(), rgba.height(), rgba.into_raw(), TextureFormat::Rgba)
+            }
         };
-        let (w, h) = (im.width(), im.height());
 
-        let t = Texture::from_memory(im.into_raw(), w, h, format);
+        let t = Texture::from_memory(data, w, h, format);
         Ok(t)
     }
 
@@ -63,13 +119,52 @@ impl Texture {
         }
         
         
+        // Rows of rgb and alpha data are not 4 byte aligned
+        Texture::set_alignment(1);
         unsafe {
             gl.TexImage2D(gl::TEXTURE_2D, 0, 
                 gl::RGBA as i32, w as i32, h as i32,
-                0, gl::RGBA as u32, gl::UNSIGNED_BYTE, data.as_ptr() as *const _
+                0, format as u32, gl::UNSIGNED_BYTE, data.as_ptr() as *const _
             );
             gl.GenerateMipmap(gl::TEXTURE_2D);
         };
+        Texture::set_alignment(4);
+
+        Texture { 
+            id: tid, 
+            unit: gl::TEXTURE0, size: glm::vec2(w,h)
+        }
+    }
+
+    /**
+     * Allocates uninitialized texture storage, used for render targets. 
+     * Clamped to edge with linear filtering and no mipmaps
+    */
+    pub fn new_empty(w: u32, h: u32, format: ColorFormat) -> Texture {
+        Texture::new_storage(w, h, format.internal_format(), format.pixel_format(), format.data_type())
+    }
+
+    /**
+     * Allocates 24 bit depth texture, used for sampling depth-only render targets
+    */
+    pub fn new_depth(w: u32, h: u32) -> Texture {
+        Texture::new_storage(w, h, gl::DEPTH_COMPONENT24, gl::DEPTH_COMPONENT, gl::FLOAT)
+    }
+
+    fn new_storage(w: u32, h: u32, internal_format: u32, format: u32, dtype: u32) -> Texture {
+        let gl = opengl();
+        let tid = gl_gen_texture();
+        unsafe {
+            gl.BindTexture(gl::TEXTURE_2D, tid);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+            gl.TexImage2D(gl::TEXTURE_2D, 0, 
+                internal_format as i32, w as i32, h as i32,
+                0, format, dtype, std::ptr::null()
+            );
+        }
 
         Texture { 
             id: tid, 
@@ -82,6 +177,18 @@ impl Texture {
         Texture::from_memory(texture_data.into(), 1, 1, TextureFormat::Rgba)
     }
 
+    /**
+     * Nearest keeps pixel art crisp when scaled up
+    */
+    pub fn set_filter(&self, min: TextureFilter, mag: TextureFilter) {
+        unsafe {
+            let gl = opengl();
+            gl.BindTexture(gl::TEXTURE_2D, self.id);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, min as i32);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, mag as i32);
+        }
+    }
+
     pub fn set_alignment(alignment: i32) {
         unsafe { opengl().PixelStorei(gl::UNPACK_ALIGNMENT, alignment) }
     } 
@@ -106,11 +213,22 @@ impl Texture {
             opengl().BindTexture(gl::TEXTURE_2D, self.id);
         }
     }
+
+    /**
+     * Binds texture to the given texture unit (0, 1, 2...) without changing the unit
+     * stored on the texture
+    */
+    pub fn apply_to_unit(&self, unit_num: u32) {
+        unsafe { 
+            opengl().ActiveTexture(gl::TEXTURE0 + unit_num);
+            opengl().BindTexture(gl::TEXTURE_2D, self.id);
+        }
+    }
 }
 
 
 // TODO :: Consolidate these with #ifdefs
-const FRAG_TEMPLATE_VARS: &'static [u8] = b"
+const FRAG_TEMPLATE_VARS: &[u8] = b"
 #version 330
 out vec4 FragColor;
 in vec2 TexCoord;
@@ -120,14 +238,33 @@ in vec3 FragPos;
 uniform sampler2D u_texture;
 \0";
 
-const FRAG_TEMPLATE_MAIN: &'static [u8] = b"
+const FRAG_TEMPLATE_MAIN: &[u8] = b"
 void main()
 {
     FragColor = effect(Color, u_texture, TexCoord, FragPos);
 }
 \0";
 
-const VERT_TEMPLATE_DECLS: &'static [u8] = b"#version 330
+/**
+ * Multiple render target variant of the fragment template. Outputs are declared 
+ * by Shader::from_frag_template_mrt() as FragData[n] and written to directly by effect()
+*/
+const FRAG_TEMPLATE_VARS_MRT: &[u8] = b"
+in vec2 TexCoord;
+in vec4 Color;
+in vec3 FragPos;
+
+uniform sampler2D u_texture;
+\0";
+
+const FRAG_TEMPLATE_MAIN_MRT: &[u8] = b"
+void main()
+{
+    effect(Color, u_texture, TexCoord, FragPos);
+}
+\0";
+
+const VERT_TEMPLATE_DECLS: &[u8] = b"#version 330
     layout(location = 0) in vec3 l_pos;
     layout(location = 1) in vec2 l_texCoords;
     layout(location = 2) in vec4 l_color;
@@ -142,7 +279,7 @@ const VERT_TEMPLATE_DECLS: &'static [u8] = b"#version 330
 \0";
 
 
-const VERT_TEMPLATE_MAIN: &'static [u8] = b"
+const VERT_TEMPLATE_MAIN: &[u8] = b"
 void main()
 {
     TexCoord = l_texCoords;
@@ -154,7 +291,7 @@ void main()
 }
 \0";
 
-const VERT_TEMPLATE_DECLS_INSTANCED: &'static [u8] = b"#version 330
+const VERT_TEMPLATE_DECLS_INSTANCED: &[u8] = b"#version 330
     layout(location = 0) in vec3 l_pos;
     layout(location = 1) in vec2 l_texCoords;
     layout(location = 2) in vec4 l_color;
@@ -167,7 +304,7 @@ const VERT_TEMPLATE_DECLS_INSTANCED: &'static [u8] = b"#version 330
     mat4 matrixMVP = l_matrixMVP;
 \0";
 
-const DEFAULT_VERT: &'static [u8] = b"#version 330
+const DEFAULT_VERT: &[u8] = b"#version 330
     layout(location = 0) in vec3 l_pos;
     layout(location = 1) in vec2 l_texCoords;
     layout(location = 2) in vec4 l_color;
@@ -190,7 +327,27 @@ const DEFAULT_VERT: &'static [u8] = b"#version 330
     }
 \0";
 
-const DEFAULT_INSTANCED_VERT: &'static [u8] = b"#version 330
+/**
+ * Full screen pass vertex shader. Maps the unit quad straight onto the viewport, so no
+ * matrices are involved and the default shader's are left alone
+*/
+const BLIT_VERT: &[u8] = b"#version 330
+    layout(location = 0) in vec3 l_pos;
+    layout(location = 1) in vec2 l_texCoords;
+    layout(location = 2) in vec4 l_color;
+
+    out vec2 TexCoord;
+    out vec4 Color;
+
+    void main()
+    {
+        TexCoord = l_texCoords;
+        Color = l_color;
+        gl_Position = vec4(l_pos.xy * 2.0, 0.0, 1.0);
+    }
+\0";
+
+const DEFAULT_INSTANCED_VERT: &[u8] = b"#version 330
 layout(location = 0) in vec3 l_pos;
 layout(location = 1) in vec2 l_texCoords;
 layout(location = 2) in vec4 l_color;
@@ -212,7 +369,7 @@ void main()
 }
 \0";
 
-const DEFAULT_FRAG: &'static [u8] = b"#version 330
+const DEFAULT_FRAG: &[u8] = b"#version 330
 out vec4 FragColor;
 
 in vec4 Color;
@@ -225,6 +382,70 @@ void main()
 }
 \0";
 
+/**
+ * Layers are composited back to front: shadow, glow, outline, then the glyph itself.
+ * Widths and softness are in distance units where 0.5 covers the field's full spread
+*/
+const SDF_TEXT_EFFECT: &[u8] = b"
+uniform int u_msdf;
+uniform vec4 u_outline_color;
+uniform float u_outline_width;
+uniform vec4 u_shadow_color;
+uniform vec2 u_shadow_offset;
+uniform float u_shadow_softness;
+uniform vec4 u_glow_color;
+uniform float u_glow_width;
+
+float median(float r, float g, float b)
+{
+    return max(min(r, g), min(max(r, g), b));
+}
+
+float distance_at(sampler2D tex, vec2 uv)
+{
+    vec4 s = texture(tex, uv);
+    return u_msdf == 1 ? median(s.r, s.g, s.b) : s.a;
+}
+
+vec4 over(vec4 top, vec4 bottom)
+{
+    float a = top.a + bottom.a * (1.0 - top.a);
+    vec3 rgb = (top.rgb * top.a + bottom.rgb * bottom.a * (1.0 - top.a)) / max(a, 0.0001);
+    return vec4(rgb, a);
+}
+
+vec4 effect(vec4 color, sampler2D tex, vec2 uv, vec3 pos)
+{
+    float d = distance_at(tex, uv);
+    float aa = max(fwidth(d) * 0.5, 0.0001);
+
+    float edge = 0.5 - u_outline_width;
+    float fill = smoothstep(0.5 - aa, 0.5 + aa, d);
+    float outline = u_outline_width > 0.0 ? smoothstep(edge - aa, edge + aa, d) : 0.0;
+    float glow = u_glow_width > 0.0 ? smoothstep(edge - u_glow_width, edge, d) : 0.0;
+
+    float sd = distance_at(tex, uv - u_shadow_offset);
+    float soft = u_shadow_softness + aa;
+    float shadow = u_shadow_color.a > 0.0 ? smoothstep(edge - soft, edge + soft, sd) : 0.0;
+
+    vec4 result = vec4(u_shadow_color.rgb, u_shadow_color.a * shadow);
+    result = over(vec4(u_glow_color.rgb, u_glow_color.a * glow), result);
+    result = over(vec4(u_outline_color.rgb, u_outline_color.a * outline), result);
+    result = over(vec4(color.rgb, color.a * fill), result);
+    return result;
+}
+\0";
+
+#[derive(Debug, Copy, Clone)]
+pub enum UniformValue {
+    Int(i32),
+    Float(f32),
+    Vec2(glm::Vec2),
+    Vec3(glm::Vec3),
+    Vec4(glm::Vec4),
+    Mat4(glm::Mat4),
+}
+
 #[derive(Debug, Copy, Clone)]
 pub enum ShaderType {
     Vertex = gl::VERTEX_SHADER as isize,
@@ -277,6 +498,18 @@ impl Shader {
         Ok(s)
     }
     
+    /**
+     * Fragment template writing to several color attachments at once. effect() has the signature
+     * void effect(vec4 color, sampler2D tex, vec2 uv, vec3 pos) and writes each output to FragData[0..output_count]
+    */
+    pub fn from_frag_template_mrt(effect: &[u8], output_count: u32) -> Result<Self, String> {
+        let header = format!("#version 330\nlayout(location = 0) out vec4 FragData[{}];\n", output_count);
+        let vars = Shader::concat_shader_sources(header.as_bytes(), FRAG_TEMPLATE_VARS_MRT, b"");
+        let frag_full = Shader::concat_shader_sources(vars.as_slice(), effect, FRAG_TEMPLATE_MAIN_MRT);
+        let s = Shader::from_memory(DEFAULT_VERT, frag_full.as_slice())?;
+        Ok(s)
+    }
+
     pub fn from_template_instanced<'a, T>(position: T, effect:T) -> Result<Self, String> where T: Into<Option<Vec<u8>>> {
         let (position, effect) = (position.into(), effect.into());
         assert!(position.is_some() || effect.is_some(), " Both of the arguments for function from_template_instanced() are None. Please pass at least 1 value with Some");
@@ -326,6 +559,25 @@ impl Shader {
         self.apply();
         gl_set_uniform_matrix_xpose(self.uniform_locations[name], glm::value_ptr(mat), transpose)
     }
+
+    pub fn set_uniform(&self, name: &str, value: &UniformValue) {
+        match *value {
+            UniformValue::Int(n) => self.set_uniform_i(name, n),
+            UniformValue::Float(n) => self.set_uniform_f(name, n),
+            UniformValue::Vec2(v) => self.set_uniform_2f(name, (v.x, v.y)),
+            UniformValue::Vec3(v) => self.set_uniform_3f(name, (v.x, v.y, v.z)),
+            UniformValue::Vec4(v) => self.set_uniform_4f(name, (v.x, v.y, v.z, v.w)),
+            UniformValue::Mat4(ref m) => self.set_uniform_matrix(name, m),
+        }
+    }
+
+    /**
+     * Returns true if shader program has an active uniform with given name. 
+     * Uniforms that are declared but never used get optimized out by the driver and will not show up here
+    */
+    pub fn has_uniform(&self, name: &str) -> bool {
+        self.uniform_locations.contains_key(name)
+    }
     
     pub fn apply(&self) {
         unsafe { opengl().UseProgram(self.id) }
@@ -335,6 +587,22 @@ impl Shader {
         Self::from_memory(DEFAULT_INSTANCED_VERT, DEFAULT_FRAG).unwrap()
     }
 
+    /**
+     * Copies a texture onto the whole viewport, see Renderer::draw_fullscreen_texture()
+    */
+    pub fn blit() -> Self {
+        Self::from_memory(BLIT_VERT, DEFAULT_FRAG).unwrap()
+    }
+
+    /**
+     * Distance field text shader. Reads the distance from alpha (SDF) or the median of rgb (MSDF, u_msdf = 1),
+     * with 0.5 on the glyph edge. Antialiasing uses screen space derivatives so text stays crisp at any scale.
+     * Outline, shadow and glow are controlled through uniforms, see font::TextEffects
+    */
+    pub fn sdf_text() -> Self {
+        Self::from_frag_template(SDF_TEXT_EFFECT).unwrap()
+    }
+
     fn new(id: u32, uniform_locations: HashMap<String, i32>) -> Self {
         Shader { id, uniform_locations }
     }
@@ -343,9 +611,12 @@ impl Shader {
         let su = String::from_utf8;
         let (a, b, c) = (a.into(), b.into(), c.into());
         let (a, b, c) = (su(a).unwrap(), su(b).unwrap(), su(c).unwrap());
-        let mut result = String::from(a);
-        result.push_str(&b);
-        result.push_str(&c); 
+        // Sources are null terminated for OpenGL, strip those so the driver doesnt stop reading at the first part
+        let strip = |s: &str| String::from(s.trim_end_matches('\0'));
+        let mut result = strip(&a);
+        result.push_str(&strip(&b));
+        result.push_str(&strip(&c)); 
+        result.push('\0');
         result.as_bytes().to_vec()
     }
 }
@@ -356,12 +627,113 @@ impl Default for Shader {
     }
 }
 
+pub struct TextureBinding {
+    pub sampler: String,
+    pub unit: u32,
+    pub texture: Rc<Texture>
+}
+
+/**
+ * Pairs a shader with the uniform values, textures and render state it should be drawn with.
+ * If no shader is set, the renderer's default shader is used, and if no state is set
+ * the renderer's current state is used. u_projection, u_view and u_model
+ * are set by the renderer on draw when the shader declares them.
+*/
+pub struct Material {
+    pub shader: Option<Rc<Shader>>,
+    pub uniforms: HashMap<String, UniformValue>,
+    pub textures: Vec<TextureBinding>,
+    pub state: Option<RenderState>,
+}
+
+impl Material {
+    pub fn new<T>(shader: T) -> Self where T: Into<Option<Rc<Shader>>> {
+        Material {
+            shader: shader.into(),
+            uniforms: HashMap::new(),
+            textures: Vec::new(),
+            state: None,
+        }
+    }
+
+    /**
+     * Material using the default shader with texture bound to u_texture
+    */
+    pub fn from_texture(texture: Rc<Texture>) -> Self {
+        let mut m = Material::new(None);
+        m.set_texture("u_texture", 0, texture);
+        m
+    }
+
+    pub fn set_uniform(&mut self, name: &str, value: UniformValue) -> &mut Self {
+        self.uniforms.insert(name.into(), value);
+        self
+    }
+
+    /**
+     * Binds texture to sampler uniform on given texture unit (0, 1, 2...), replacing 
+     * any texture already bound to that sampler
+    */
+    pub fn set_texture(&mut self, sampler: &str, unit: u32, texture: Rc<Texture>) -> &mut Self {
+        self.textures.retain(|b| b.sampler != sampler);
+        self.textures.push(TextureBinding { sampler: sampler.into(), unit, texture });
+        self
+    }
+
+    /**
+     * Binds texture to sampler uniform on the lowest texture unit not already used
+     * by this material. Returns the unit that was assigned
+    */
+    pub fn add_texture(&mut self, sampler: &str, texture: Rc<Texture>) -> u32 {
+        self.textures.retain(|b| b.sampler != sampler);
+        let unit = (0..).find(|u| !self.textures.iter().any(|b| b.unit == *u)).unwrap();
+        self.textures.push(TextureBinding { sampler: sampler.into(), unit, texture });
+        unit
+    }
+
+    /**
+     * Sets uniforms and binds textures on given shader. Uniforms the shader does not 
+     * declare are skipped.
+    */
+    pub fn apply_to(&self, shader: &Shader) {
+        shader.apply();
+
+        for (name, value) in self.uniforms.iter() {
+            if shader.has_uniform(name) {
+                shader.set_uniform(name, value);
+            }
+        }
+
+        for binding in self.textures.iter() {
+            binding.texture.apply_to_unit(binding.unit);
+            if shader.has_uniform(&binding.sampler) {
+                shader.set_uniform_i(&binding.sampler, binding.unit as i32);
+            }
+        }
+    }
+
+    pub fn set_state(&mut self, state: RenderState) -> &mut Self {
+        self.state = Some(state);
+        self
+    }
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material::new(None)
+    }
+}
+
 pub struct Mesh {
     pub transform: Transform,
     pub buffer: VertexBuffer,
     pub indices: Option<ElementBuffer>,
     pub texture: Option<Texture>,
     pub shader: Option<Shader>,
+    /**
+     * When set, takes precedence over texture and shader
+    */
+    pub material: Option<Material>,
 }
 
 impl Mesh {
@@ -372,6 +744,7 @@ impl Mesh {
             indices: None,
             texture: None,
             shader: None,
+            material: None,
         }
     }
 
@@ -382,7 +755,7 @@ impl Mesh {
             indices: Some(ElementBuffer::new_quad(6)),
             texture: None,
             shader: None,
-
+            material: None,
         }
     }
 
@@ -397,31 +770,203 @@ impl Mesh {
     pub fn shader(&self) -> &Shader {
         self.shader.as_ref().expect("No Shader for Mesh")
     }
+
+    pub fn material(&self) -> &Material {
+        self.material.as_ref().expect("No Material for Mesh")
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DepthStencil {
+    None,
+    Depth,
+    DepthStencil,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderTextureDesc {
+    pub width: u32,
+    pub height: u32,
+    /**
+     * One texture is created per format, attached to COLOR_ATTACHMENT0..n in order. 
+     * Empty creates a depth-only target, with the depth buffer stored in a sampleable texture
+    */
+    pub colors: Vec<ColorFormat>,
+    pub depth_stencil: DepthStencil,
+    /**
+     * MSAA sample count, 0 for no multisampling. Multisampled targets are drawn into renderbuffers 
+     * and need resolve() before their texture can be sampled
+    */
+    pub samples: u32,
+}
+
+impl RenderTextureDesc {
+    pub fn new(width: u32, height: u32) -> Self {
+        RenderTextureDesc {
+            width, height,
+            colors: vec![ColorFormat::Rgba8],
+            depth_stencil: DepthStencil::DepthStencil,
+            samples: 0
+        }
+    }
+}
+
+struct MultisampleTarget {
+    frame_buffer: FrameBuffer,
+    #[allow(dead_code)] // Kept alive for as long as frame_buffer uses them
+    render_buffers: Vec<RenderBuffer>,
 }
 
 pub struct RenderTexture {
     pub frame_buffer: FrameBuffer,
-    pub render_buffer: RenderBuffer,
-    pub texture: Texture
+    pub render_buffer: Option<RenderBuffer>,
+    /**
+     * Color texture at attachment 0, or the depth texture for depth-only targets
+    */
+    pub texture: Texture,
+    /**
+     * Color textures at attachments 1..n for multiple render targets
+    */
+    pub extra_textures: Vec<Texture>,
+    desc: RenderTextureDesc,
+    multisample: Option<MultisampleTarget>,
 }
 
 impl RenderTexture {
     pub fn new(width: u32, height: u32) -> Result<Self, String> {
-        let rt = RenderTexture {
-            frame_buffer: FrameBuffer::new(),
-            render_buffer: RenderBuffer::new(width as i32, height as i32),
-            texture: Texture::from_memory(vec![], width, height, TextureFormat::Rgb)
+        RenderTexture::with_desc(RenderTextureDesc { colors: vec![ColorFormat::Rgb8], ..RenderTextureDesc::new(width, height) })
+    }
+
+    /**
+     * Render target with one color texture per format, e.g. albedo + normal + emissive
+    */
+    pub fn new_mrt(width: u32, height: u32, colors: &[ColorFormat]) -> Result<Self, String> {
+        RenderTexture::with_desc(RenderTextureDesc { colors: colors.to_vec(), ..RenderTextureDesc::new(width, height) })
+    }
+
+    pub fn with_desc(desc: RenderTextureDesc) -> Result<Self, String> {
+        let (w, h) = (desc.width as i32, desc.height as i32);
+        let depth_format = match desc.depth_stencil {
+            DepthStencil::None => None,
+            DepthStencil::Depth => Some(RenderBufferFormat::Depth24),
+            DepthStencil::DepthStencil => Some(RenderBufferFormat::Depth24Stencil8),
         };
-        rt.frame_buffer.attach_texture(&rt.texture);
-        rt.frame_buffer.attach_render_buffer(&rt.render_buffer);
+        let color_count = desc.colors.len() as u32;
+
+        let frame_buffer = FrameBuffer::new();
+        let mut textures: Vec<Texture> = desc.colors.iter()
+            .map(|f| Texture::new_empty(desc.width, desc.height, *f))
+            .collect();
+        for (i, t) in textures.iter().enumerate() {
+            frame_buffer.attach_texture_n(t, i as u32);
+        }
 
-        unsafe {
-            if opengl().CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
-                return Err("OpenGL :: Could not create RenderTexture. Framebuffer is not complete".into());
+        let mut render_buffer = None;
+        if color_count > 0 {
+            frame_buffer.set_draw_buffers(color_count);
+            // Multisampled targets only need depth on the multisampled framebuffer
+            if let Some(f) = depth_format.filter(|_| desc.samples == 0) {
+                let rb = RenderBuffer::with_format(w, h, f, 0);
+                frame_buffer.attach_render_buffer(&rb);
+                render_buffer = Some(rb);
             }
+        } else {
+            let texture = Texture::new_depth(desc.width, desc.height);
+            frame_buffer.attach_depth_texture(&texture);
+            frame_buffer.disable_color();
+            textures.push(texture);
         }
+
+        let status = frame_buffer.check_status();
         FrameBuffer::unbind();
-        Ok(rt)
+        status.map_err(|e| format!("Could not create RenderTexture. {}", e))?;
+
+        let multisample = if color_count > 0 && desc.samples > 0 {
+            let ms_fb = FrameBuffer::new();
+            let mut render_buffers = Vec::new();
+            for (i, f) in desc.colors.iter().enumerate() {
+                let rb = RenderBuffer::with_format(w, h, f.render_buffer_format(), desc.samples);
+                ms_fb.attach_render_buffer_n(&rb, i as u32);
+                render_buffers.push(rb);
+            }
+            if let Some(f) = depth_format {
+                let rb = RenderBuffer::with_format(w, h, f, desc.samples);
+                ms_fb.attach_render_buffer(&rb);
+                render_buffers.push(rb);
+            }
+            ms_fb.set_draw_buffers(color_count);
+
+            let status = ms_fb.check_status();
+            FrameBuffer::unbind();
+            status.map_err(|e| format!("Could not create multisampled RenderTexture. {}", e))?;
+
+            Some(MultisampleTarget { frame_buffer: ms_fb, render_buffers })
+        } else {
+            None
+        };
+
+        let extra_textures = textures.split_off(1);
+        let texture = textures.pop().unwrap();
+        Ok(RenderTexture { frame_buffer, render_buffer, texture, extra_textures, desc, multisample })
+    }
+
+    /**
+     * Same as the texture field
+    */
+    pub fn texture(&self) -> &Texture { &self.texture }
+
+    /**
+     * Color texture at the given attachment, panics past the last one
+    */
+    pub fn texture_n(&self, attachment_num: usize) -> &Texture {
+        match attachment_num {
+            0 => &self.texture,
+            n => &self.extra_textures[n - 1]
+        }
+    }
+
+    pub fn desc(&self) -> &RenderTextureDesc { &self.desc }
+    pub fn width(&self) -> u32 { self.desc.width }
+    pub fn height(&self) -> u32 { self.desc.height }
+    pub fn is_multisampled(&self) -> bool { self.multisample.is_some() }
+
+    /**
+     * Framebuffer draws should go to. For multisampled targets this is the multisampled framebuffer
+    */
+    pub fn draw_frame_buffer(&self) -> &FrameBuffer {
+        match self.multisample.as_ref() {
+            Some(ms) => &ms.frame_buffer,
+            None => &self.frame_buffer
+        }
+    }
+
+    /**
+     * Blits multisampled color attachments into their textures. Does nothing for targets without MSAA. 
+     * Leaves framebuffer 0 bound
+    */
+    pub fn resolve(&self) {
+        if let Some(ms) = self.multisample.as_ref() {
+            let (w, h) = (self.desc.width as i32, self.desc.height as i32);
+            let color_count = self.desc.colors.len() as u32;
+            for i in 0..color_count {
+                FrameBuffer::blit_attachment(&ms.frame_buffer, &self.frame_buffer, i, w, h);
+            }
+            // Blitting single attachments changes the draw buffers, put them back how we found them
+            self.frame_buffer.set_draw_buffers(color_count);
+            ms.frame_buffer.set_draw_buffers(color_count);
+            FrameBuffer::unbind();
+        }
+    }
+
+    /**
+     * Recreates all attachments at the new size, keeping every other setting. Contents are lost
+    */
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<(), String> {
+        if width == self.desc.width && height == self.desc.height {
+            return Ok(());
+        }
+        *self = RenderTexture::with_desc(RenderTextureDesc { width, height, ..self.desc.clone() })?;
+        Ok(())
     }
 }
 
@@ -443,7 +988,21 @@ impl Drop for Shader {
 impl Drop for Texture {
     
     fn drop(&mut self) { 
+        // Tests have no GL context to delete from, see Texture::unloaded()
+        if cfg!(test) && self.id == 0 {
+            return;
+        }
         unsafe { opengl().DeleteTextures(1, &self.id) }
     }
 }
 
+#[cfg(test)]
+impl Texture {
+    /**
+     * Texture that was never uploaded, for tests that only need its size
+    */
+    pub(crate) fn unloaded(w: u32, h: u32) -> Texture {
+        Texture { id: 0, unit: 0, size: glm::vec2(w, h) }
+    }
+}
+
@@ -0,0 +1,986 @@
+use crate::graphics::Texture;
+use crate::sys::{Quad, Rectui, parse_hex_color, read_file};
+use crate::tilemap::{Tile, TileMap, Tileset};
+use crate::vertex::*;
+use nalgebra_glm as glm;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+
+/**
+ * Value of an entity or level field. Points are in grid cells of the layer holding the entity
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldValue {
+    Null,
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    String(String),
+    Color(glm::Vec4),
+    Point(glm::TVec2<i32>),
+    Enum(String),
+    /**
+     * Resolved relative to the project file
+    */
+    FilePath(String),
+    EntityRef { entity_iid: String, layer_iid: String, level_iid: String, world_iid: String },
+    Tile { tileset_uid: i64, rect: Rectui },
+    Array(Vec<FieldValue>),
+}
+
+pub type Fields = HashMap<String, FieldValue>;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LdtkLayerType {
+    IntGrid,
+    Entities,
+    Tiles,
+    AutoLayer,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LdtkTilesetDef {
+    pub uid: i64,
+    pub identifier: String,
+    /**
+     * Image path resolved relative to the project, None for tilesets without an image (e.g. internal icons)
+    */
+    pub path: Option<String>,
+    pub px_width: u32,
+    pub px_height: u32,
+    pub grid_size: u32,
+    pub spacing: u32,
+    pub padding: u32,
+    /**
+     * Custom data string per tile id
+    */
+    pub custom_data: HashMap<u32, String>,
+    /**
+     * Tile ids tagged with each enum value
+    */
+    pub enum_tags: HashMap<String, Vec<u32>>,
+}
+
+impl LdtkTilesetDef {
+    pub fn columns(&self) -> u32 {
+        (self.px_width.saturating_sub(self.padding * 2) + self.spacing) / (self.grid_size + self.spacing).max(1)
+    }
+
+    /**
+     * Pixel rect of a tile with origin at the top left
+    */
+    pub fn tile_rect(&self, tile_id: u32) -> Rectui {
+        let (col, row) = (tile_id % self.columns().max(1), tile_id / self.columns().max(1));
+        let step = self.grid_size + self.spacing;
+        Rectui::new(self.padding + col * step, self.padding + row * step, self.grid_size, self.grid_size)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LdtkIntGridValue {
+    pub value: i32,
+    pub identifier: Option<String>,
+    pub color: glm::Vec4,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LdtkLayerDef {
+    pub uid: i64,
+    pub identifier: String,
+    pub layer_type: LdtkLayerType,
+    pub grid_size: u32,
+    pub int_grid_values: Vec<LdtkIntGridValue>,
+    pub tileset_uid: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LdtkEntityDef {
+    pub uid: i64,
+    pub identifier: String,
+    pub width: u32,
+    pub height: u32,
+    pub color: glm::Vec4,
+    /**
+     * Tile shown for the entity in the editor, (tileset uid, pixel rect)
+    */
+    pub tile: Option<(i64, Rectui)>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LdtkTile {
+    /**
+     * Position in the layer, pixels with y down
+    */
+    pub px: glm::TVec2<i32>,
+    /**
+     * Top left of the tile in the tileset image
+    */
+    pub src: glm::TVec2<u32>,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    pub id: u32,
+    pub alpha: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LdtkEntity {
+    pub identifier: String,
+    pub iid: String,
+    pub grid: glm::TVec2<i32>,
+    /**
+     * Normalized, 0, 0 at the top left of the entity
+    */
+    pub pivot: glm::Vec2,
+    /**
+     * Position of the pivot in the layer, pixels with y down
+    */
+    pub px: glm::TVec2<i32>,
+    pub width: u32,
+    pub height: u32,
+    pub tile: Option<(i64, Rectui)>,
+    pub tags: Vec<String>,
+    pub fields: Fields,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LdtkLayer {
+    pub identifier: String,
+    pub layer_type: LdtkLayerType,
+    pub grid_size: u32,
+    /**
+     * Size in cells
+    */
+    pub width: u32,
+    pub height: u32,
+    pub opacity: f32,
+    pub visible: bool,
+    /**
+     * Pixels, y down, including the layer definition's offset
+    */
+    pub offset: glm::TVec2<i32>,
+    pub tileset_uid: Option<i64>,
+    /**
+     * One value per cell in row order, 0 for empty. Only filled for IntGrid layers
+    */
+    pub int_grid: Vec<i32>,
+    /**
+     * Hand placed tiles for Tiles layers, rule generated ones for AutoLayer and IntGrid layers.
+     * Several tiles can share a cell
+    */
+    pub tiles: Vec<LdtkTile>,
+    pub entities: Vec<LdtkEntity>,
+}
+
+impl LdtkLayer {
+    pub fn int_grid_value(&self, x: u32, y: u32) -> i32 {
+        if x < self.width && y < self.height { self.int_grid.get((y * self.width + x) as usize).copied().unwrap_or(0) } else { 0 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LdtkLevel {
+    pub identifier: String,
+    pub iid: String,
+    pub uid: i64,
+    /**
+     * Top left of the level in the world, pixels with y down
+    */
+    pub world_x: i32,
+    pub world_y: i32,
+    pub world_depth: i32,
+    pub px_width: u32,
+    pub px_height: u32,
+    pub bg_color: Option<glm::Vec4>,
+    pub fields: Fields,
+    /**
+     * In LDtk's order, top most first
+    */
+    pub layers: Vec<LdtkLayer>,
+}
+
+impl LdtkLevel {
+    pub fn layer(&self, identifier: &str) -> Option<&LdtkLayer> {
+        self.layers.iter().find(|l| l.identifier == identifier)
+    }
+
+    /**
+     * Pixel position inside the level (y down) to world space (y up), keeping levels laid out
+     * the same way they are in the LDtk world
+    */
+    pub fn to_world(&self, px: glm::Vec2) -> glm::Vec2 {
+        glm::vec2(self.world_x as f32 + px.x, -(self.world_y as f32 + px.y))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LdtkWorld {
+    pub identifier: String,
+    pub iid: String,
+    pub layout: String,
+    pub levels: Vec<LdtkLevel>,
+}
+
+/**
+ * LDtk project loaded from its JSON, including levels saved as separate .ldtkl files.
+ * Projects without multiple worlds get a single world holding every level
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct LdtkProject {
+    pub json_version: String,
+    pub default_grid_size: u32,
+    pub bg_color: Option<glm::Vec4>,
+    pub tilesets: Vec<LdtkTilesetDef>,
+    pub layer_defs: Vec<LdtkLayerDef>,
+    pub entity_defs: Vec<LdtkEntityDef>,
+    pub worlds: Vec<LdtkWorld>,
+}
+
+impl LdtkProject {
+    pub fn from_file(filename: &str) -> Result<Self, String> {
+        let json = read_file(filename).map_err(|e| format!("Error loading file: {} :: {}", filename, e))?;
+        let dir = Path::new(filename).parent().map(|p| p.to_string_lossy().into_owned()).unwrap_or_default();
+        LdtkProject::from_json(&json, &dir).map_err(|e| format!("Error loading file: {} :: {}", filename, e))
+    }
+
+    /**
+     * base_dir is where tileset images and external level files are looked up
+    */
+    pub fn from_json(json: &str, base_dir: &str) -> Result<Self, String> {
+        let project: JsonProject = serde_json::from_str(json).map_err(|e| format!("LdtkError: {}", e))?;
+
+        let worlds = if project.worlds.is_empty() {
+            vec![JsonWorld {
+                identifier: "World".to_string(),
+                iid: project.iid.clone(),
+                world_layout: project.world_layout.clone(),
+                levels: project.levels,
+            }]
+        } else {
+            project.worlds
+        };
+
+        let worlds = worlds.into_iter()
+            .map(|w| Ok(LdtkWorld {
+                identifier: w.identifier,
+                iid: w.iid,
+                layout: w.world_layout.unwrap_or_default(),
+                levels: w.levels.into_iter().map(|l| convert_level(l, base_dir)).collect::<Result<_, String>>()?,
+            }))
+            .collect::<Result<_, String>>()?;
+
+        Ok(LdtkProject {
+            json_version: project.json_version,
+            default_grid_size: project.default_grid_size,
+            bg_color: project.bg_color.as_deref().and_then(parse_hex_color),
+            tilesets: project.defs.tilesets.into_iter().map(|t| t.into_def(base_dir)).collect(),
+            layer_defs: project.defs.layers.into_iter().map(JsonLayerDef::into_def).collect::<Result<_, _>>()?,
+            entity_defs: project.defs.entities.into_iter().map(JsonEntityDef::into_def).collect(),
+            worlds,
+        })
+    }
+
+    pub fn tileset(&self, uid: i64) -> Option<&LdtkTilesetDef> {
+        self.tilesets.iter().find(|t| t.uid == uid)
+    }
+
+    pub fn entity_def(&self, identifier: &str) -> Option<&LdtkEntityDef> {
+        self.entity_defs.iter().find(|e| e.identifier == identifier)
+    }
+
+    pub fn layer_def(&self, identifier: &str) -> Option<&LdtkLayerDef> {
+        self.layer_defs.iter().find(|l| l.identifier == identifier)
+    }
+
+    /**
+     * Every level in every world
+    */
+    pub fn levels(&self) -> impl Iterator<Item = &LdtkLevel> {
+        self.worlds.iter().flat_map(|w| w.levels.iter())
+    }
+
+    pub fn level(&self, identifier: &str) -> Option<&LdtkLevel> {
+        self.levels().find(|l| l.identifier == identifier)
+    }
+
+    /**
+     * Loads every tileset image, keyed by tileset uid, so levels can share them
+    */
+    pub fn load_textures(&self) -> Result<HashMap<i64, Rc<Texture>>, String> {
+        let mut textures = HashMap::new();
+        for t in self.tilesets.iter() {
+            if let Some(path) = &t.path {
+                textures.insert(t.uid, Rc::new(Texture::from_file(path)?));
+            }
+        }
+        Ok(textures)
+    }
+
+    /**
+     * Builds a TileMap from the level's tile, auto layer and IntGrid rule tiles, placed in world space
+     * with level.to_world(). Cells holding several tiles are split over extra layers, all keeping the
+     * LDtk layer's identifier. Every tiled layer must share one grid size. Per tile alpha is ignored
+    */
+    pub fn to_tile_map(&self, level: &LdtkLevel, textures: &HashMap<i64, Rc<Texture>>) -> Result<TileMap, String> {
+        let tiled: Vec<&LdtkLayer> = level.layers.iter().rev().filter(|l| !l.tiles.is_empty() && l.tileset_uid.is_some()).collect();
+        let grid = tiled.first().map(|l| l.grid_size).unwrap_or(self.default_grid_size).max(1);
+        if let Some(l) = tiled.iter().find(|l| l.grid_size != grid) {
+            return Err(format!("LdtkError: layer '{}' has grid size {} but others use {}", l.identifier, l.grid_size, grid));
+        }
+
+        let (width, height) = (level.px_width.div_ceil(grid), level.px_height.div_ceil(grid));
+        let cell_count = width.checked_mul(height)
+            .ok_or_else(|| format!("LdtkError: level '{}' is too large", level.identifier))? as usize;
+        let mut map = TileMap::new(width, height, glm::vec2(grid as f32, grid as f32));
+        map.set_origin(level.to_world(glm::vec2(0., 0.)));
+
+        let mut tileset_indices: HashMap<i64, usize> = HashMap::new();
+        for layer in tiled {
+            let uid = layer.tileset_uid.unwrap();
+            let def = self.tileset(uid).ok_or_else(|| format!("LdtkError: layer '{}' uses unknown tileset {}", layer.identifier, uid))?;
+            let index = match tileset_indices.get(&uid) {
+                Some(i) => *i,
+                None => {
+                    let texture = textures.get(&uid)
+                        .ok_or_else(|| format!("LdtkError: no texture loaded for tileset '{}'", def.identifier))?;
+                    let tileset = Tileset::new(texture.clone(), glm::vec2(def.grid_size, def.grid_size), def.padding, def.spacing)?;
+                    let i = map.add_tileset(tileset);
+                    tileset_indices.insert(uid, i);
+                    i
+                }
+            };
+            let columns = map.tilesets()[index].columns();
+
+            // Stacked tiles go on the first sub layer with that cell still free
+            let mut stacks: Vec<Vec<Tile>> = Vec::new();
+            for t in layer.tiles.iter() {
+                let (cx, cy) = (t.px.x.div_euclid(grid as i32), t.px.y.div_euclid(grid as i32));
+                if cx < 0 || cy < 0 || cx as u32 >= width || cy as u32 >= height {
+                    continue;
+                }
+                let cell = (cy as u32 * width + cx as u32) as usize;
+                let step = def.grid_size + def.spacing;
+                let (col, row) = (t.src.x.saturating_sub(def.padding) / step, t.src.y.saturating_sub(def.padding) / step);
+                let tile = Tile::new(row * columns + col).with_flip(t.flip_x, t.flip_y, false);
+
+                match stacks.iter_mut().find(|s| s[cell].is_empty()) {
+                    Some(s) => s[cell] = tile,
+                    None => {
+                        let mut s = vec![Tile::EMPTY; cell_count];
+                        s[cell] = tile;
+                        stacks.push(s);
+                    }
+                }
+            }
+
+            for tiles in stacks {
+                let i = map.add_layer(&layer.identifier, index)?;
+                let l = map.layer_mut(i).unwrap();
+                l.set_tiles(&tiles)?;
+                l.set_visible(layer.visible)
+                    .set_color(glm::vec4(1., 1., 1., layer.opacity))
+                    .set_offset(glm::vec2(layer.offset.x as f32, -layer.offset.y as f32));
+            }
+        }
+        Ok(map)
+    }
+
+    /**
+     * Quad for an entity's tile in world space, sized to the entity and placed around its pivot.
+     * Texture coordinates are in pixels of the tileset image (normalize_texture_coords() them with
+     * the texture's size). Also returns the tileset uid. None for entities without a tile
+    */
+    pub fn entity_quad(&self, level: &LdtkLevel, layer: &LdtkLayer, entity: &LdtkEntity) -> Option<(i64, Quad)> {
+        let (uid, rect) = entity.tile.or_else(|| self.entity_def(&entity.identifier).and_then(|d| d.tile))?;
+
+        let (w, h) = (entity.width as f32, entity.height as f32);
+        let px = entity.px + layer.offset;
+        let top_left = glm::vec2(px.x as f32 - entity.pivot.x * w, px.y as f32 - entity.pivot.y * h);
+        let corner = |x: f32, y: f32| level.to_world(top_left + glm::vec2(x, y));
+        let corners = [corner(0., 0.), corner(0., h), corner(w, 0.), corner(w, h)];
+
+        let (x0, y0) = (rect.x as f32, rect.y as f32);
+        let (x1, y1) = ((rect.x + rect.w) as f32, (rect.y + rect.h) as f32);
+        let uvs = [(x0, y0), (x0, y1), (x1, y0), (x1, y1)];
+
+        let mut verts = Quad::default_verts();
+        for (i, v) in verts.iter_mut().enumerate() {
+            v.position = Vert2DPosition { x: corners[i].x, y: corners[i].y, z: 0. };
+            v.text_coord = Vert2DTextureCoord { u: uvs[i].0, v: uvs[i].1 };
+        }
+        Some((uid, Quad::with_verts(&verts)))
+    }
+}
+
+fn resolve(base_dir: &str, path: &str) -> String {
+    Path::new(base_dir).join(path).to_string_lossy().into_owned()
+}
+
+fn convert_level(level: JsonLevel, base_dir: &str) -> Result<LdtkLevel, String> {
+    // Separate level files hold the whole level, layers included
+    let level = match (&level.layer_instances, &level.external_rel_path) {
+        (None, Some(rel)) => {
+            let filename = resolve(base_dir, rel);
+            let json = read_file(&filename).map_err(|e| format!("Error loading file: {} :: {}", filename, e))?;
+            serde_json::from_str::<JsonLevel>(&json).map_err(|e| format!("Error loading file: {} :: LdtkError: {}", filename, e))?
+        },
+        _ => level
+    };
+
+    let identifier = level.identifier;
+    let layers = level.layer_instances.unwrap_or_default().into_iter()
+        .map(|l| convert_layer(l, base_dir))
+        .collect::<Result<_, String>>()
+        .map_err(|e| format!("{} (level '{}')", e, identifier))?;
+
+    Ok(LdtkLevel {
+        identifier,
+        iid: level.iid,
+        uid: level.uid,
+        world_x: level.world_x,
+        world_y: level.world_y,
+        world_depth: level.world_depth,
+        px_width: level.px_wid,
+        px_height: level.px_hei,
+        bg_color: level.bg_color.as_deref().and_then(parse_hex_color),
+        fields: convert_fields(level.field_instances, base_dir)?,
+        layers,
+    })
+}
+
+fn convert_layer(layer: JsonLayer, base_dir: &str) -> Result<LdtkLayer, String> {
+    let tile = |t: &JsonTile| LdtkTile {
+        px: glm::vec2(t.px[0], t.px[1]),
+        src: glm::vec2(t.src[0], t.src[1]),
+        flip_x: t.f & 1 != 0,
+        flip_y: t.f & 2 != 0,
+        id: t.t,
+        alpha: t.a,
+    };
+    let tiles = layer.grid_tiles.iter().chain(layer.auto_layer_tiles.iter()).map(tile).collect();
+
+    let entities = layer.entity_instances.into_iter()
+        .map(|e| Ok(LdtkEntity {
+            identifier: e.identifier,
+            iid: e.iid,
+            grid: glm::vec2(e.grid[0], e.grid[1]),
+            pivot: glm::vec2(e.pivot[0], e.pivot[1]),
+            px: glm::vec2(e.px[0], e.px[1]),
+            width: e.width,
+            height: e.height,
+            tile: e.tile.map(|t| (t.tileset_uid, Rectui::new(t.x, t.y, t.w, t.h))),
+            tags: e.tags,
+            fields: convert_fields(e.field_instances, base_dir)?,
+        }))
+        .collect::<Result<_, String>>()?;
+
+    Ok(LdtkLayer {
+        layer_type: parse_layer_type(&layer.layer_type)?,
+        identifier: layer.identifier,
+        grid_size: layer.grid_size,
+        width: layer.c_wid,
+        height: layer.c_hei,
+        opacity: layer.opacity,
+        visible: layer.visible,
+        offset: glm::vec2(layer.px_total_offset_x, layer.px_total_offset_y),
+        tileset_uid: layer.tileset_def_uid,
+        int_grid: layer.int_grid_csv,
+        tiles,
+        entities,
+    })
+}
+
+fn parse_layer_type(s: &str) -> Result<LdtkLayerType, String> {
+    Ok(match s {
+        "IntGrid" => LdtkLayerType::IntGrid,
+        "Entities" => LdtkLayerType::Entities,
+        "Tiles" => LdtkLayerType::Tiles,
+        "AutoLayer" => LdtkLayerType::AutoLayer,
+        other => return Err(format!("LdtkError: unknown layer type '{}'", other))
+    })
+}
+
+fn convert_fields(fields: Vec<JsonField>, base_dir: &str) -> Result<Fields, String> {
+    fields.into_iter()
+        .map(|f| {
+            let value = field_value(&f.field_type, &f.value, base_dir)
+                .map_err(|e| format!("{} (field '{}')", e, f.identifier))?;
+            Ok((f.identifier, value))
+        })
+        .collect()
+}
+
+/**
+ * Converts __value based on __type, e.g. "Int", "Array<Point>", "LocalEnum.Item"
+*/
+fn field_value(kind: &str, value: &Value, base_dir: &str) -> Result<FieldValue, String> {
+    if value.is_null() {
+        return Ok(FieldValue::Null);
+    }
+    if let Some(inner) = kind.strip_prefix("Array<").and_then(|k| k.strip_suffix('>')) {
+        let items = value.as_array().ok_or_else(|| format!("LdtkError: expected an array for {}", kind))?;
+        return Ok(FieldValue::Array(items.iter().map(|v| field_value(inner, v, base_dir)).collect::<Result<_, _>>()?));
+    }
+
+    let bad = || format!("LdtkError: bad {} value {}", kind, value);
+    let str_of = |key: &str| value.get(key).and_then(Value::as_str).unwrap_or("").to_string();
+    let int_of = |key: &str| value.get(key).and_then(Value::as_i64);
+
+    Ok(match kind {
+        "Int" => FieldValue::Int(value.as_i64().ok_or_else(bad)?),
+        "Float" => FieldValue::Float(value.as_f64().ok_or_else(bad)?),
+        "Bool" => FieldValue::Bool(value.as_bool().ok_or_else(bad)?),
+        "String" | "Multilines" => FieldValue::String(value.as_str().ok_or_else(bad)?.to_string()),
+        "Color" => FieldValue::Color(value.as_str().and_then(parse_hex_color).ok_or_else(bad)?),
+        "FilePath" => FieldValue::FilePath(resolve(base_dir, value.as_str().ok_or_else(bad)?)),
+        "Point" => FieldValue::Point(glm::vec2(int_of("cx").ok_or_else(bad)? as i32, int_of("cy").ok_or_else(bad)? as i32)),
+        "EntityRef" => FieldValue::EntityRef {
+            entity_iid: str_of("entityIid"),
+            layer_iid: str_of("layerIid"),
+            level_iid: str_of("levelIid"),
+            world_iid: str_of("worldIid"),
+        },
+        "Tile" => {
+            let n = |key: &str| int_of(key).map(|v| v as u32).ok_or_else(bad);
+            FieldValue::Tile { tileset_uid: int_of("tilesetUid").ok_or_else(bad)?, rect: Rectui::new(n("x")?, n("y")?, n("w")?, n("h")?) }
+        },
+        k if k.starts_with("LocalEnum.") || k.starts_with("ExternEnum.") || k.starts_with("Enum(") => {
+            FieldValue::Enum(value.as_str().ok_or_else(bad)?.to_string())
+        },
+        other => return Err(format!("LdtkError: unknown field type '{}'", other))
+    })
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonProject {
+    #[serde(default)]
+    iid: String,
+    #[serde(default)]
+    json_version: String,
+    #[serde(default)]
+    default_grid_size: u32,
+    bg_color: Option<String>,
+    world_layout: Option<String>,
+    defs: JsonDefs,
+    #[serde(default)]
+    levels: Vec<JsonLevel>,
+    #[serde(default)]
+    worlds: Vec<JsonWorld>,
+}
+
+#[derive(Deserialize)]
+struct JsonDefs {
+    #[serde(default)]
+    tilesets: Vec<JsonTilesetDef>,
+    #[serde(default)]
+    layers: Vec<JsonLayerDef>,
+    #[serde(default)]
+    entities: Vec<JsonEntityDef>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonCustomData {
+    tile_id: u32,
+    data: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonEnumTag {
+    enum_value_id: String,
+    tile_ids: Vec<u32>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonTilesetDef {
+    uid: i64,
+    identifier: String,
+    rel_path: Option<String>,
+    px_wid: u32,
+    px_hei: u32,
+    tile_grid_size: u32,
+    #[serde(default)]
+    spacing: u32,
+    #[serde(default)]
+    padding: u32,
+    #[serde(default)]
+    custom_data: Vec<JsonCustomData>,
+    #[serde(default)]
+    enum_tags: Vec<JsonEnumTag>,
+}
+
+impl JsonTilesetDef {
+    fn into_def(self, base_dir: &str) -> LdtkTilesetDef {
+        LdtkTilesetDef {
+            uid: self.uid,
+            identifier: self.identifier,
+            path: self.rel_path.map(|p| resolve(base_dir, &p)),
+            px_width: self.px_wid,
+            px_height: self.px_hei,
+            grid_size: self.tile_grid_size,
+            spacing: self.spacing,
+            padding: self.padding,
+            custom_data: self.custom_data.into_iter().map(|c| (c.tile_id, c.data)).collect(),
+            enum_tags: self.enum_tags.into_iter().map(|t| (t.enum_value_id, t.tile_ids)).collect(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonIntGridValue {
+    value: i32,
+    identifier: Option<String>,
+    color: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonLayerDef {
+    uid: i64,
+    identifier: String,
+    #[serde(rename = "type")]
+    layer_type: String,
+    grid_size: u32,
+    #[serde(default)]
+    int_grid_values: Vec<JsonIntGridValue>,
+    tileset_def_uid: Option<i64>,
+}
+
+impl JsonLayerDef {
+    fn into_def(self) -> Result<LdtkLayerDef, String> {
+        Ok(LdtkLayerDef {
+            uid: self.uid,
+            layer_type: parse_layer_type(&self.layer_type)?,
+            identifier: self.identifier,
+            grid_size: self.grid_size,
+            int_grid_values: self.int_grid_values.into_iter()
+                .map(|v| LdtkIntGridValue {
+                    value: v.value,
+                    identifier: v.identifier,
+                    color: parse_hex_color(&v.color).unwrap_or_else(|| glm::vec4(1., 1., 1., 1.)),
+                })
+                .collect(),
+            tileset_uid: self.tileset_def_uid,
+        })
+    }
+}
+
+#[derive(Deserialize, Copy, Clone)]
+#[serde(rename_all = "camelCase")]
+struct JsonTileRect {
+    tileset_uid: i64,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonEntityDef {
+    uid: i64,
+    identifier: String,
+    width: u32,
+    height: u32,
+    color: String,
+    tile_rect: Option<JsonTileRect>,
+}
+
+impl JsonEntityDef {
+    fn into_def(self) -> LdtkEntityDef {
+        LdtkEntityDef {
+            uid: self.uid,
+            identifier: self.identifier,
+            width: self.width,
+            height: self.height,
+            color: parse_hex_color(&self.color).unwrap_or_else(|| glm::vec4(1., 1., 1., 1.)),
+            tile: self.tile_rect.map(|t| (t.tileset_uid, Rectui::new(t.x, t.y, t.w, t.h))),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonWorld {
+    identifier: String,
+    iid: String,
+    world_layout: Option<String>,
+    #[serde(default)]
+    levels: Vec<JsonLevel>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonLevel {
+    identifier: String,
+    iid: String,
+    uid: i64,
+    world_x: i32,
+    world_y: i32,
+    #[serde(default)]
+    world_depth: i32,
+    px_wid: u32,
+    px_hei: u32,
+    #[serde(rename = "__bgColor")]
+    bg_color: Option<String>,
+    #[serde(default)]
+    field_instances: Vec<JsonField>,
+    /**
+     * null when the level is saved in its own file
+    */
+    layer_instances: Option<Vec<JsonLayer>>,
+    external_rel_path: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct JsonField {
+    #[serde(rename = "__identifier")]
+    identifier: String,
+    #[serde(rename = "__type")]
+    field_type: String,
+    #[serde(rename = "__value")]
+    value: Value,
+}
+
+#[derive(Deserialize)]
+struct JsonTile {
+    px: [i32; 2],
+    src: [u32; 2],
+    /**
+     * Bit 0 flips x, bit 1 flips y
+    */
+    #[serde(default)]
+    f: u32,
+    #[serde(default)]
+    t: u32,
+    #[serde(default = "default_alpha")]
+    a: f32,
+}
+
+fn default_alpha() -> f32 { 1. }
+fn default_visible() -> bool { true }
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonLayer {
+    #[serde(rename = "__identifier")]
+    identifier: String,
+    #[serde(rename = "__type")]
+    layer_type: String,
+    #[serde(rename = "__cWid")]
+    c_wid: u32,
+    #[serde(rename = "__cHei")]
+    c_hei: u32,
+    #[serde(rename = "__gridSize")]
+    grid_size: u32,
+    #[serde(rename = "__opacity", default = "default_alpha")]
+    opacity: f32,
+    #[serde(rename = "__pxTotalOffsetX", default)]
+    px_total_offset_x: i32,
+    #[serde(rename = "__pxTotalOffsetY", default)]
+    px_total_offset_y: i32,
+    #[serde(rename = "__tilesetDefUid")]
+    tileset_def_uid: Option<i64>,
+    #[serde(default = "default_visible")]
+    visible: bool,
+    #[serde(default)]
+    int_grid_csv: Vec<i32>,
+    #[serde(default)]
+    auto_layer_tiles: Vec<JsonTile>,
+    #[serde(default)]
+    grid_tiles: Vec<JsonTile>,
+    #[serde(default)]
+    entity_instances: Vec<JsonEntity>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonEntity {
+    #[serde(rename = "__identifier")]
+    identifier: String,
+    #[serde(default)]
+    iid: String,
+    #[serde(rename = "__grid")]
+    grid: [i32; 2],
+    #[serde(rename = "__pivot")]
+    pivot: [f32; 2],
+    #[serde(rename = "__tags", default)]
+    tags: Vec<String>,
+    #[serde(rename = "__tile")]
+    tile: Option<JsonTileRect>,
+    width: u32,
+    height: u32,
+    px: [i32; 2],
+    #[serde(default)]
+    field_instances: Vec<JsonField>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn convert(kind: &str, value: Value) -> Result<FieldValue, String> {
+        field_value(kind, &value, "assets")
+    }
+
+    #[test]
+    fn converts_scalar_fields() {
+        assert_eq!(convert("Int", json!(7)), Ok(FieldValue::Int(7)));
+        assert_eq!(convert("Float", json!(0.5)), Ok(FieldValue::Float(0.5)));
+        assert_eq!(convert("Bool", json!(true)), Ok(FieldValue::Bool(true)));
+        assert_eq!(convert("Multilines", json!("a\nb")), Ok(FieldValue::String("a\nb".into())));
+        assert_eq!(convert("LocalEnum.Kind", json!("Door")), Ok(FieldValue::Enum("Door".into())));
+        assert_eq!(convert("Color", json!("#ff0000")), Ok(FieldValue::Color(glm::vec4(1., 0., 0., 1.))));
+        assert_eq!(convert("String", Value::Null), Ok(FieldValue::Null));
+        assert_eq!(convert("FilePath", json!("a.png")), Ok(FieldValue::FilePath(resolve("assets", "a.png"))));
+    }
+
+    #[test]
+    fn converts_structured_fields() {
+        assert_eq!(convert("Point", json!({ "cx": 3, "cy": 4 })), Ok(FieldValue::Point(glm::vec2(3, 4))));
+        assert_eq!(convert("Tile", json!({ "tilesetUid": 2, "x": 16, "y": 0, "w": 8, "h": 8 })),
+            Ok(FieldValue::Tile { tileset_uid: 2, rect: Rectui::new(16, 0, 8, 8) }));
+        assert_eq!(convert("EntityRef", json!({ "entityIid": "e", "layerIid": "l", "levelIid": "v", "worldIid": "w" })),
+            Ok(FieldValue::EntityRef { entity_iid: "e".into(), layer_iid: "l".into(), level_iid: "v".into(), world_iid: "w".into() }));
+        assert_eq!(convert("Array<Int>", json!([1, null, 3])),
+            Ok(FieldValue::Array(vec![FieldValue::Int(1), FieldValue::Null, FieldValue::Int(3)])));
+    }
+
+    #[test]
+    fn bad_fields_are_errors() {
+        assert!(convert("Int", json!("7")).is_err());
+        assert!(convert("Color", json!("#ñ0000")).is_err());
+        assert!(convert("Point", json!({ "cx": 1 })).is_err());
+        assert!(convert("Array<Int>", json!(1)).is_err());
+        assert!(convert("Mystery", json!(1)).is_err());
+
+        let fields = vec![JsonField { identifier: "hp".into(), field_type: "Int".into(), value: json!(false) }];
+        assert!(convert_fields(fields, "").unwrap_err().contains("'hp'"));
+    }
+
+    const TILESET: &str = r#"{ "uid": 1, "identifier": "Tiles", "relPath": "tiles.png", "pxWid": 32, "pxHei": 16, "tileGridSize": 8 }"#;
+    const LAYER_DEF: &str = r#"{ "uid": 10, "identifier": "Ground", "type": "Tiles", "gridSize": 8, "tilesetDefUid": 1 }"#;
+
+    /**
+     * 2x2 cell tiles layer, the top left cell holds two tiles
+    */
+    const GROUND: &str = r#"{ "__identifier": "Ground", "__type": "Tiles", "__cWid": 2, "__cHei": 2, "__gridSize": 8,
+        "__tilesetDefUid": 1, "gridTiles": [
+            { "px": [0, 0], "src": [8, 0], "f": 1, "t": 1 },
+            { "px": [0, 0], "src": [0, 8], "t": 4 },
+            { "px": [8, 8], "src": [24, 8], "t": 7 }
+        ] }"#;
+
+    fn level(identifier: &str, world_x: i32, layers: &str) -> String {
+        format!(r#"{{ "identifier": "{}", "iid": "{}_iid", "uid": 0, "worldX": {}, "worldY": 0, "pxWid": 16, "pxHei": 16,
+            "layerInstances": {} }}"#, identifier, identifier, world_x, layers)
+    }
+
+    fn project(levels: &str, worlds: &str) -> String {
+        format!(r##"{{ "iid": "project", "jsonVersion": "1.5.3", "defaultGridSize": 8, "bgColor": "#000000",
+            "defs": {{ "tilesets": [{}], "layers": [{}], "entities": [] }},
+            "levels": [{}], "worlds": [{}] }}"##, TILESET, LAYER_DEF, levels, worlds)
+    }
+
+    fn ground_project(layers: &str) -> LdtkProject {
+        LdtkProject::from_json(&project(&level("Level_0", 0, layers), ""), "assets").unwrap()
+    }
+
+    #[test]
+    fn flat_levels_go_in_one_world() {
+        let p = ground_project(&format!("[{}]", GROUND));
+        assert_eq!(p.worlds.len(), 1);
+        assert_eq!(p.worlds[0].identifier, "World");
+        assert_eq!(p.worlds[0].iid, "project");
+        assert_eq!(p.tilesets[0].path, Some(resolve("assets", "tiles.png")));
+        assert_eq!(p.layer_def("Ground").unwrap().layer_type, LdtkLayerType::Tiles);
+
+        let layer = p.level("Level_0").unwrap().layer("Ground").unwrap();
+        assert_eq!(layer.tiles.len(), 3);
+        assert!(layer.tiles[0].flip_x && !layer.tiles[0].flip_y);
+    }
+
+    #[test]
+    fn multiple_worlds() {
+        let world = |id: &str, levels: String| format!(r#"{{ "identifier": "{}", "iid": "{}_iid", "worldLayout": "Free", "levels": [{}] }}"#, id, id, levels);
+        let worlds = format!("{}, {}", world("A", level("A_0", 0, "[]")), world("B", level("B_0", 0, "[]") + ", " + &level("B_1", 16, "[]")));
+        let p = LdtkProject::from_json(&project("", &worlds), "").unwrap();
+
+        assert_eq!(p.worlds.len(), 2);
+        assert_eq!(p.worlds[1].layout, "Free");
+        let ids: Vec<&str> = p.levels().map(|l| l.identifier.as_str()).collect();
+        assert_eq!(ids, vec!["A_0", "B_0", "B_1"]);
+        assert_eq!(p.level("B_1").unwrap().world_x, 16);
+    }
+
+    #[test]
+    fn external_levels_are_loaded_from_their_own_file() {
+        let dir = std::env::temp_dir().join(format!("ruckus_ldtk_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("levels")).unwrap();
+        std::fs::write(dir.join("levels/Level_0.ldtkl"), level("Level_0", 0, &format!("[{}]", GROUND))).unwrap();
+
+        let external = |path: &str| format!(r#"{{ "identifier": "Level_0", "iid": "Level_0_iid", "uid": 0, "worldX": 0, "worldY": 0,
+            "pxWid": 16, "pxHei": 16, "layerInstances": null, "externalRelPath": "{}" }}"#, path);
+        let base_dir = dir.to_string_lossy().into_owned();
+        let p = LdtkProject::from_json(&project(&external("levels/Level_0.ldtkl"), ""), &base_dir);
+        let missing = LdtkProject::from_json(&project(&external("levels/Level_1.ldtkl"), ""), &base_dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(p.unwrap().level("Level_0").unwrap().layer("Ground").unwrap().tiles.len(), 3);
+        assert!(missing.unwrap_err().starts_with("Error loading file"));
+    }
+
+    #[test]
+    fn unknown_layer_types_are_errors() {
+        assert_eq!(parse_layer_type("IntGrid"), Ok(LdtkLayerType::IntGrid));
+        assert_eq!(parse_layer_type("Entities"), Ok(LdtkLayerType::Entities));
+        assert_eq!(parse_layer_type("Tiles"), Ok(LdtkLayerType::Tiles));
+        assert_eq!(parse_layer_type("AutoLayer"), Ok(LdtkLayerType::AutoLayer));
+        assert!(parse_layer_type("tiles").unwrap_err().contains("'tiles'"));
+
+        let bad_def = project("", "").replace(r#""type": "Tiles""#, r#""type": "Grid""#);
+        assert!(LdtkProject::from_json(&bad_def, "").unwrap_err().contains("'Grid'"));
+
+        let bad_layer = project(&level("Level_0", 0, &format!("[{}]", GROUND.replace(r#""__type": "Tiles""#, r#""__type": "Grid""#))), "");
+        assert!(LdtkProject::from_json(&bad_layer, "").unwrap_err().contains("(level 'Level_0')"));
+    }
+
+    #[test]
+    fn stacked_tiles_are_split_into_layers() {
+        let p = ground_project(&format!("[{}]", GROUND));
+        let textures = HashMap::from([(1, Rc::new(Texture::unloaded(32, 16)))]);
+        let map = p.to_tile_map(p.level("Level_0").unwrap(), &textures).unwrap();
+
+        assert_eq!(map.width(), 2);
+        assert_eq!(map.tilesets().len(), 1);
+        assert_eq!(map.layers().len(), 2);
+        assert!(map.layers().iter().all(|l| l.name() == "Ground"));
+
+        let (first, second) = (&map.layers()[0], &map.layers()[1]);
+        assert_eq!(first.tile(0, 0), Tile::new(1).with_flip(true, false, false));
+        assert_eq!(first.tile(1, 1), Tile::new(7));
+        assert_eq!(second.tile(0, 0), Tile::new(4));
+        assert!(second.tile(1, 1).is_empty());
+        assert!(first.tile(1, 0).is_empty());
+    }
+
+    #[test]
+    fn tile_map_needs_one_grid_size_and_every_texture() {
+        let detail = GROUND.replace("Ground", "Detail").replace(r#""__gridSize": 8"#, r#""__gridSize": 16"#);
+        let p = ground_project(&format!("[{}, {}]", detail, GROUND));
+        let textures = HashMap::from([(1, Rc::new(Texture::unloaded(32, 16)))]);
+        let err = p.to_tile_map(p.level("Level_0").unwrap(), &textures).err().unwrap();
+        assert!(err.contains("'Detail'") && err.contains("grid size 16"));
+
+        let p = ground_project(&format!("[{}]", GROUND));
+        let err = p.to_tile_map(p.level("Level_0").unwrap(), &HashMap::new()).err().unwrap();
+        assert!(err.contains("no texture loaded for tileset 'Tiles'"));
+    }
+}
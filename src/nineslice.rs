@@ -0,0 +1,386 @@
+use crate::Renderable;
+use crate::Renderer;
+use crate::graphics::Texture;
+use crate::sys::{Rectf, Rectui};
+use crate::vertex::*;
+use nalgebra_glm as glm;
+use std::cell::{Cell, Ref, RefCell};
+use std::rc::Rc;
+
+/**
+ * How the edges and center fill the space between the corners
+*/
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SliceMode {
+    Stretch,
+    /**
+     * Repeats the source at its natural size, the last tile is cut short
+    */
+    Tile,
+}
+
+/**
+ * Border widths in texture pixels, measured inward from each side of the region
+*/
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct SliceInsets {
+    pub left: u32,
+    pub right: u32,
+    pub top: u32,
+    pub bottom: u32,
+}
+
+impl SliceInsets {
+    pub fn new(left: u32, right: u32, top: u32, bottom: u32) -> Self {
+        SliceInsets { left, right, top, bottom }
+    }
+
+    pub fn uniform(inset: u32) -> Self {
+        SliceInsets::new(inset, inset, inset, inset)
+    }
+}
+
+/**
+ * Texture region split into 3x3 cells by four insets. Corners keep their size, edges stretch or tile
+ * along one axis and the center along both, so panels and buttons can be any size without distorting
+ * their borders. The destination rect is in world space with x, y at the bottom left (y up).
+ * Vertices are rebuilt on draw only when something changed
+*/
+pub struct NineSlice {
+    texture: Rc<Texture>,
+    region: Rectui,
+    insets: SliceInsets,
+    rect: Rectf,
+    border_scale: f32,
+    edge_mode: SliceMode,
+    center_mode: SliceMode,
+    fill_center: bool,
+    color: glm::Vec4,
+
+    verts: RefCell<Vec<Vertex2D>>,
+    dirty: Cell<bool>,
+}
+
+impl NineSlice {
+    /**
+     * region is in pixels with origin at the top left, like Sprite::with_rect()
+    */
+    pub fn new(texture: Rc<Texture>, region: Rectui, insets: SliceInsets) -> Self {
+        NineSlice {
+            texture,
+            region,
+            insets,
+            rect: Rectf::new(0., 0., region.w as f32, region.h as f32),
+            border_scale: 1.,
+            edge_mode: SliceMode::Stretch,
+            center_mode: SliceMode::Stretch,
+            fill_center: true,
+            color: glm::vec4(1., 1., 1., 1.),
+            verts: RefCell::new(Vec::new()),
+            dirty: Cell::new(true),
+        }
+    }
+
+    pub fn texture(&self) -> &Rc<Texture> { &self.texture }
+    pub fn region(&self) -> Rectui { self.region }
+    pub fn insets(&self) -> SliceInsets { self.insets }
+    pub fn rect(&self) -> Rectf { self.rect }
+    pub fn border_scale(&self) -> f32 { self.border_scale }
+    pub fn edge_mode(&self) -> SliceMode { self.edge_mode }
+    pub fn center_mode(&self) -> SliceMode { self.center_mode }
+    pub fn fill_center(&self) -> bool { self.fill_center }
+    pub fn color(&self) -> glm::Vec4 { self.color }
+
+    pub fn set_texture(&mut self, texture: Rc<Texture>) -> &mut Self {
+        self.texture = texture;
+        self.invalidate()
+    }
+
+    pub fn set_region(&mut self, region: Rectui) -> &mut Self {
+        if self.region != region {
+            self.region = region;
+            self.invalidate();
+        }
+        self
+    }
+
+    pub fn set_insets(&mut self, insets: SliceInsets) -> &mut Self {
+        if self.insets != insets {
+            self.insets = insets;
+            self.invalidate();
+        }
+        self
+    }
+
+    pub fn set_rect(&mut self, rect: Rectf) -> &mut Self {
+        if self.rect != rect {
+            self.rect = rect;
+            self.invalidate();
+        }
+        self
+    }
+
+    /**
+     * World units per texture pixel for the borders and tiles, 1 by default
+    */
+    pub fn set_border_scale(&mut self, scale: f32) -> &mut Self {
+        if self.border_scale != scale {
+            self.border_scale = scale;
+            self.invalidate();
+        }
+        self
+    }
+
+    pub fn set_edge_mode(&mut self, mode: SliceMode) -> &mut Self {
+        if self.edge_mode != mode {
+            self.edge_mode = mode;
+            self.invalidate();
+        }
+        self
+    }
+
+    pub fn set_center_mode(&mut self, mode: SliceMode) -> &mut Self {
+        if self.center_mode != mode {
+            self.center_mode = mode;
+            self.invalidate();
+        }
+        self
+    }
+
+    /**
+     * When false only the border is drawn, for frames around other content
+    */
+    pub fn set_fill_center(&mut self, fill: bool) -> &mut Self {
+        if self.fill_center != fill {
+            self.fill_center = fill;
+            self.invalidate();
+        }
+        self
+    }
+
+    /**
+     * Multiplied with the texture color
+    */
+    pub fn set_color(&mut self, color: glm::Vec4) -> &mut Self {
+        if self.color != color {
+            self.color = color;
+            self.invalidate();
+        }
+        self
+    }
+
+    /**
+     * Triangle list with normalized texture coordinates, ready for Renderer::draw_triangles()
+    */
+    pub fn vertices(&self) -> Ref<'_, Vec<Vertex2D>> {
+        if self.dirty.get() {
+            self.verts.replace(self.build_verts());
+            self.dirty.set(false);
+        }
+        self.verts.borrow()
+    }
+
+    fn invalidate(&mut self) -> &mut Self {
+        self.dirty.set(true);
+        self
+    }
+
+    fn build_verts(&self) -> Vec<Vertex2D> {
+        let (r, i, d) = (self.region, self.insets, self.rect);
+        let scale = self.border_scale.max(0.);
+
+        // Borders shrink together when the rect is too small to fit them
+        let (bl, br) = (i.left as f32 * scale, i.right as f32 * scale);
+        let (bt, bb) = (i.top as f32 * scale, i.bottom as f32 * scale);
+        let sx = if bl + br > d.w { d.w / (bl + br) } else { 1. };
+        let sy = if bt + bb > d.h { d.h / (bt + bb) } else { 1. };
+
+        let top = d.y + d.h;
+        let xs = [d.x, d.x + bl * sx, d.right() - br * sx, d.right()];
+        let ys = [top, top - bt * sy, d.y + bb * sy, d.y];
+
+        let (l, t) = (r.x as f32, r.y as f32);
+        let (right, bottom) = ((r.x + r.w) as f32, (r.y + r.h) as f32);
+        let us = [l, l + i.left as f32, right - i.right as f32, right];
+        let vs = [t, t + i.top as f32, bottom - i.bottom as f32, bottom];
+
+        let mut out = Vec::with_capacity(54);
+        for row in 0..3 {
+            for col in 0..3 {
+                let center = row == 1 && col == 1;
+                if center && !self.fill_center {
+                    continue;
+                }
+                let dest = [xs[col], ys[row], xs[col + 1], ys[row + 1]];
+                let src = [us[col], vs[row], us[col + 1], vs[row + 1]];
+                if dest[2] - dest[0] <= 0. || dest[1] - dest[3] <= 0. || src[2] <= src[0] || src[3] <= src[1] {
+                    continue;
+                }
+
+                let mode = if center { self.center_mode } else if row == 1 || col == 1 { self.edge_mode } else { SliceMode::Stretch };
+                // Edge tiles keep the aspect of their shrunk border, the center tiles at plain scale
+                let (tile_x, tile_y) = match mode {
+                    SliceMode::Stretch => (0., 0.),
+                    SliceMode::Tile if center => (scale, scale),
+                    SliceMode::Tile if col == 1 => (scale * sy, 0.),
+                    SliceMode::Tile => (0., scale * sx),
+                };
+                self.push_cell(&mut out, dest, src, tile_x, tile_y);
+            }
+        }
+        out
+    }
+
+    /**
+     * dest is [left, top, right, bottom] in world space, src the same in texture pixels.
+     * A non zero tile scale repeats the source along that axis at source size times the scale
+    */
+    fn push_cell(&self, out: &mut Vec<Vertex2D>, dest: [f32; 4], src: [f32; 4], tile_x: f32, tile_y: f32) {
+        let spans_x = tile_spans(dest[0], dest[2], src[0], src[2], tile_x);
+        let spans_y = tile_spans(-dest[1], -dest[3], src[1], src[3], tile_y);
+
+        let size = glm::vec2(self.texture.size.x as f32, self.texture.size.y as f32);
+        let color = Vert2DColor::from(&self.color);
+        let vert = |x: f32, y: f32, u: f32, v: f32| Vertex2D {
+            position: Vert2DPosition { x, y, z: 0. },
+            text_coord: Vert2DTextureCoord { u: u / size.x, v: 1. - v / size.y },
+            color,
+        };
+
+        for (x0, x1, u0, u1) in spans_x.iter() {
+            for (y0, y1, v0, v1) in spans_y.iter() {
+                // y spans were built on negated y so they run top to bottom like the texture
+                let tl = vert(*x0, -y0, *u0, *v0);
+                let bl = vert(*x0, -y1, *u0, *v1);
+                let tr = vert(*x1, -y0, *u1, *v0);
+                let br = vert(*x1, -y1, *u1, *v1);
+                out.extend_from_slice(&[tl, bl, br, tl, br, tr]);
+            }
+        }
+    }
+}
+
+impl Renderable for NineSlice {
+    fn draw(&self, renderer: &Renderer) {
+        renderer.draw_triangles(&self.vertices(), self.texture.as_ref());
+    }
+}
+
+/**
+ * Most pieces tile_spans() splits one axis of a cell into
+*/
+const MAX_TILE_SPANS: f32 = 256.;
+
+/**
+ * Splits d0..d1 into pieces mapping onto s0..s1. With tile_scale 0 that's a single stretched piece,
+ * otherwise pieces are (s1 - s0) * tile_scale long and the last one samples only part of the source.
+ * Tiles are made longer if needed so there are at most MAX_TILE_SPANS pieces
+*/
+fn tile_spans(d0: f32, d1: f32, s0: f32, s1: f32, tile_scale: f32) -> Vec<(f32, f32, f32, f32)> {
+    let tile = (s1 - s0) * tile_scale;
+    if tile <= 0. {
+        return vec![(d0, d1, s0, s1)];
+    }
+    // A tiny border scale would otherwise mean millions of tiles
+    let tile = tile.max((d1 - d0) / MAX_TILE_SPANS);
+    let tile_scale = tile / (s1 - s0);
+
+    let mut spans = Vec::with_capacity(((d1 - d0) / tile).ceil() as usize);
+    let mut start = d0;
+    while d1 - start > 1e-4 {
+        let end = (start + tile).min(d1);
+        spans.push((start, end, s0, s0 + (end - start) / tile_scale));
+        start = end;
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type SliceCell = ((f32, f32, f32, f32), (f32, f32, f32, f32));
+
+    fn slice(rect: Rectf) -> NineSlice {
+        let mut n = NineSlice::new(Rc::new(Texture::unloaded(32, 32)), Rectui::new(0, 0, 32, 32), SliceInsets::uniform(8));
+        n.set_rect(rect);
+        n
+    }
+
+    /**
+     * Each quad's positions as (left, top, right, bottom) and uvs as (u left, v top, u right, v bottom)
+    */
+    fn cells(n: &NineSlice) -> Vec<SliceCell> {
+        let verts = n.vertices();
+        assert_eq!(verts.len() % 6, 0);
+        verts.chunks(6).map(|q| {
+            let (tl, br) = (q[0], q[2]);
+            ((tl.position.x, tl.position.y, br.position.x, br.position.y),
+             (tl.text_coord.u, tl.text_coord.v, br.text_coord.u, br.text_coord.v))
+        }).collect()
+    }
+
+    #[test]
+    fn stretch_corners_edges_and_center() {
+        let c = cells(&slice(Rectf::new(0., 0., 100., 50.)));
+        assert_eq!(c.len(), 9);
+        // Top left corner keeps its 8 pixels
+        assert_eq!(c[0], ((0., 50., 8., 42.), (0., 1., 0.25, 0.75)));
+        // Top edge stretches across
+        assert_eq!(c[1], ((8., 50., 92., 42.), (0.25, 1., 0.75, 0.75)));
+        assert_eq!(c[4], ((8., 42., 92., 8.), (0.25, 0.75, 0.75, 0.25)));
+        assert_eq!(c[5], ((92., 42., 100., 8.), (0.75, 0.75, 1., 0.25)));
+        assert_eq!(c[8], ((92., 8., 100., 0.), (0.75, 0.25, 1., 0.)));
+    }
+
+    #[test]
+    fn borders_shrink_to_fit_small_rects() {
+        let c = cells(&slice(Rectf::new(0., 0., 8., 40.)));
+        // No room for the middle column, left and right borders are squashed to half width
+        assert_eq!(c.len(), 6);
+        assert_eq!(c[0], ((0., 40., 4., 32.), (0., 1., 0.25, 0.75)));
+        assert_eq!(c[1], ((4., 40., 8., 32.), (0.75, 1., 1., 0.75)));
+        assert_eq!(c[2].0, (0., 32., 4., 8.));
+    }
+
+    #[test]
+    fn tiled_edges_cut_the_last_tile_short() {
+        let mut n = slice(Rectf::new(0., 0., 56., 32.));
+        n.set_edge_mode(SliceMode::Tile);
+        let c = cells(&n);
+
+        // Top edge is 40 wide and its source 16, so two whole tiles and half of one
+        assert_eq!(c[1], ((8., 32., 24., 24.), (0.25, 1., 0.75, 0.75)));
+        assert_eq!(c[2], ((24., 32., 40., 24.), (0.25, 1., 0.75, 0.75)));
+        assert_eq!(c[3], ((40., 32., 48., 24.), (0.25, 1., 0.5, 0.75)));
+        assert_eq!(c[4].0, (48., 32., 56., 24.));
+        // Corners and the stretched center aren't tiled
+        assert_eq!(c.len(), 13);
+    }
+
+    #[test]
+    fn tiny_border_scale_caps_the_tile_count() {
+        let mut n = slice(Rectf::new(0., 0., 100., 100.));
+        n.set_border_scale(1e-6).set_edge_mode(SliceMode::Tile).set_center_mode(SliceMode::Tile);
+
+        let c = cells(&n);
+        let limit = MAX_TILE_SPANS as usize;
+        assert!(c.len() <= 4 + 4 * limit + limit * limit);
+        // Tiles still reach the far side of the rect
+        let right = c.iter().map(|(p, _)| p.2).fold(0., f32::max);
+        assert!((right - 100.).abs() < 1e-3);
+    }
+
+    #[test]
+    fn border_only_without_center() {
+        let mut n = slice(Rectf::new(0., 0., 100., 50.));
+        n.vertices();
+        n.set_fill_center(false);
+        assert!(n.dirty.get());
+
+        let c = cells(&n);
+        assert_eq!(c.len(), 8);
+        let covers_center = c.iter().any(|((l, t, r, b), _)| *l < 50. && *r > 50. && *t > 25. && *b < 25.);
+        assert!(!covers_center);
+    }
+}
@@ -70,6 +70,18 @@ pub(crate) fn gl_delete_buffer(id: u32) {
     }
 }
 
+#[allow(dead_code)]
+pub(crate) fn gl_bind_frame_buffer(id: u32) {
+    unsafe { opengl().BindFramebuffer(gl::FRAMEBUFFER, id) }
+}
+
+#[allow(dead_code)]
+pub(crate) fn gl_gen_renderbuffer() -> u32 {
+    let mut id = unsafe { std::mem::zeroed() };
+    unsafe { opengl().GenRenderbuffers(1, &mut id) };
+    id
+}
+
 #[allow(dead_code)]
 pub(crate) fn gl_gen_texture() -> u32 {
     let mut id = unsafe { std::mem::zeroed() };
@@ -77,6 +89,13 @@ pub(crate) fn gl_gen_texture() -> u32 {
     id
 }
 
+#[allow(dead_code)]
+pub(crate) fn gl_get_max_texture_image_units() -> u32 {
+    let mut count = 0;
+    unsafe { opengl().GetIntegerv(gl::MAX_TEXTURE_IMAGE_UNITS, &mut count) };
+    count as u32
+}
+
 #[allow(dead_code)]
 pub(crate) fn gl_get_uniform_location(shader_id: u32, name: &str) -> i32 {
     unsafe { opengl().GetUniformLocation(shader_id, name.as_ptr() as *const _) }
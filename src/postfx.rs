@@ -0,0 +1,513 @@
+use crate::Renderer;
+use crate::graphics::*;
+use crate::state::*;
+use nalgebra_glm as glm;
+use std::rc::Rc;
+
+/**
+ * Render state for full screen passes, output is completely overwritten by each pass
+*/
+pub const POST_RENDER_STATE: RenderState = RenderState {
+    blend: BlendMode::Replace,
+    depth_test: false,
+    depth_write: false,
+    cull_face: None,
+    scissor: None,
+    color_mask: ColorMask::all(),
+};
+
+pub trait PostEffect {
+    /**
+     * Reads input and writes the result into output. time is seconds passed into PostProcess::end()
+    */
+    fn apply(&mut self, renderer: &mut Renderer, input: &Texture, output: &RenderTexture, time: f32);
+
+    /**
+     * Called when the PostProcess is resized, for effects that own their own render targets
+    */
+    fn resize(&mut self, _width: u32, _height: u32) -> Result<(), String> { Ok(()) }
+
+    fn enabled(&self) -> bool { true }
+}
+
+/**
+ * Full screen fragment effect written against Shader::from_frag_template()'s effect() convention.
+ * The input texture is bound to u_texture on unit 0, extra textures in the material should use units 1 and up.
+ * u_resolution (output size in pixels) and u_time are set when the shader declares them.
+*/
+pub struct ShaderEffect {
+    pub enabled: bool,
+    pub material: Material,
+}
+
+impl ShaderEffect {
+    pub fn new(shader: Shader) -> Self {
+        ShaderEffect { enabled: true, material: Material::new(Rc::new(shader)) }
+    }
+
+    pub fn from_effect(effect: &[u8]) -> Result<Self, String> {
+        Ok(ShaderEffect::new(Shader::from_frag_template(effect)?))
+    }
+
+    pub fn set_uniform(&mut self, name: &str, value: UniformValue) -> &mut Self {
+        self.material.set_uniform(name, value);
+        self
+    }
+
+    pub fn grayscale() -> Result<Self, String> {
+        ShaderEffect::from_effect(GRAYSCALE_EFFECT)
+    }
+
+    /**
+     * radius and softness are in uv units measured from the center of the screen
+    */
+    pub fn vignette(radius: f32, softness: f32) -> Result<Self, String> {
+        let mut e = ShaderEffect::from_effect(VIGNETTE_EFFECT)?;
+        e.set_uniform("u_radius", UniformValue::Float(radius))
+            .set_uniform("u_softness", UniformValue::Float(softness));
+        Ok(e)
+    }
+
+    /**
+     * lut is a 2D strip of lut_size slices laid out horizontally (e.g. 256x16 for a 16^3 LUT),
+     * with red along x inside each slice, green along y and blue selecting the slice
+    */
+    pub fn color_grade(lut: Rc<Texture>, lut_size: f32) -> Result<Self, String> {
+        let mut e = ShaderEffect::from_effect(COLOR_GRADE_EFFECT)?;
+        e.set_uniform("u_lut_size", UniformValue::Float(lut_size));
+        e.material.set_texture("u_lut", 1, lut);
+        Ok(e)
+    }
+
+    /**
+     * line_count scanlines over the height of the screen, intensity 0..1 controls how dark they get
+    */
+    pub fn crt_scanlines(line_count: f32, intensity: f32) -> Result<Self, String> {
+        let mut e = ShaderEffect::from_effect(CRT_SCANLINES_EFFECT)?;
+        e.set_uniform("u_line_count", UniformValue::Float(line_count))
+            .set_uniform("u_intensity", UniformValue::Float(intensity));
+        Ok(e)
+    }
+
+    /**
+     * amount is the red/blue channel offset in pixels at the edges of the screen
+    */
+    pub fn chromatic_aberration(amount: f32) -> Result<Self, String> {
+        let mut e = ShaderEffect::from_effect(CHROMATIC_ABERRATION_EFFECT)?;
+        e.set_uniform("u_amount", UniformValue::Float(amount));
+        Ok(e)
+    }
+
+    pub fn pixelate(pixel_size: f32) -> Result<Self, String> {
+        let mut e = ShaderEffect::from_effect(PIXELATE_EFFECT)?;
+        e.set_uniform("u_pixel_size", UniformValue::Float(pixel_size));
+        Ok(e)
+    }
+}
+
+impl PostEffect for ShaderEffect {
+    fn apply(&mut self, renderer: &mut Renderer, input: &Texture, output: &RenderTexture, time: f32) {
+        let shader = self.material.shader.clone().expect("ShaderEffect has no shader");
+        self.material
+            .set_uniform("u_time", UniformValue::Float(time))
+            .set_uniform("u_resolution", UniformValue::Vec2(glm::vec2(output.width() as f32, output.height() as f32)));
+
+        let material = &self.material;
+        renderer.with_target(output, |r| {
+            r.with_render_state(POST_RENDER_STATE, |r| {
+                material.apply_to(&shader);
+                r.draw_fullscreen_quad(&shader, input);
+            });
+        });
+    }
+
+    fn enabled(&self) -> bool { self.enabled }
+}
+
+/**
+ * Renders the scene into an internal RenderTexture between begin() and end(), then runs
+ * each enabled effect in order, ping-ponging between two targets, and draws the result
+ * to whatever target was bound before begin()
+*/
+pub struct PostProcess {
+    scene: RenderTexture,
+    ping_pong: [RenderTexture; 2],
+    effects: Vec<Box<dyn PostEffect>>,
+}
+
+impl PostProcess {
+    pub fn new(width: u32, height: u32) -> Result<Self, String> {
+        PostProcess::with_format(width, height, ColorFormat::Rgba8, 0)
+    }
+
+    /**
+     * Float formats keep HDR values through the chain, samples > 0 multisamples the scene target
+    */
+    pub fn with_format(width: u32, height: u32, format: ColorFormat, samples: u32) -> Result<Self, String> {
+        let scene = RenderTexture::with_desc(RenderTextureDesc {
+            colors: vec![format], samples, ..RenderTextureDesc::new(width, height)
+        })?;
+        let pass_desc = RenderTextureDesc {
+            colors: vec![format], depth_stencil: DepthStencil::None, ..RenderTextureDesc::new(width, height)
+        };
+        let ping_pong = [RenderTexture::with_desc(pass_desc.clone())?, RenderTexture::with_desc(pass_desc)?];
+
+        Ok(PostProcess { scene, ping_pong, effects: Vec::new() })
+    }
+
+    pub fn add_effect<E>(&mut self, effect: E) -> &mut Self where E: PostEffect + 'static {
+        self.effects.push(Box::new(effect));
+        self
+    }
+
+    pub fn effects(&self) -> &[Box<dyn PostEffect>] { &self.effects }
+    pub fn effects_mut(&mut self) -> &mut Vec<Box<dyn PostEffect>> { &mut self.effects }
+
+    pub fn scene(&self) -> &RenderTexture { &self.scene }
+
+    /**
+     * Resizes internal targets and every effect, e.g. when the window is resized
+    */
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<(), String> {
+        self.scene.resize(width, height)?;
+        for t in self.ping_pong.iter_mut() {
+            t.resize(width, height)?;
+        }
+        for e in self.effects.iter_mut() {
+            e.resize(width, height)?;
+        }
+        Ok(())
+    }
+
+    /**
+     * Starts drawing the scene into the internal target
+    */
+    pub fn begin(&self, renderer: &mut Renderer) {
+        renderer.push_target(&self.scene);
+    }
+
+    /**
+     * Finishes the scene, runs the effect chain and draws the result to the previous target
+    */
+    pub fn end(&mut self, renderer: &mut Renderer, time: f32) {
+        renderer.flush();
+        self.scene.resolve();
+        renderer.pop_target();
+
+        let mut input = self.scene.texture();
+        let mut next = 0;
+        for effect in self.effects.iter_mut().filter(|e| e.enabled()) {
+            let output = &self.ping_pong[next];
+            effect.apply(renderer, input, output, time);
+            input = output.texture();
+            next = 1 - next;
+        }
+
+        renderer.with_render_state(renderer.clipped_state(POST_RENDER_STATE), |r| {
+            r.draw_fullscreen_texture(input);
+        });
+    }
+}
+
+/**
+ * Separable gaussian blur, run as a horizontal then vertical pass per iteration on targets
+ * downscaled from the input size. radius is the number of texels sampled on each side of the center
+*/
+pub struct GaussianBlur {
+    pub radius: u32,
+    pub iterations: u32,
+    pub enabled: bool,
+    downscale: u32,
+    shader: Shader,
+    targets: [RenderTexture; 2],
+}
+
+impl GaussianBlur {
+    /**
+     * width and height are full resolution, targets are allocated at size / downscale 
+     * (1 = full, 2 = half, 4 = quarter) in a float format
+    */
+    pub fn new(width: u32, height: u32, downscale: u32, radius: u32, iterations: u32) -> Result<Self, String> {
+        let downscale = downscale.max(1);
+        Ok(GaussianBlur {
+            radius, iterations, enabled: true, downscale,
+            shader: Shader::from_frag_template(GAUSSIAN_BLUR_EFFECT)?,
+            targets: GaussianBlur::create_targets(width, height, downscale)?,
+        })
+    }
+
+    fn create_targets(width: u32, height: u32, downscale: u32) -> Result<[RenderTexture; 2], String> {
+        let desc = RenderTextureDesc {
+            colors: vec![ColorFormat::Rgba16F],
+            depth_stencil: DepthStencil::None,
+            ..RenderTextureDesc::new((width / downscale).max(1), (height / downscale).max(1))
+        };
+        Ok([RenderTexture::with_desc(desc.clone())?, RenderTexture::with_desc(desc)?])
+    }
+
+    /**
+     * Blurs input, returning the texture holding the result. The result is owned by the 
+     * blur and overwritten on the next call. With 0 iterations input is returned as is
+    */
+    pub fn blur<'a>(&'a mut self, renderer: &mut Renderer, input: &'a Texture) -> &'a Texture {
+        if self.iterations == 0 {
+            return input;
+        }
+        let shader = &self.shader;
+        let radius = self.radius as i32;
+        let pass = |r: &mut Renderer, src: &Texture, dst: &RenderTexture, direction: glm::Vec2| {
+            r.with_target(dst, |r| {
+                r.with_render_state(POST_RENDER_STATE, |r| {
+                    // The compiler is free to strip unused uniforms, so only set the ones still there
+                    if shader.has_uniform("u_radius") { shader.set_uniform_i("u_radius", radius) }
+                    if shader.has_uniform("u_direction") { shader.set_uniform_2f("u_direction", (direction.x, direction.y)) }
+                    if shader.has_uniform("u_texel") { shader.set_uniform_2f("u_texel", (1. / src.size.x as f32, 1. / src.size.y as f32)) }
+                    r.draw_fullscreen_quad(shader, src);
+                });
+            });
+        };
+
+        let [a, b] = &self.targets;
+        pass(renderer, input, a, glm::vec2(1., 0.));
+        pass(renderer, a.texture(), b, glm::vec2(0., 1.));
+        for _ in 1..self.iterations {
+            pass(renderer, b.texture(), a, glm::vec2(1., 0.));
+            pass(renderer, a.texture(), b, glm::vec2(0., 1.));
+        }
+        b.texture()
+    }
+}
+
+impl PostEffect for GaussianBlur {
+    fn apply(&mut self, renderer: &mut Renderer, input: &Texture, output: &RenderTexture, _time: f32) {
+        let blurred = self.blur(renderer, input);
+        renderer.with_target(output, |r| {
+            r.with_render_state(POST_RENDER_STATE, |r| r.draw_fullscreen_texture(blurred));
+        });
+    }
+
+    fn resize(&mut self, width: u32, height: u32) -> Result<(), String> {
+        self.targets = GaussianBlur::create_targets(width, height, self.downscale)?;
+        Ok(())
+    }
+
+    fn enabled(&self) -> bool { self.enabled }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct BloomSettings {
+    /**
+     * Brightness above which pixels start to glow
+    */
+    pub threshold: f32,
+    pub intensity: f32,
+    pub radius: u32,
+    pub iterations: u32,
+    /**
+     * 2 for half resolution, 4 for quarter
+    */
+    pub downscale: u32,
+}
+
+impl Default for BloomSettings {
+    fn default() -> Self {
+        BloomSettings { threshold: 0.8, intensity: 1., radius: 8, iterations: 2, downscale: 2 }
+    }
+}
+
+/**
+ * Bright pass into a downscaled float target, gaussian blur, then additive composite
+ * of the blurred highlights back onto the input
+*/
+pub struct Bloom {
+    pub threshold: f32,
+    pub intensity: f32,
+    pub enabled: bool,
+    pub blur: GaussianBlur,
+    bright_shader: Shader,
+    composite_shader: Shader,
+    bright_target: RenderTexture,
+}
+
+impl Bloom {
+    pub fn new(width: u32, height: u32, settings: BloomSettings) -> Result<Self, String> {
+        let downscale = settings.downscale.max(1);
+        Ok(Bloom {
+            threshold: settings.threshold,
+            intensity: settings.intensity,
+            enabled: true,
+            blur: GaussianBlur::new(width, height, downscale, settings.radius, settings.iterations)?,
+            bright_shader: Shader::from_frag_template(BRIGHT_PASS_EFFECT)?,
+            composite_shader: Shader::from_frag_template(BLOOM_COMPOSITE_EFFECT)?,
+            bright_target: Bloom::create_target(width, height, downscale)?,
+        })
+    }
+
+    fn create_target(width: u32, height: u32, downscale: u32) -> Result<RenderTexture, String> {
+        RenderTexture::with_desc(RenderTextureDesc {
+            colors: vec![ColorFormat::Rgba16F],
+            depth_stencil: DepthStencil::None,
+            ..RenderTextureDesc::new((width / downscale).max(1), (height / downscale).max(1))
+        })
+    }
+}
+
+impl PostEffect for Bloom {
+    fn apply(&mut self, renderer: &mut Renderer, input: &Texture, output: &RenderTexture, _time: f32) {
+        let (bright_shader, threshold) = (&self.bright_shader, self.threshold);
+        renderer.with_target(&self.bright_target, |r| {
+            r.with_render_state(POST_RENDER_STATE, |r| {
+                if bright_shader.has_uniform("u_threshold") { bright_shader.set_uniform_f("u_threshold", threshold) }
+                r.draw_fullscreen_quad(bright_shader, input);
+            });
+        });
+
+        let bloom = self.blur.blur(renderer, self.bright_target.texture());
+
+        let (composite, intensity) = (&self.composite_shader, self.intensity);
+        renderer.with_target(output, |r| {
+            r.with_render_state(POST_RENDER_STATE, |r| {
+                if composite.has_uniform("u_intensity") { composite.set_uniform_f("u_intensity", intensity) }
+                if composite.has_uniform("u_bloom") { composite.set_uniform_i("u_bloom", 1) }
+                bloom.apply_to_unit(1);
+                r.draw_fullscreen_quad(composite, input);
+            });
+        });
+    }
+
+    fn resize(&mut self, width: u32, height: u32) -> Result<(), String> {
+        self.bright_target = Bloom::create_target(width, height, self.blur.downscale)?;
+        self.blur.resize(width, height)
+    }
+
+    fn enabled(&self) -> bool { self.enabled }
+}
+
+const GRAYSCALE_EFFECT: &[u8] = b"
+vec4 effect(vec4 color, sampler2D tex, vec2 uv, vec3 pos)
+{
+    vec4 c = texture(tex, uv) * color;
+    float l = dot(c.rgb, vec3(0.2126, 0.7152, 0.0722));
+    return vec4(vec3(l), c.a);
+}
+\0";
+
+const VIGNETTE_EFFECT: &[u8] = b"
+uniform float u_radius;
+uniform float u_softness;
+
+vec4 effect(vec4 color, sampler2D tex, vec2 uv, vec3 pos)
+{
+    vec4 c = texture(tex, uv) * color;
+    float d = distance(uv, vec2(0.5));
+    float v = smoothstep(u_radius, u_radius - u_softness, d);
+    return vec4(c.rgb * v, c.a);
+}
+\0";
+
+const COLOR_GRADE_EFFECT: &[u8] = b"
+uniform sampler2D u_lut;
+uniform float u_lut_size;
+
+vec3 lut_sample(float slice, vec2 rg)
+{
+    vec2 texel = vec2(1.0 / (u_lut_size * u_lut_size), 1.0 / u_lut_size);
+    vec2 lut_uv = vec2((slice + rg.x * (u_lut_size - 1.0) / u_lut_size) / u_lut_size + texel.x * 0.5,
+                       rg.y * (u_lut_size - 1.0) / u_lut_size + texel.y * 0.5);
+    return texture(u_lut, lut_uv).rgb;
+}
+
+vec4 effect(vec4 color, sampler2D tex, vec2 uv, vec3 pos)
+{
+    vec4 c = clamp(texture(tex, uv) * color, 0.0, 1.0);
+    float b = c.b * (u_lut_size - 1.0);
+    float lo = floor(b);
+    float hi = min(lo + 1.0, u_lut_size - 1.0);
+    vec3 graded = mix(lut_sample(lo, c.rg), lut_sample(hi, c.rg), b - lo);
+    return vec4(graded, c.a);
+}
+\0";
+
+const CRT_SCANLINES_EFFECT: &[u8] = b"
+uniform float u_line_count;
+uniform float u_intensity;
+uniform float u_time;
+
+vec4 effect(vec4 color, sampler2D tex, vec2 uv, vec3 pos)
+{
+    vec4 c = texture(tex, uv) * color;
+    float line = sin((uv.y * u_line_count + u_time * 2.0) * 3.14159265);
+    float scan = 1.0 - u_intensity * (0.5 - 0.5 * line);
+    return vec4(c.rgb * scan, c.a);
+}
+\0";
+
+const CHROMATIC_ABERRATION_EFFECT: &[u8] = b"
+uniform float u_amount;
+uniform vec2 u_resolution;
+
+vec4 effect(vec4 color, sampler2D tex, vec2 uv, vec3 pos)
+{
+    vec2 dir = (uv - vec2(0.5)) * 2.0;
+    vec2 offset = dir * u_amount / u_resolution;
+    float r = texture(tex, uv + offset).r;
+    vec4 g = texture(tex, uv);
+    float b = texture(tex, uv - offset).b;
+    return vec4(r, g.g, b, g.a) * color;
+}
+\0";
+
+const PIXELATE_EFFECT: &[u8] = b"
+uniform float u_pixel_size;
+uniform vec2 u_resolution;
+
+vec4 effect(vec4 color, sampler2D tex, vec2 uv, vec3 pos)
+{
+    vec2 cell = u_pixel_size / u_resolution;
+    vec2 snapped = (floor(uv / cell) + 0.5) * cell;
+    return texture(tex, snapped) * color;
+}
+\0";
+
+const GAUSSIAN_BLUR_EFFECT: &[u8] = b"
+uniform int u_radius;
+uniform vec2 u_direction;
+uniform vec2 u_texel;
+
+vec4 effect(vec4 color, sampler2D tex, vec2 uv, vec3 pos)
+{
+    float sigma = max(float(u_radius) / 2.0, 0.0001);
+    vec4 sum = vec4(0.0);
+    float total = 0.0;
+    for (int i = -u_radius; i <= u_radius; i++) {
+        float w = exp(-float(i * i) / (2.0 * sigma * sigma));
+        sum += texture(tex, uv + u_direction * u_texel * float(i)) * w;
+        total += w;
+    }
+    return sum / total;
+}
+\0";
+
+const BRIGHT_PASS_EFFECT: &[u8] = b"
+uniform float u_threshold;
+
+vec4 effect(vec4 color, sampler2D tex, vec2 uv, vec3 pos)
+{
+    vec4 c = texture(tex, uv) * color;
+    float brightness = max(c.r, max(c.g, c.b));
+    float contribution = max(brightness - u_threshold, 0.0) / max(brightness, 0.0001);
+    return vec4(c.rgb * contribution, 1.0);
+}
+\0";
+
+const BLOOM_COMPOSITE_EFFECT: &[u8] = b"
+uniform sampler2D u_bloom;
+uniform float u_intensity;
+
+vec4 effect(vec4 color, sampler2D tex, vec2 uv, vec3 pos)
+{
+    vec4 scene = texture(tex, uv) * color;
+    vec3 bloom = texture(u_bloom, uv).rgb * u_intensity;
+    return vec4(scene.rgb + bloom, scene.a);
+}
+\0";
@@ -3,17 +3,38 @@ use crate::opengl::gl_draw_arrays;
 use crate::opengl::gl_unbind_element_buffer;
 use crate::opengl::gl_draw_elements;
 pub use nalgebra_glm as glm;
+use std::cell::{Cell, RefCell};
 
 pub mod sys;
 pub mod opengl;
 pub mod buffers;
 pub mod graphics;
 pub mod vertex;
+pub mod state;
+pub mod postfx;
+pub mod screen;
+pub mod shapes;
+pub mod svg;
+pub mod font;
+pub mod ttf;
+pub mod atlas;
+pub mod spritesheet;
+pub mod sprite;
+pub mod nineslice;
+pub mod tilemap;
+pub mod tiled;
+pub mod ldtk;
+pub mod animation;
+pub mod tween;
 
 use sys::*;
 use buffers::*;
 use graphics::*;
 use vertex::*;
+use state::*;
+use shapes::{StrokeStyle, DEFAULT_CIRCLE_SEGMENTS};
+use font::{Font, TextAlign, TextEffects};
+use ttf::{TtfFont, TextStyle, TextLayout};
 use opengl::{opengl, gl};
 
 const CLIP_NEAR_DEFAULT: f32 = 0.1;
@@ -38,17 +59,53 @@ impl ProjectionInfo {
     }
 }
 
+/**
+ * Renderer state saved by push_target() and restored by pop_target()
+*/
+struct TargetFrame {
+    frame_buffer: u32,
+    viewport: Rectf,
+    projection_info: ProjectionInfo,
+    camera: Option<FlyCamera>,
+    clip_rects: Vec<Recti>,
+    clip_mask_depth: u32,
+}
+
+/**
+ * World space triangles queued by draw_triangles() that share a texture and matrices,
+ * drawn together by Renderer::flush()
+*/
+struct TriangleBatch {
+    verts: Vec<Vertex2D>,
+    texture: u32,
+    projection: glm::Mat4,
+    view: glm::Mat4,
+}
+
 pub struct Renderer {
     pub camera: FlyCamera,
 
     draw_vao: VAO,
     quad_buffer: VertexBuffer,
+    triangle_buffer: RefCell<VertexBuffer>,
     instanced_mat_buffer: VertexBuffer,
 
     shader: Shader,
     instanced_shader: Shader,
+    blit_shader: Shader,
+    sdf_shader: Shader,
+    batch_shader: Shader,
+    batch: RefCell<TriangleBatch>,
     
     default_texture: Texture,
+    max_texture_units: u32,
+    state: Cell<RenderState>,
+    clip_rects: RefCell<Vec<Recti>>,
+    clip_mask_depth: Cell<u32>,
+    drawing_mask: Cell<bool>,
+    viewport: Rectf,
+    frame_buffer: u32,
+    target_stack: Vec<TargetFrame>,
     projection: glm::Mat4,
     projection_info: ProjectionInfo,
 }
@@ -62,15 +119,17 @@ impl Renderer {
     pub const U_PROJECTION: &'static str = "u_projection";
     pub const U_VIEW: &'static str = "u_view";
     pub const U_MODEL: &'static str = "u_model";
+    /**
+     * Initial size of the buffer draw_triangles() batches are drawn from, grows as needed
+    */
+    pub const TRIANGLE_BUFFER_VERTS: u32 = 1024;
+    pub const ROUNDED_CORNER_SEGMENTS: u32 = 8;
 
     pub fn new(width: u32, height: u32) -> Self {     
-        unsafe {
-            let gl = opengl();
-            gl.Viewport(0, 0, width as i32, height as i32);
-            gl.Enable(gl::DEPTH_TEST);
-            gl.Enable(gl::BLEND);
-            gl.BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
-        };
+        unsafe { opengl().Viewport(0, 0, width as i32, height as i32) };
+        let state = RenderState::default();
+        state.apply();
+
         let projection_info = ProjectionInfo { 
             width: width as f32, height: height as f32, 
             fov_deg: Self::DEFAULT_FOV, clip_near: 0.1, clip_far: 100. 
@@ -87,42 +146,309 @@ impl Renderer {
 
         let shader = Shader::default();
         let instanced_shader = Shader::default_instanced();
+        let blit_shader = Shader::blit();
+        let sdf_shader = Shader::sdf_text();
+        // Own copy of the default shader, so batches never touch the u_model set by use_default_shader()
+        let batch_shader = Shader::default();
         let default_texture = Texture::new_blank();
+        let max_texture_units = opengl::gl_get_max_texture_image_units();
 
         let quad_buffer = VertexBuffer::new(&sys::Quad::default_verts(), DrawUsage::Dynamic);
+        let triangle_buffer = RefCell::new(VertexBuffer::zeroed::<Vertex2D>(Self::TRIANGLE_BUFFER_VERTS, DrawUsage::Dynamic, DrawPrimitive::Triangles));
         let instanced_mat_buffer = VertexBuffer::zeroed::<glm::Mat4>(2, DrawUsage::Dynamic, DrawPrimitive::Triangles);
 
         let draw_vao = VAO::new();
 
         Renderer { 
-            camera, draw_vao, quad_buffer,
+            camera, draw_vao, quad_buffer, triangle_buffer,
             instanced_mat_buffer, shader, 
-            instanced_shader,
-            default_texture, projection,
-            projection_info
+            instanced_shader, blit_shader, sdf_shader, batch_shader,
+            batch: RefCell::new(TriangleBatch { 
+                verts: Vec::new(), texture: 0, projection, view: glm::Mat4::identity() 
+            }),
+            default_texture, max_texture_units, 
+            state: Cell::new(state),
+            clip_rects: RefCell::new(Vec::new()),
+            clip_mask_depth: Cell::new(0),
+            drawing_mask: Cell::new(false),
+            viewport: Rectf::new(0., 0., width as f32, height as f32),
+            frame_buffer: 0,
+            target_stack: Vec::new(),
+            projection, projection_info
         }
     }
 
     pub fn set_viewport(&mut self, rect: &Rectf) {
+        self.flush();
         self.projection_info.width = rect.w;
         self.projection_info.height = rect.h;
         self.projection = self.projection_info.to_matrix();
+        self.viewport = *rect;
         unsafe { opengl().Viewport(rect.x as i32, rect.y as i32, rect.w as i32, rect.h as i32) }
     }
 
+    pub fn viewport(&self) -> &Rectf { &self.viewport }
+
+    /**
+     * Draws f into rect of the current framebuffer. The viewport and the whole projection,
+     * including any fov or clip planes set by the caller, are put back afterwards
+    */
+    pub fn with_viewport<F>(&mut self, rect: &Rectf, f: F) where F: FnOnce(&mut Self) {
+        let (prev_viewport, prev_projection) = (self.viewport, self.projection_info);
+        self.set_viewport(rect);
+        f(self);
+        self.set_viewport(&prev_viewport);
+        self.projection_info = prev_projection;
+        self.projection = self.projection_info.to_matrix();
+    }
+
+    /**
+     * Clips all following draws to rect (in world space, transformed by camera and projection)
+     * until the matching pop_clip_rect(). Nested rects are intersected with their parent.
+    */
+    pub fn push_clip_rect(&self, rect: Rectf) {
+        let window_rect = self.world_to_window_rect(&rect);
+        let clipped = match self.clip_rects.borrow().last() {
+            Some(parent) => window_rect.intersection(parent).unwrap_or(Recti::new(0, 0, 0, 0)),
+            None => window_rect
+        };
+        self.clip_rects.borrow_mut().push(clipped);
+        self.set_render_state(RenderState { scissor: Some(clipped), ..self.state.get() });
+    }
+
+    pub fn pop_clip_rect(&self) {
+        let mut rects = self.clip_rects.borrow_mut();
+        assert!(rects.pop().is_some(), "pop_clip_rect() called without matching push_clip_rect()");
+        let scissor = rects.last().copied();
+        drop(rects);
+        self.set_render_state(RenderState { scissor, ..self.state.get() });
+    }
+
+    /**
+     * Clips all following draws to the shapes drawn inside f, until the matching pop_clip_mask().
+     * Masks nest, each one further restricting its parent. Requires a stencil buffer on the current
+     * framebuffer (RenderTexture has one, the window needs one requested on context creation)
+    */
+    pub fn push_clip_mask<F>(&self, f: F) where F: FnOnce(&Self) {
+        self.flush();
+        let depth = self.clip_mask_depth.get();
+        let prev = self.state.get();
+
+        unsafe {
+            let gl = opengl();
+            gl.Enable(gl::STENCIL_TEST);
+            gl.StencilMask(0xFF);
+            // Only pixels already inside parent masks get bumped up to the new depth
+            gl.StencilFunc(gl::EQUAL, depth as i32, 0xFF);
+            gl.StencilOp(gl::KEEP, gl::KEEP, gl::INCR);
+        }
+
+        self.set_render_state(RenderState { color_mask: ColorMask::none(), depth_test: false, depth_write: false, ..prev });
+        let was_drawing_mask = self.drawing_mask.replace(true);
+        f(self);
+        self.drawing_mask.set(was_drawing_mask);
+        self.set_render_state(prev);
+
+        self.clip_mask_depth.set(depth + 1);
+        unsafe {
+            let gl = opengl();
+            gl.StencilFunc(gl::EQUAL, (depth + 1) as i32, 0xFF);
+            gl.StencilOp(gl::KEEP, gl::KEEP, gl::KEEP);
+        }
+    }
+
+    pub fn pop_clip_mask(&self) {
+        self.flush();
+        let depth = self.clip_mask_depth.get();
+        assert!(depth > 0, "pop_clip_mask() called without matching push_clip_mask()");
+        let parent = depth - 1;
+        let prev = self.state.get();
+
+        // Knock everything written by this mask back down to the parent's depth
+        unsafe {
+            let gl = opengl();
+            gl.StencilFunc(gl::LESS, parent as i32, 0xFF);
+            gl.StencilOp(gl::KEEP, gl::KEEP, gl::REPLACE);
+        }
+        self.set_render_state(RenderState { color_mask: ColorMask::none(), depth_test: false, depth_write: false, scissor: None, ..prev });
+        self.draw_fullscreen_texture(None);
+        self.set_render_state(prev);
+
+        self.clip_mask_depth.set(parent);
+        unsafe {
+            let gl = opengl();
+            gl.StencilOp(gl::KEEP, gl::KEEP, gl::KEEP);
+            if parent == 0 {
+                gl.Disable(gl::STENCIL_TEST);
+            } else {
+                gl.StencilFunc(gl::EQUAL, parent as i32, 0xFF);
+            }
+        }
+    }
+
+    /**
+     * Draws a quad covering the whole viewport with shader, ignoring camera and projection.
+     * shader's matrices are overwritten, except for the default shader which is swapped for the
+     * blit shader so its matrices stay as they were. The default shader is bound again afterwards
+    */
+    pub fn draw_fullscreen_quad<'b, T>(&self, shader: &Shader, texture: T) where T: Into<Option<&'b Texture>> {
+        self.flush();
+        let shader = if std::ptr::eq(shader, &self.shader) { &self.blit_shader } else { shader };
+        let identity = glm::Mat4::identity();
+        let model = Transform::from_scale(glm::vec2(2., 2.));
+        shader.apply();
+        if shader.has_uniform(Self::U_PROJECTION) { shader.set_uniform_matrix(Self::U_PROJECTION, &identity) }
+        if shader.has_uniform(Self::U_VIEW) { shader.set_uniform_matrix(Self::U_VIEW, &identity) }
+        if shader.has_uniform(Self::U_MODEL) { shader.set_uniform_matrix(Self::U_MODEL, model.model()) }
+
+        texture.into().unwrap_or(&self.default_texture).apply_to_unit(0);
+
+        self.draw_vao.set_buffer_layout(&self.quad_buffer);
+        self.quad_buffer.write(&Quad::default_verts(), 0);
+        self.draw_arrays(0, self.quad_buffer.vert_count(), DrawPrimitive::TriangleStrip);
+        self.shader.apply();
+    }
+
+    /**
+     * Copies texture (or plain white) over the whole viewport with the blit shader
+    */
+    pub fn draw_fullscreen_texture<'b, T>(&self, texture: T) where T: Into<Option<&'b Texture>> {
+        self.draw_fullscreen_quad(&self.blit_shader, texture);
+    }
+
+    /**
+     * Projects world space rect through camera and projection, returning its bounds
+     * in window pixels (origin bottom left)
+    */
+    pub fn world_to_window_rect(&self, rect: &Rectf) -> Recti {
+        let mvp = self.projection * self.camera.view();
+        let corners = [
+            glm::vec4(rect.x, rect.y, 0., 1.),
+            glm::vec4(rect.right(), rect.y, 0., 1.),
+            glm::vec4(rect.x, rect.bottom(), 0., 1.),
+            glm::vec4(rect.right(), rect.bottom(), 0., 1.),
+        ];
+
+        let (mut min, mut max) = (glm::vec2(f32::MAX, f32::MAX), glm::vec2(f32::MIN, f32::MIN));
+        for c in corners.iter() {
+            let clip = mvp * c;
+            let ndc = glm::vec2(clip.x / clip.w, clip.y / clip.w);
+            let px = glm::vec2(
+                self.viewport.x + (ndc.x + 1.) * 0.5 * self.viewport.w,
+                self.viewport.y + (ndc.y + 1.) * 0.5 * self.viewport.h
+            );
+            min = glm::min2(&min, &px);
+            max = glm::max2(&max, &px);
+        }
+
+        Recti::new(min.x.floor() as i32, min.y.floor() as i32, 
+            (max.x - min.x).ceil() as i32, (max.y - min.y).ceil() as i32)
+    }
+
+    /**
+     * World space area on the z = 0 plane seen through the viewport, for culling 2D content
+    */
+    pub fn visible_world_rect(&self) -> Rectf {
+        let inverse = glm::inverse(&(self.projection * self.camera.view()));
+        let unproject = |x: f32, y: f32, z: f32| {
+            let p = inverse * glm::vec4(x, y, z, 1.);
+            glm::vec3(p.x / p.w, p.y / p.w, p.z / p.w)
+        };
+
+        let (mut min, mut max) = (glm::vec2(f32::MAX, f32::MAX), glm::vec2(f32::MIN, f32::MIN));
+        for (x, y) in [(-1., -1.), (1., -1.), (-1., 1.), (1., 1.)].iter() {
+            let (near, far) = (unproject(*x, *y, -1.), unproject(*x, *y, 1.));
+            // Where the corner's ray crosses z = 0, orthographic rays all hit it at the same depth
+            let dz = far.z - near.z;
+            let t = if dz.abs() > f32::EPSILON { -near.z / dz } else { 0. };
+            let p = near + (far - near) * t;
+            min = glm::min2(&min, &glm::vec2(p.x, p.y));
+            max = glm::max2(&max, &glm::vec2(p.x, p.y));
+        }
+        Rectf::new(min.x, min.y, max.x - min.x, max.y - min.y)
+    }
+
     // NOTE :: Maybe add a way to set near and far clip here as well?
     pub fn set_projection(&mut self, width: f32, height: f32, fov_deg: f32) {
+        self.flush();
         self.projection_info = ProjectionInfo {
             width, height, fov_deg, ..self.projection_info
         };
         self.projection = self.projection_info.to_matrix();
     }
     
-    pub fn begin_draw_texture(rt: &RenderTexture) {
-        FrameBuffer::apply(&rt.frame_buffer);
+    /**
+     * Redirects all following draws into rt until the matching pop_target(). Viewport and projection 
+     * are sized to rt, and clip rects and clip masks are cleared, all of which are restored on pop. 
+     * Multisampled targets need flush() and then RenderTexture::resolve() before pop_target()
+    */
+    pub fn push_target(&mut self, rt: &RenderTexture) {
+        self.flush();
+        let frame = TargetFrame {
+            frame_buffer: self.frame_buffer,
+            viewport: self.viewport,
+            projection_info: self.projection_info,
+            camera: None,
+            clip_rects: self.clip_rects.replace(Vec::new()),
+            clip_mask_depth: self.clip_mask_depth.replace(0),
+        };
+        self.target_stack.push(frame);
+
+        self.frame_buffer = rt.draw_frame_buffer().id();
+        FrameBuffer::apply(rt.draw_frame_buffer());
+        self.set_viewport(&Rectf::new(0., 0., rt.width() as f32, rt.height() as f32));
+        self.set_render_state(RenderState { scissor: None, ..self.state.get() });
+        // rt's stencil holds none of the current masks, testing against them would discard everything
+        unsafe { opengl().Disable(gl::STENCIL_TEST) }
+    }
+
+    /**
+     * Same as push_target(), but draws with camera until pop_target() puts the previous one back
+    */
+    pub fn push_target_with_camera(&mut self, rt: &RenderTexture, camera: FlyCamera) {
+        self.push_target(rt);
+        let prev = std::mem::replace(&mut self.camera, camera);
+        self.target_stack.last_mut().unwrap().camera = Some(prev);
+    }
+
+    pub fn pop_target(&mut self) {
+        self.flush();
+        let frame = self.target_stack.pop().expect("pop_target() called without matching push_target()");
+
+        self.frame_buffer = frame.frame_buffer;
+        opengl::gl_bind_frame_buffer(frame.frame_buffer);
+        self.set_viewport(&frame.viewport);
+        self.projection_info = frame.projection_info;
+        self.projection = self.projection_info.to_matrix();
+
+        if let Some(camera) = frame.camera {
+            self.camera = camera;
+        }
+
+        let scissor = frame.clip_rects.last().copied();
+        self.clip_rects.replace(frame.clip_rects);
+        self.set_render_state(RenderState { scissor, ..self.state.get() });
+
+        self.clip_mask_depth.set(frame.clip_mask_depth);
+        if frame.clip_mask_depth > 0 {
+            unsafe {
+                let gl = opengl();
+                gl.Enable(gl::STENCIL_TEST);
+                gl.StencilFunc(gl::EQUAL, frame.clip_mask_depth as i32, 0xFF);
+                gl.StencilOp(gl::KEEP, gl::KEEP, gl::KEEP);
+            }
+        }
     }
-    pub fn end_draw_texture() {
-        FrameBuffer::unbind();
+
+    /**
+     * Draws everything in f into rt, resolving multisampled targets afterwards
+    */
+    pub fn with_target<F>(&mut self, rt: &RenderTexture, f: F) where F: FnOnce(&mut Self) {
+        self.push_target(rt);
+        f(self);
+        self.flush();
+        rt.resolve();
+        self.pop_target();
     }
 
     pub fn draw<T>(&self, renderable: &T) where T: Renderable {
@@ -130,32 +456,47 @@ impl Renderer {
     }
 
     pub fn draw_mesh(&self, mesh: &Mesh) {
-        let shader = match mesh.shader.as_ref() {
-            Some(s) => s,
-            None => {
-                self.shader.set_uniform_matrix("u_projection", &self.projection);
-                self.shader.set_uniform_matrix("u_view", &self.camera.view());
-                self.shader.set_uniform_matrix("u_model", mesh.transform.model());
-                &self.shader
-            }
-        };
+        self.flush();
+        if let Some(material) = mesh.material.as_ref() {
+            self.apply_material(material, mesh.transform.model());
+        } else {
+            let shader = mesh.shader.as_ref().unwrap_or(&self.shader);
+            self.set_matrices(shader, mesh.transform.model());
+            shader.apply();
 
-        shader.apply();
+            let texture = mesh.texture.as_ref().unwrap_or(&self.default_texture);
+            texture.apply();
+        }
 
         self.draw_vao.set_buffer_layout(&mesh.buffer);
 
-        let texture = mesh.texture.as_ref().unwrap_or(&self.default_texture);
-        texture.apply();
-
         if let Some(e) = mesh.indices.as_ref() {
             self.draw_elements(&e, mesh.buffer.draw_prim);
         } else {
             self.draw_arrays(0, mesh.buffer.vert_count(), mesh.buffer.draw_prim);
         }
 
+        if mesh.material.is_some() {
+            self.restore_state();
+        }
+    }
+
+    /**
+     * Draws quad with material's shader, uniforms, textures and state. Quad verts are
+     * expected to already be in world space, so u_model is set to identity
+    */
+    pub fn draw_quad_with_material(&self, q: &Quad, material: &Material) {
+        self.apply_material(material, &glm::Mat4::identity());
+
+        self.draw_vao.set_buffer_layout(&self.quad_buffer);
+        self.quad_buffer.write(&q.verts, 0);
+        self.draw_arrays(0, self.quad_buffer.vert_count(), DrawPrimitive::TriangleStrip);
+
+        self.restore_state();
     }
 
     pub fn draw_quad<'b, T>(&self, q: &Quad, texture: T) where T: Into<Option<&'b Texture>> {
+        self.flush();
         let texture = texture.into().unwrap_or(&self.default_texture);
         texture.apply();
 
@@ -166,7 +507,237 @@ impl Renderer {
         self.draw_arrays(0, self.quad_buffer.vert_count(), DrawPrimitive::TriangleStrip);
     }
 
+    /**
+     * Draws quad with several textures bound at once, e.g. [("u_texture", &diffuse), ("u_normal", &normal)].
+     * Textures are assigned units in order starting at 0 and each sampler uniform is set to its unit.
+     * Uses the default shader if none is given
+    */
+    pub fn draw_quad_textured<'b, S>(&self, q: &Quad, textures: &[(&str, &Texture)], shader: S) where S: Into<Option<&'b Shader>> {
+        let shader = shader.into().unwrap_or(&self.shader);
+        self.bind_textures(shader, textures);
+
+        self.draw_vao.set_buffer_layout(&self.quad_buffer);
+        self.quad_buffer.write(&q.verts, 0);
+        self.draw_arrays(0, self.quad_buffer.vert_count(), DrawPrimitive::TriangleStrip);
+    }
+
+    /**
+     * Binds each texture to its own texture unit, starting at 0, and points the matching
+     * sampler uniform on shader at it. Samplers the shader does not declare are skipped
+    */
+    pub fn bind_textures(&self, shader: &Shader, textures: &[(&str, &Texture)]) {
+        self.flush();
+        assert!(textures.len() as u32 <= self.max_texture_units, 
+            "Tried to bind {} textures, but only {} texture units are available", textures.len(), self.max_texture_units);
+
+        shader.apply();
+        for (unit, (sampler, texture)) in textures.iter().enumerate() {
+            texture.apply_to_unit(unit as u32);
+            if shader.has_uniform(sampler) {
+                shader.set_uniform_i(sampler, unit as i32);
+            }
+        }
+    }
+
+    /**
+     * Number of texture units available to the fragment shader (GL_MAX_TEXTURE_IMAGE_UNITS)
+    */
+    pub fn max_texture_units(&self) -> u32 { self.max_texture_units }
+
+    /**
+     * Queues triangle list (3 verts per triangle) in world space, drawn with a copy of the default 
+     * shader using the renderer's projection and view and an identity u_model. Consecutive calls with 
+     * the same texture and camera go out as a single draw call, see flush()
+    */
+    pub fn draw_triangles<'b, T>(&self, verts: &[Vertex2D], texture: T) where T: Into<Option<&'b Texture>> {
+        if verts.is_empty() {
+            return;
+        }
+        let texture = texture.into().unwrap_or(&self.default_texture).id();
+        let view = self.camera.view();
+
+        let changed = {
+            let batch = self.batch.borrow();
+            !batch.verts.is_empty() && (batch.texture != texture || batch.projection != self.projection || batch.view != view)
+        };
+        if changed {
+            self.flush();
+        }
+
+        let mut batch = self.batch.borrow_mut();
+        batch.texture = texture;
+        batch.projection = self.projection;
+        batch.view = view;
+        batch.verts.extend_from_slice(verts);
+    }
+
+    /**
+     * Same as draw_triangles() with shader, whose matrices are set the same way when it declares them.
+     * Drawn right away rather than batched, unless shader is the default shader. The default shader 
+     * is bound again afterwards
+    */
+    pub fn draw_triangles_with_shader<'b, T>(&self, shader: &Shader, verts: &[Vertex2D], texture: T) where T: Into<Option<&'b Texture>> {
+        if std::ptr::eq(shader, &self.shader) {
+            self.draw_triangles(verts, texture);
+            return;
+        }
+        if verts.is_empty() {
+            return;
+        }
+        self.flush();
+        self.set_matrices(shader, &glm::Mat4::identity());
+        shader.apply();
+
+        texture.into().unwrap_or(&self.default_texture).apply();
+
+        self.upload_triangles(verts);
+        self.draw_arrays(0, verts.len() as u32, DrawPrimitive::Triangles);
+        self.shader.apply();
+    }
+
+    /**
+     * Draws the triangles queued by draw_triangles() and everything built on it (shapes, text). Other 
+     * draws and any state, clip, viewport, projection or target change made through the renderer flush 
+     * first on their own. Call it once at the end of the frame before swapping buffers, and before 
+     * drawing or changing GL state without going through the renderer
+    */
+    pub fn flush(&self) {
+        let mut batch = self.batch.borrow_mut();
+        if batch.verts.is_empty() {
+            return;
+        }
+
+        // Whatever program the caller bound directly (e.g. before draw_quad()) is put back afterwards
+        let mut prev_program = 0;
+        unsafe { opengl().GetIntegerv(gl::CURRENT_PROGRAM, &mut prev_program) };
+
+        let shader = &self.batch_shader;
+        shader.apply();
+        shader.set_uniform_matrix(Self::U_PROJECTION, &batch.projection);
+        shader.set_uniform_matrix(Self::U_VIEW, &batch.view);
+        shader.set_uniform_matrix(Self::U_MODEL, &glm::Mat4::identity());
+        unsafe {
+            opengl().ActiveTexture(gl::TEXTURE0);
+            opengl().BindTexture(gl::TEXTURE_2D, batch.texture);
+        }
+
+        self.upload_triangles(&batch.verts);
+        self.draw_arrays(0, batch.verts.len() as u32, DrawPrimitive::Triangles);
+        batch.verts.clear();
+
+        unsafe { opengl().UseProgram(prev_program as u32) };
+    }
+
+    pub fn draw_triangle(&self, a: glm::Vec2, b: glm::Vec2, c: glm::Vec2, color: &glm::Vec4) {
+        self.draw_triangles(&shapes::triangle(a, b, c, color), None);
+    }
+
+    pub fn draw_rect(&self, rect: &Rectf, color: &glm::Vec4) {
+        self.draw_triangles(&shapes::rect(rect, color), None);
+    }
+
+    pub fn draw_rect_outline(&self, rect: &Rectf, thickness: f32, color: &glm::Vec4) {
+        self.draw_triangles(&shapes::rect_outline(rect, thickness, color), None);
+    }
+
+    pub fn draw_rounded_rect(&self, rect: &Rectf, radius: f32, color: &glm::Vec4) {
+        self.draw_triangles(&shapes::rounded_rect(rect, radius, Self::ROUNDED_CORNER_SEGMENTS, color), None);
+    }
+
+    pub fn draw_circle(&self, center: glm::Vec2, radius: f32, color: &glm::Vec4) {
+        self.draw_triangles(&shapes::circle(center, radius, DEFAULT_CIRCLE_SEGMENTS, color), None);
+    }
+
+    pub fn draw_ellipse(&self, center: glm::Vec2, radii: glm::Vec2, color: &glm::Vec4) {
+        self.draw_triangles(&shapes::ellipse(center, radii, DEFAULT_CIRCLE_SEGMENTS, color), None);
+    }
+
+    pub fn draw_line(&self, a: glm::Vec2, b: glm::Vec2, thickness: f32, color: &glm::Vec4) {
+        self.draw_triangles(&shapes::line(a, b, thickness, color), None);
+    }
+
+    pub fn draw_polyline(&self, points: &[glm::Vec2], closed: bool, style: &StrokeStyle, color: &glm::Vec4) {
+        self.draw_triangles(&shapes::polyline(points, closed, style, color), None);
+    }
+
+    /**
+     * Fills simple polygon, convex or concave
+    */
+    pub fn draw_polygon(&self, points: &[glm::Vec2], color: &glm::Vec4) {
+        self.draw_triangles(&shapes::polygon(points, color), None);
+    }
+
+    /**
+     * Stroked arc from start to end angle in degrees, counter clockwise
+    */
+    pub fn draw_arc(&self, center: glm::Vec2, radius: f32, start_degrees: f32, end_degrees: f32, thickness: f32, color: &glm::Vec4) {
+        let sweep = (end_degrees - start_degrees).abs() / 360.;
+        let segments = ((DEFAULT_CIRCLE_SEGMENTS as f32 * sweep).ceil() as u32).max(1);
+        self.draw_triangles(&shapes::arc(center, radius, start_degrees, end_degrees, segments, &StrokeStyle::new(thickness), color), None);
+    }
+
+    /**
+     * Draws left aligned text with pos at the top left of the first line, see draw_text_aligned()
+    */
+    pub fn draw_text(&self, font: &Font, text: &str, pos: glm::Vec2, color: &glm::Vec4) {
+        self.draw_text_aligned(font, text, pos, TextAlign::Left, color);
+    }
+
+    /**
+     * Draws text split on '\n', with kerning applied. pos.y is the top of the first line and pos.x is
+     * the left edge, center or right edge of every line depending on align. One draw per font page
+    */
+    pub fn draw_text_aligned(&self, font: &Font, text: &str, pos: glm::Vec2, align: TextAlign, color: &glm::Vec4) {
+        for (page, verts) in font.layout_triangles(text, pos, align, color).iter().enumerate() {
+            self.draw_triangles(verts, &font.pages[page]);
+        }
+    }
+
+    /**
+     * Lays out and draws text from a TrueType font, see TtfFont::layout()
+    */
+    pub fn draw_ttf_text(&self, font: &TtfFont, text: &str, pos: glm::Vec2, style: &TextStyle) -> Result<(), String> {
+        let layout = font.layout(text, pos, style)?;
+        self.draw_text_layout(font, &layout);
+        Ok(())
+    }
+
+    /**
+     * Draws text previously laid out with font, useful when the same text is drawn every frame
+    */
+    pub fn draw_text_layout(&self, font: &TtfFont, layout: &TextLayout) {
+        let atlas = font.atlas();
+        self.draw_triangles(&layout.triangles(atlas.size()), atlas.texture());
+    }
+
+    /**
+     * Draws text from a distance field BMFont (e.g. generated by msdf-bmfont) with effects,
+     * fonts without a distanceField entry are treated as single channel SDF
+    */
+    pub fn draw_text_sdf(&self, font: &Font, text: &str, pos: glm::Vec2, align: TextAlign, color: &glm::Vec4, effects: &TextEffects) {
+        let multichannel = font.desc.distance_field.map(|d| d.field_type.is_multichannel()).unwrap_or(false);
+        let shader = self.sdf_shader(effects, multichannel);
+        for (page, verts) in font.layout_triangles(text, pos, align, color).iter().enumerate() {
+            self.draw_triangles_with_shader(shader, verts, &font.pages[page]);
+        }
+    }
+
+    /**
+     * Lays out and draws text from a TrueType font loaded with SdfSettings
+    */
+    pub fn draw_ttf_text_sdf(&self, font: &TtfFont, text: &str, pos: glm::Vec2, style: &TextStyle, effects: &TextEffects) -> Result<(), String> {
+        let layout = font.layout(text, pos, style)?;
+        self.draw_text_layout_sdf(font, &layout, effects);
+        Ok(())
+    }
+
+    pub fn draw_text_layout_sdf(&self, font: &TtfFont, layout: &TextLayout, effects: &TextEffects) {
+        let atlas = font.atlas();
+        self.draw_triangles_with_shader(self.sdf_shader(effects, false), &layout.triangles(atlas.size()), atlas.texture());
+    }
+
     pub fn draw_buffer<'b, T>(&self, buffer: &VertexBuffer, first_vertex: u32, texture: T) where T: Into<Option<&'b Texture>> {
+        self.flush();
         let texture = texture.into().unwrap_or(&self.default_texture);
         texture.apply();
 
@@ -177,6 +748,7 @@ impl Renderer {
     }
 
     pub fn draw_indexed_buffer<'b, T>(&self, buffer: &VertexBuffer, ebo: &ElementBuffer, texture: T) where T: Into<Option<&'b Texture>> {
+        self.flush();
         let texture = texture.into().unwrap_or(&self.default_texture);
         texture.apply();
 
@@ -190,6 +762,7 @@ impl Renderer {
     }
 
     pub fn use_default_shader<'b, T>(&self, xform: T) where T: Into<Option<&'b Transform>> {
+        self.flush();
         if let Some(xform) = xform.into() {
             self.shader.set_uniform_matrix("u_projection", &self.projection);
             self.shader.set_uniform_matrix("u_view", &self.camera.view());
@@ -205,12 +778,82 @@ impl Renderer {
     }
 
     pub fn clear(&self, r: f32, g: f32, b: f32, a: f32) {
+        self.flush();
         unsafe {
             opengl().ClearColor(r, g, b, a);
-            opengl().Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+            opengl().StencilMask(0xFF);
+            opengl().Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT);
+        }
+    }
+
+    /**
+     * Applies material's shader (or default shader), uniforms, textures and render state.
+     * Returns the shader that was applied
+    */
+    pub fn apply_material<'m>(&'m self, material: &'m Material, model: &glm::Mat4) -> &'m Shader {
+        self.flush();
+        let shader = material.shader.as_deref().unwrap_or(&self.shader);
+        self.set_matrices(shader, model);
+
+        assert!(material.textures.iter().all(|b| b.unit < self.max_texture_units), 
+            "Material binds a texture unit past GL_MAX_TEXTURE_IMAGE_UNITS ({})", self.max_texture_units);
+
+        // Material textures take over unit 0, make sure something sane is bound there
+        // if the material does not bind u_texture itself
+        self.default_texture.apply_to_unit(0);
+        material.apply_to(shader);
+        if let Some(state) = material.state {
+            self.clipped_state(state).apply();
         }
+        shader
+    }
+
+    /**
+     * state with the renderer's current clip applied: the active clip rect's scissor and,
+     * while drawing a clip mask, color writes turned off. Use it for states that replace the
+     * current one for a few draws, so they don't escape push_clip_rect() or push_clip_mask()
+    */
+    pub fn clipped_state(&self, state: RenderState) -> RenderState {
+        let current = self.state.get();
+        let color_mask = if self.drawing_mask.get() { current.color_mask } else { state.color_mask };
+        RenderState { scissor: current.scissor, color_mask, ..state }
     }
 
+    /**
+     * Sets render state used for all following draws
+    */
+    pub fn set_render_state(&self, state: RenderState) {
+        self.flush();
+        state.apply();
+        self.state.set(state);
+    }
+
+    pub fn render_state(&self) -> RenderState { self.state.get() }
+
+    pub fn set_blend_mode(&self, blend: BlendMode) {
+        self.set_render_state(RenderState { blend, ..self.state.get() });
+    }
+
+    /**
+     * Applies state only for draws made inside f, then restores the previous state
+    */
+    pub fn with_render_state<F>(&self, state: RenderState, f: F) where F: FnOnce(&Self) {
+        let prev = self.state.get();
+        self.set_render_state(state);
+        f(self);
+        self.set_render_state(prev);
+    }
+
+    /**
+     * Re-applies renderer's current state, undoing any changes made by materials 
+     * or direct OpenGL calls
+    */
+    pub fn restore_state(&self) {
+        self.flush();
+        self.state.get().apply();
+    }
+
+    pub fn default_shader(&self) -> &Shader { &self.shader }
     pub fn projection(&self) -> &glm::Mat4 { &self.projection }
     pub fn view(&self) -> glm::Mat4 { self.camera.view() }
     
@@ -221,6 +864,42 @@ impl Renderer {
         self.draw_vao.apply();
     }
     
+    /**
+     * Sets u_projection, u_view and u_model on shader, skipping any the shader does not declare
+    */
+    fn set_matrices(&self, shader: &Shader, model: &glm::Mat4) {
+        if shader.has_uniform(Self::U_PROJECTION) {
+            shader.set_uniform_matrix(Self::U_PROJECTION, &self.projection);
+        }
+        if shader.has_uniform(Self::U_VIEW) {
+            shader.set_uniform_matrix(Self::U_VIEW, &self.camera.view());
+        }
+        if shader.has_uniform(Self::U_MODEL) {
+            shader.set_uniform_matrix(Self::U_MODEL, model);
+        }
+    }
+
+    /**
+     * Distance field text shader with effects' uniforms set
+    */
+    fn sdf_shader(&self, effects: &TextEffects, multichannel: bool) -> &Shader {
+        effects.apply_to(&self.sdf_shader, multichannel);
+        &self.sdf_shader
+    }
+
+    /**
+     * Writes verts to the triangle buffer, growing it if needed, and lays it out on the draw VAO
+    */
+    fn upload_triangles(&self, verts: &[Vertex2D]) {
+        let mut buffer = self.triangle_buffer.borrow_mut();
+        if verts.len() as u32 > buffer.vert_count() {
+            buffer.alloc(verts, DrawUsage::Dynamic);
+        } else {
+            buffer.write(verts, 0);
+        }
+        self.draw_vao.set_buffer_layout(&buffer);
+    }
+
     fn draw_arrays(&self, start: u32, vert_count: u32, prim: DrawPrimitive) {
         self.draw_vao.apply();
         gl_draw_arrays(start, vert_count, prim);        
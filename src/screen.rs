@@ -0,0 +1,209 @@
+use crate::Renderer;
+use crate::graphics::*;
+use crate::postfx::POST_RENDER_STATE;
+use crate::sys::Rectf;
+use nalgebra_glm as glm;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ScalePolicy {
+    /**
+     * Largest whole number scale that fits the window, remaining space is letterboxed
+    */
+    IntegerScale,
+    /**
+     * Largest scale that fits the window keeping aspect ratio, letterboxed on one axis
+    */
+    Fit,
+    /**
+     * Smallest scale that covers the window keeping aspect ratio, cropping on one axis
+    */
+    Fill,
+    /**
+     * Covers the window exactly, ignoring aspect ratio
+    */
+    Stretch,
+}
+
+/**
+ * Where logical resolution ends up inside the window for the given policy, in window pixels
+ * with origin at bottom left (same as glViewport). Fill can return a rect larger than the window
+*/
+pub fn scaled_viewport(logical: glm::Vec2, window: glm::Vec2, policy: ScalePolicy) -> Rectf {
+    let ratio = glm::vec2(window.x / logical.x, window.y / logical.y);
+    let scale = match policy {
+        ScalePolicy::IntegerScale => ratio.x.min(ratio.y).floor().max(1.),
+        ScalePolicy::Fit => ratio.x.min(ratio.y),
+        ScalePolicy::Fill => ratio.x.max(ratio.y),
+        ScalePolicy::Stretch => return Rectf::new(0., 0., window.x, window.y)
+    };
+
+    // Whole pixels, otherwise float error in the matching axis can push the offset a pixel off the window
+    let size = (logical * scale).map(|v| v.round());
+    let (x, y) = (((window.x - size.x) * 0.5).floor(), ((window.y - size.y) * 0.5).floor());
+    Rectf::new(x, y, size.x, size.y)
+}
+
+/**
+ * Fixed logical resolution (e.g. 320x180) drawn into an offscreen target between begin() and end(),
+ * then scaled up to the window. The target uses nearest filtering so pixel art stays crisp
+*/
+pub struct VirtualScreen {
+    pub policy: ScalePolicy,
+    /**
+     * Color of the letterbox bars
+    */
+    pub bar_color: glm::Vec4,
+    target: RenderTexture,
+    window_size: glm::Vec2,
+}
+
+impl VirtualScreen {
+    pub fn new(logical_width: u32, logical_height: u32, window_width: u32, window_height: u32, policy: ScalePolicy) -> Result<Self, String> {
+        let target = RenderTexture::with_desc(RenderTextureDesc::new(logical_width, logical_height))?;
+        target.texture().set_filter(TextureFilter::Nearest, TextureFilter::Nearest);
+
+        Ok(VirtualScreen {
+            policy,
+            bar_color: glm::vec4(0., 0., 0., 1.),
+            target,
+            window_size: glm::vec2(window_width as f32, window_height as f32),
+        })
+    }
+
+    pub fn set_window_size(&mut self, width: u32, height: u32) {
+        self.window_size = glm::vec2(width as f32, height as f32);
+    }
+
+    pub fn logical_size(&self) -> glm::Vec2 {
+        glm::vec2(self.target.width() as f32, self.target.height() as f32)
+    }
+
+    pub fn target(&self) -> &RenderTexture { &self.target }
+
+    /**
+     * Area of the window the logical screen is drawn to, see scaled_viewport()
+    */
+    pub fn viewport(&self) -> Rectf {
+        scaled_viewport(self.logical_size(), self.window_size, self.policy)
+    }
+
+    /**
+     * Starts drawing at logical resolution
+    */
+    pub fn begin(&self, renderer: &mut Renderer) {
+        renderer.push_target(&self.target);
+    }
+
+    /**
+     * Draws the logical screen scaled into the window, clearing the bars with bar_color
+    */
+    pub fn end(&self, renderer: &mut Renderer) {
+        renderer.flush();
+        self.target.resolve();
+        renderer.pop_target();
+
+        let c = self.bar_color;
+        renderer.clear(c.x, c.y, c.z, c.w);
+
+        renderer.with_viewport(&self.viewport(), |r| {
+            r.with_render_state(r.clipped_state(POST_RENDER_STATE), |r| {
+                r.draw_fullscreen_texture(self.target.texture());
+            });
+        });
+    }
+
+    /**
+     * Maps window coordinates (origin top left, as reported by window mouse events) to logical
+     * coordinates (origin top left). None if the point is inside the letterbox bars
+    */
+    pub fn window_to_logical(&self, window_pos: glm::Vec2) -> Option<glm::Vec2> {
+        viewport_to_logical(self.viewport(), self.logical_size(), self.window_size, window_pos)
+    }
+
+    /**
+     * Maps logical coordinates (origin top left) to window coordinates (origin top left)
+    */
+    pub fn logical_to_window(&self, logical_pos: glm::Vec2) -> glm::Vec2 {
+        logical_to_viewport(self.viewport(), self.logical_size(), self.window_size, logical_pos)
+    }
+}
+
+fn viewport_to_logical(vp: Rectf, logical: glm::Vec2, window: glm::Vec2, window_pos: glm::Vec2) -> Option<glm::Vec2> {
+    // Viewport is bottom left origin, flip it to match window coordinates
+    let top = window.y - vp.y - vp.h;
+
+    let x = (window_pos.x - vp.x) / vp.w * logical.x;
+    let y = (window_pos.y - top) / vp.h * logical.y;
+
+    if x >= 0. && x < logical.x && y >= 0. && y < logical.y {
+        Some(glm::vec2(x, y))
+    } else {
+        None
+    }
+}
+
+fn logical_to_viewport(vp: Rectf, logical: glm::Vec2, window: glm::Vec2, logical_pos: glm::Vec2) -> glm::Vec2 {
+    let top = window.y - vp.y - vp.h;
+    glm::vec2(vp.x + logical_pos.x / logical.x * vp.w, top + logical_pos.y / logical.y * vp.h)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOGICAL: (f32, f32) = (320., 180.);
+
+    fn viewport(window: (f32, f32), policy: ScalePolicy) -> Rectf {
+        scaled_viewport(glm::vec2(LOGICAL.0, LOGICAL.1), glm::vec2(window.0, window.1), policy)
+    }
+
+    fn to_logical(window: (f32, f32), policy: ScalePolicy, x: f32, y: f32) -> Option<glm::Vec2> {
+        let (logical, window) = (glm::vec2(LOGICAL.0, LOGICAL.1), glm::vec2(window.0, window.1));
+        viewport_to_logical(scaled_viewport(logical, window, policy), logical, window, glm::vec2(x, y))
+    }
+
+    fn rect(r: Rectf) -> (f32, f32, f32, f32) { (r.x, r.y, r.w, r.h) }
+
+    #[test]
+    fn integer_scale_letterboxes_at_whole_scales() {
+        assert_eq!(rect(viewport((1000., 600.), ScalePolicy::IntegerScale)), (20., 30., 960., 540.));
+        // Never below 1, even when the window is smaller than the logical screen
+        assert_eq!(rect(viewport((200., 100.), ScalePolicy::IntegerScale)), (-60., -40., 320., 180.));
+
+        assert_eq!(to_logical((1000., 600.), ScalePolicy::IntegerScale, 20., 30.), Some(glm::vec2(0., 0.)));
+        assert_eq!(to_logical((1000., 600.), ScalePolicy::IntegerScale, 500., 300.), Some(glm::vec2(160., 90.)));
+        assert_eq!(to_logical((1000., 600.), ScalePolicy::IntegerScale, 10., 300.), None);
+    }
+
+    #[test]
+    fn fit_keeps_aspect_inside_the_window() {
+        assert_eq!(rect(viewport((1280., 800.), ScalePolicy::Fit)), (0., 40., 1280., 720.));
+        assert_eq!(to_logical((1280., 800.), ScalePolicy::Fit, 640., 40.), Some(glm::vec2(160., 0.)));
+        assert_eq!(to_logical((1280., 800.), ScalePolicy::Fit, 640., 20.), None);
+        assert_eq!(to_logical((1280., 800.), ScalePolicy::Fit, 640., 770.), None);
+    }
+
+    #[test]
+    fn fill_covers_the_window_and_crops() {
+        assert_eq!(rect(viewport((1280., 800.), ScalePolicy::Fill)), (-71., 0., 1422., 800.));
+        let p = to_logical((1280., 800.), ScalePolicy::Fill, 0., 0.).unwrap();
+        assert!((p.x - 71. / 1422. * 320.).abs() < 1e-3 && p.y == 0.);
+    }
+
+    #[test]
+    fn stretch_ignores_aspect() {
+        assert_eq!(rect(viewport((640., 720.), ScalePolicy::Stretch)), (0., 0., 640., 720.));
+        assert_eq!(to_logical((640., 720.), ScalePolicy::Stretch, 320., 360.), Some(glm::vec2(160., 90.)));
+    }
+
+    #[test]
+    fn logical_to_window_round_trips() {
+        let (logical, window) = (glm::vec2(LOGICAL.0, LOGICAL.1), glm::vec2(1000., 600.));
+        for policy in [ScalePolicy::IntegerScale, ScalePolicy::Fit, ScalePolicy::Fill, ScalePolicy::Stretch] {
+            let vp = scaled_viewport(logical, window, policy);
+            let w = logical_to_viewport(vp, logical, window, glm::vec2(100., 50.));
+            let back = viewport_to_logical(vp, logical, window, w).unwrap();
+            assert!((back - glm::vec2(100., 50.)).norm() < 1e-3, "{:?}", policy);
+        }
+    }
+}
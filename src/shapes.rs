@@ -0,0 +1,151 @@
+use crate::sys::{Rectf, radians, TWO_PI, PI, HALF_PI};
+use crate::vertex::{self, Vertex2D};
+use nalgebra_glm as glm;
+
+pub use crate::vertex::{LineJoin, LineCap, StrokeStyle};
+
+/**
+ * Segment count used for full circles when none is given
+*/
+pub const DEFAULT_CIRCLE_SEGMENTS: u32 = 32;
+
+/*
+ * All functions below build triangle lists (3 verts per triangle) ready for Renderer::draw_triangles()
+*/
+
+pub fn triangle(a: glm::Vec2, b: glm::Vec2, c: glm::Vec2, color: &glm::Vec4) -> Vec<Vertex2D> {
+    vec![Vertex2D::colored(a, color), Vertex2D::colored(b, color), Vertex2D::colored(c, color)]
+}
+
+pub fn rect(r: &Rectf, color: &glm::Vec4) -> Vec<Vertex2D> {
+    let mut out = Vec::with_capacity(6);
+    push_quad(&mut out,
+        glm::vec2(r.x, r.y), glm::vec2(r.right(), r.y),
+        glm::vec2(r.right(), r.bottom()), glm::vec2(r.x, r.bottom()), color);
+    out
+}
+
+/**
+ * Outline drawn on the inside of r
+*/
+pub fn rect_outline(r: &Rectf, thickness: f32, color: &glm::Vec4) -> Vec<Vertex2D> {
+    let t = thickness.min(r.w * 0.5).min(r.h * 0.5);
+    let mut out = Vec::with_capacity(24);
+    out.extend(rect(&Rectf::new(r.x, r.y, r.w, t), color));
+    out.extend(rect(&Rectf::new(r.x, r.bottom() - t, r.w, t), color));
+    out.extend(rect(&Rectf::new(r.x, r.y + t, t, r.h - t * 2.), color));
+    out.extend(rect(&Rectf::new(r.right() - t, r.y + t, t, r.h - t * 2.), color));
+    out
+}
+
+pub fn circle(center: glm::Vec2, radius: f32, segments: u32, color: &glm::Vec4) -> Vec<Vertex2D> {
+    ellipse(center, glm::vec2(radius, radius), segments, color)
+}
+
+pub fn ellipse(center: glm::Vec2, radii: glm::Vec2, segments: u32, color: &glm::Vec4) -> Vec<Vertex2D> {
+    let segments = segments.max(3);
+    let ring: Vec<glm::Vec2> = (0..segments)
+        .map(|i| {
+            let a = TWO_PI * i as f32 / segments as f32;
+            center + glm::vec2(a.cos() * radii.x, a.sin() * radii.y)
+        })
+        .collect();
+    fan(center, &ring, true, color)
+}
+
+/**
+ * Fills convex polygon as a triangle fan
+*/
+pub fn convex_polygon(points: &[glm::Vec2], color: &glm::Vec4) -> Vec<Vertex2D> {
+    if points.len() < 3 {
+        return Vec::new();
+    }
+    let mut out = Vec::with_capacity((points.len() - 2) * 3);
+    for i in 1..points.len() - 1 {
+        push_tri(&mut out, points[0], points[i], points[i + 1], color);
+    }
+    out
+}
+
+pub fn rounded_rect(r: &Rectf, radius: f32, segments_per_corner: u32, color: &glm::Vec4) -> Vec<Vertex2D> {
+    convex_polygon(&rounded_rect_points(r, radius, segments_per_corner), color)
+}
+
+/**
+ * Outline of a rounded rect, counter clockwise starting at the bottom right corner
+*/
+pub fn rounded_rect_points(r: &Rectf, radius: f32, segments_per_corner: u32) -> Vec<glm::Vec2> {
+    let radius = radius.max(0.).min(r.w * 0.5).min(r.h * 0.5);
+    let corners = [
+        (glm::vec2(r.right() - radius, r.y + radius), -HALF_PI),
+        (glm::vec2(r.right() - radius, r.bottom() - radius), 0.),
+        (glm::vec2(r.x + radius, r.bottom() - radius), HALF_PI),
+        (glm::vec2(r.x + radius, r.y + radius), PI),
+    ];
+
+    let segments = segments_per_corner.max(1);
+    let mut points = Vec::with_capacity(corners.len() * (segments as usize + 1));
+    for (c, start) in corners.iter() {
+        for i in 0..=segments {
+            let a = start + HALF_PI * i as f32 / segments as f32;
+            points.push(c + glm::vec2(a.cos(), a.sin()) * radius);
+        }
+    }
+    points
+}
+
+pub fn line(a: glm::Vec2, b: glm::Vec2, thickness: f32, color: &glm::Vec4) -> Vec<Vertex2D> {
+    polyline(&[a, b], false, &StrokeStyle::new(thickness), color)
+}
+
+/**
+ * Stroked arc around center from start to end angle (degrees, counter clockwise)
+*/
+pub fn arc(center: glm::Vec2, radius: f32, start_degrees: f32, end_degrees: f32, segments: u32, style: &StrokeStyle, color: &glm::Vec4) -> Vec<Vertex2D> {
+    polyline(&arc_points(center, radius, start_degrees, end_degrees, segments), false, style, color)
+}
+
+pub fn arc_points(center: glm::Vec2, radius: f32, start_degrees: f32, end_degrees: f32, segments: u32) -> Vec<glm::Vec2> {
+    let segments = segments.max(1);
+    let (start, end) = (radians(start_degrees), radians(end_degrees));
+    (0..=segments)
+        .map(|i| {
+            let a = start + (end - start) * i as f32 / segments as f32;
+            center + glm::vec2(a.cos(), a.sin()) * radius
+        })
+        .collect()
+}
+
+/**
+ * Strokes line segments through points with the given joins and caps. Caps are ignored when closed
+*/
+pub fn polyline(points: &[glm::Vec2], closed: bool, style: &StrokeStyle, color: &glm::Vec4) -> Vec<Vertex2D> {
+    vertex::stroke_polyline(points, closed, style, color).to_triangle_list()
+}
+
+/**
+ * Fills simple polygon, convex or concave
+*/
+pub fn polygon(points: &[glm::Vec2], color: &glm::Vec4) -> Vec<Vertex2D> {
+    vertex::triangulate_polygon(points, color).to_triangle_list()
+}
+
+fn fan(center: glm::Vec2, ring: &[glm::Vec2], closed: bool, color: &glm::Vec4) -> Vec<Vertex2D> {
+    let count = if closed { ring.len() } else { ring.len().saturating_sub(1) };
+    let mut out = Vec::with_capacity(count * 3);
+    for i in 0..count {
+        push_tri(&mut out, center, ring[i], ring[(i + 1) % ring.len()], color);
+    }
+    out
+}
+
+fn push_tri(out: &mut Vec<Vertex2D>, a: glm::Vec2, b: glm::Vec2, c: glm::Vec2, color: &glm::Vec4) {
+    out.push(Vertex2D::colored(a, color));
+    out.push(Vertex2D::colored(b, color));
+    out.push(Vertex2D::colored(c, color));
+}
+
+fn push_quad(out: &mut Vec<Vertex2D>, a: glm::Vec2, b: glm::Vec2, c: glm::Vec2, d: glm::Vec2, color: &glm::Vec4) {
+    push_tri(out, a, b, c, color);
+    push_tri(out, a, c, d, color);
+}
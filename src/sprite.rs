@@ -0,0 +1,314 @@
+use crate::Renderable;
+use crate::Renderer;
+use crate::graphics::Texture;
+use crate::sys::{Quad, Rectui, Transform};
+use crate::vertex::*;
+use nalgebra_glm as glm;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+/**
+ * Textured quad with its own transform. Size is the source rect's size in pixels times scale.
+ * The quad is rebuilt on draw only when something changed since the last draw
+*/
+pub struct Sprite {
+    texture: Rc<Texture>,
+    source_rect: Rectui,
+    position: glm::Vec2,
+    scale: glm::Vec2,
+    rotation: f32,
+    origin: glm::Vec2,
+    flip_x: bool,
+    flip_y: bool,
+    tint: glm::Vec4,
+    z_order: f32,
+
+    quad: RefCell<Quad>,
+    dirty: Cell<bool>,
+}
+
+impl Sprite {
+    /**
+     * Sprite showing the whole texture
+    */
+    pub fn new(texture: Rc<Texture>) -> Self {
+        let rect = Rectui::new(0, 0, texture.size.x, texture.size.y);
+        Sprite::with_rect(texture, rect)
+    }
+
+    /**
+     * Sprite showing part of the texture, rect is in pixels with origin at the top left
+    */
+    pub fn with_rect(texture: Rc<Texture>, source_rect: Rectui) -> Self {
+        Sprite {
+            texture,
+            source_rect,
+            position: glm::vec2(0., 0.),
+            scale: glm::vec2(1., 1.),
+            rotation: 0.,
+            origin: glm::vec2(0.5, 0.5),
+            flip_x: false,
+            flip_y: false,
+            tint: glm::vec4(1., 1., 1., 1.),
+            z_order: 0.,
+            quad: RefCell::new(Quad::default()),
+            dirty: Cell::new(true),
+        }
+    }
+
+    pub fn texture(&self) -> &Rc<Texture> { &self.texture }
+    pub fn source_rect(&self) -> Rectui { self.source_rect }
+    pub fn position(&self) -> glm::Vec2 { self.position }
+    pub fn scale(&self) -> glm::Vec2 { self.scale }
+    pub fn rotation(&self) -> f32 { self.rotation }
+    pub fn origin(&self) -> glm::Vec2 { self.origin }
+    pub fn flip_x(&self) -> bool { self.flip_x }
+    pub fn flip_y(&self) -> bool { self.flip_y }
+    pub fn tint(&self) -> glm::Vec4 { self.tint }
+    pub fn z_order(&self) -> f32 { self.z_order }
+
+    /**
+     * Size in world units after scaling
+    */
+    pub fn size(&self) -> glm::Vec2 {
+        glm::vec2(self.source_rect.w as f32 * self.scale.x, self.source_rect.h as f32 * self.scale.y)
+    }
+
+    pub fn set_texture(&mut self, texture: Rc<Texture>) -> &mut Self {
+        self.texture = texture;
+        self.invalidate()
+    }
+
+    pub fn set_source_rect(&mut self, rect: Rectui) -> &mut Self {
+        if self.source_rect != rect {
+            self.source_rect = rect;
+            self.invalidate();
+        }
+        self
+    }
+
+    pub fn set_position(&mut self, position: glm::Vec2) -> &mut Self {
+        if self.position != position {
+            self.position = position;
+            self.invalidate();
+        }
+        self
+    }
+
+    pub fn move_by(&mut self, offset: glm::Vec2) -> &mut Self {
+        self.set_position(self.position + offset)
+    }
+
+    pub fn set_scale(&mut self, scale: glm::Vec2) -> &mut Self {
+        if self.scale != scale {
+            self.scale = scale;
+            self.invalidate();
+        }
+        self
+    }
+
+    /**
+     * Degrees, counter clockwise around origin
+    */
+    pub fn set_rotation(&mut self, degrees: f32) -> &mut Self {
+        if self.rotation != degrees {
+            self.rotation = degrees;
+            self.invalidate();
+        }
+        self
+    }
+
+    pub fn rotate_by(&mut self, degrees: f32) -> &mut Self {
+        self.set_rotation(self.rotation + degrees)
+    }
+
+    /**
+     * Point the sprite is positioned, rotated and scaled around, normalized with 0, 0 at the
+     * top left and 1, 1 at the bottom right. Defaults to the center
+    */
+    pub fn set_origin(&mut self, origin: glm::Vec2) -> &mut Self {
+        if self.origin != origin {
+            self.origin = origin;
+            self.invalidate();
+        }
+        self
+    }
+
+    pub fn set_flip(&mut self, flip_x: bool, flip_y: bool) -> &mut Self {
+        if self.flip_x != flip_x || self.flip_y != flip_y {
+            self.flip_x = flip_x;
+            self.flip_y = flip_y;
+            self.invalidate();
+        }
+        self
+    }
+
+    /**
+     * Multiplied with the texture color
+    */
+    pub fn set_tint(&mut self, tint: glm::Vec4) -> &mut Self {
+        if self.tint != tint {
+            self.tint = tint;
+            self.invalidate();
+        }
+        self
+    }
+
+    /**
+     * Written to the quad's z. The default camera sits at z = -3 looking down +z, so with depth
+     * testing on lower values are nearer and draw over higher ones. The projection is perspective,
+     * so z also changes how big the sprite looks on screen
+    */
+    pub fn set_z_order(&mut self, z_order: f32) -> &mut Self {
+        if self.z_order != z_order {
+            self.z_order = z_order;
+            self.invalidate();
+        }
+        self
+    }
+
+    pub fn transform(&self) -> Transform {
+        let mut t = Transform::default();
+        t.translate(glm::vec3(self.position.x, self.position.y, self.z_order))
+            .rotate(self.rotation)
+            .scale(self.size());
+        t
+    }
+
+    /**
+     * World space quad with normalized texture coordinates, rebuilt only when the sprite changed
+    */
+    pub fn quad(&self) -> Quad {
+        if self.dirty.get() {
+            self.quad.replace(self.build_quad());
+            self.dirty.set(false);
+        }
+        *self.quad.borrow()
+    }
+
+    fn invalidate(&mut self) -> &mut Self {
+        self.dirty.set(true);
+        self
+    }
+
+    fn build_quad(&self) -> Quad {
+        let mut verts = Quad::default_verts();
+
+        // Default verts are centered, shift them so origin lands on 0, 0 (y up)
+        let offset = glm::vec2(0.5 - self.origin.x, self.origin.y - 0.5);
+        for v in verts.iter_mut() {
+            v.position.x += offset.x;
+            v.position.y += offset.y;
+        }
+        verts.iter_mut().translate(&self.transform());
+        verts.iter_mut().calc_texture_coords(&self.source_rect);
+        verts.iter_mut().normalize_texture_coords(glm::vec2(self.texture.size.x as f32, self.texture.size.y as f32));
+
+        // Verts are top left, bottom left, top right, bottom right
+        if self.flip_x {
+            swap_uv(&mut verts, 0, 2);
+            swap_uv(&mut verts, 1, 3);
+        }
+        if self.flip_y {
+            swap_uv(&mut verts, 0, 1);
+            swap_uv(&mut verts, 2, 3);
+        }
+        verts.iter_mut().set_color(&self.tint);
+        Quad::with_verts(&verts)
+    }
+}
+
+impl Renderable for Sprite {
+    fn draw(&self, renderer: &Renderer) {
+        renderer.draw_quad(&self.quad(), self.texture.as_ref());
+    }
+}
+
+fn swap_uv(verts: &mut [Vertex2D], a: usize, b: usize) {
+    let tmp = verts[a].text_coord;
+    verts[a].text_coord = verts[b].text_coord;
+    verts[b].text_coord = tmp;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sprite() -> Sprite {
+        Sprite::new(Rc::new(Texture::unloaded(64, 32)))
+    }
+
+    fn positions(q: &Quad) -> Vec<(f32, f32)> {
+        q.verts.iter().map(|v| (v.position.x, v.position.y)).collect()
+    }
+
+    fn uvs(q: &Quad) -> Vec<(f32, f32)> {
+        q.verts.iter().map(|v| (v.text_coord.u, v.text_coord.v)).collect()
+    }
+
+    #[test]
+    fn origin_offsets_the_quad() {
+        let mut s = sprite();
+        s.set_position(glm::vec2(10., 5.));
+        assert_eq!(positions(&s.quad()), vec![(-22., 21.), (-22., -11.), (42., 21.), (42., -11.)]);
+
+        s.set_origin(glm::vec2(0., 0.));
+        assert_eq!(positions(&s.quad()), vec![(10., 5.), (10., -27.), (74., 5.), (74., -27.)]);
+
+        s.set_origin(glm::vec2(1., 1.));
+        assert_eq!(positions(&s.quad()), vec![(-54., 37.), (-54., 5.), (10., 37.), (10., 5.)]);
+    }
+
+    #[test]
+    fn whole_texture_uvs() {
+        assert_eq!(uvs(&sprite().quad()), vec![(0., 1.), (0., 0.), (1., 1.), (1., 0.)]);
+    }
+
+    #[test]
+    fn source_rect_uvs() {
+        let s = Sprite::with_rect(Rc::new(Texture::unloaded(64, 32)), Rectui::new(16, 8, 16, 8));
+        assert_eq!(s.size(), glm::vec2(16., 8.));
+        assert_eq!(uvs(&s.quad()), vec![(0.25, 0.75), (0.25, 0.5), (0.5, 0.75), (0.5, 0.5)]);
+    }
+
+    #[test]
+    fn flips_swap_uvs() {
+        let mut s = Sprite::with_rect(Rc::new(Texture::unloaded(64, 32)), Rectui::new(16, 8, 16, 8));
+
+        s.set_flip(true, false);
+        assert_eq!(uvs(&s.quad()), vec![(0.5, 0.75), (0.5, 0.5), (0.25, 0.75), (0.25, 0.5)]);
+
+        s.set_flip(false, true);
+        assert_eq!(uvs(&s.quad()), vec![(0.25, 0.5), (0.25, 0.75), (0.5, 0.5), (0.5, 0.75)]);
+
+        s.set_flip(true, true);
+        assert_eq!(uvs(&s.quad()), vec![(0.5, 0.5), (0.5, 0.75), (0.25, 0.5), (0.25, 0.75)]);
+    }
+
+    #[test]
+    fn quad_is_cached_until_something_changes() {
+        let mut s = sprite();
+        let first = positions(&s.quad());
+        assert!(!s.dirty.get());
+
+        // Setting the same values again keeps the cached quad
+        s.set_position(glm::vec2(0., 0.)).set_scale(glm::vec2(1., 1.)).set_flip(false, false).set_z_order(0.);
+        assert!(!s.dirty.get());
+
+        s.set_z_order(2.);
+        assert!(s.dirty.get());
+        let q = s.quad();
+        assert!(!s.dirty.get());
+        assert_eq!(positions(&q), first);
+        assert!(q.verts.iter().all(|v| v.position.z == 2.));
+
+        s.set_tint(glm::vec4(1., 0., 0., 1.));
+        assert!(s.dirty.get());
+        assert!(s.quad().verts.iter().all(|v| v.color.g == 0.));
+
+        // Source rect stays in pixels, so it now runs past the narrower texture
+        s.set_texture(Rc::new(Texture::unloaded(32, 32)));
+        assert!(s.dirty.get());
+        assert_eq!(uvs(&s.quad())[2], (2., 1.));
+    }
+}
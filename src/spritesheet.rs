@@ -0,0 +1,416 @@
+use crate::graphics::Texture;
+use crate::sys::{Quad, Rectui, Transform, read_file};
+use crate::vertex::*;
+use nalgebra_glm as glm;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+
+/**
+ * Playback direction of an Aseprite frame tag
+*/
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TagDirection {
+    Forward,
+    Reverse,
+    PingPong,
+    PingPongReverse,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameTag {
+    pub name: String,
+    /**
+     * Inclusive range of frame indices
+    */
+    pub from: usize,
+    pub to: usize,
+    pub direction: TagDirection,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpriteFrame {
+    pub name: String,
+    /**
+     * Area of the texture in pixels, origin top left. When rotated, this is the area as stored
+     * in the texture, so w and h are swapped relative to the sprite
+    */
+    pub rect: Rectui,
+    /**
+     * Stored rotated 90 degrees clockwise in the texture
+    */
+    pub rotated: bool,
+    pub trimmed: bool,
+    /**
+     * Size of the sprite before transparent borders were trimmed
+    */
+    pub source_size: glm::TVec2<u32>,
+    /**
+     * Where the trimmed pixels sit inside the untrimmed sprite
+    */
+    pub source_rect: Rectui,
+    /**
+     * Normalized pivot with 0, 0 at the top left of the untrimmed sprite
+    */
+    pub pivot: glm::Vec2,
+    /**
+     * Seconds, 0 when the exporter doesn't write durations
+    */
+    pub duration: f32,
+}
+
+impl SpriteFrame {
+    /**
+     * Size of the sprite as drawn, after undoing rotation
+    */
+    pub fn size(&self) -> glm::TVec2<u32> {
+        if self.rotated { glm::vec2(self.rect.h, self.rect.w) } else { glm::vec2(self.rect.w, self.rect.h) }
+    }
+}
+
+/**
+ * Frames packed into one texture, loaded from TexturePacker or Aseprite JSON (hash or array).
+ * Frames keep the order they appear in the file, which is what Aseprite frame tags index into
+*/
+pub struct SpriteSheet {
+    texture: Rc<Texture>,
+    frames: Vec<SpriteFrame>,
+    names: HashMap<String, usize>,
+    tags: Vec<FrameTag>,
+}
+
+impl SpriteSheet {
+
+    /**
+     * Loads the JSON and the image named in meta.image, relative to the JSON file
+    */
+    pub fn from_file(filename: &str) -> Result<Self, String> {
+        let json = read_file(filename).map_err(|e| format!("Error loading file: {} :: {}", filename, e))?;
+        let sheet: JsonSheet = serde_json::from_str(&json)
+            .map_err(|e| format!("Error loading file: {} :: SpriteSheetError: {}", filename, e))?;
+
+        let image = sheet.meta.image.as_ref()
+            .ok_or_else(|| format!("Error loading file: {} :: SpriteSheetError: meta.image is missing", filename))?;
+        let dir = Path::new(filename).parent().unwrap_or_else(|| Path::new(""));
+        let texture = Texture::from_file(&dir.join(image).to_string_lossy())?;
+
+        SpriteSheet::from_parsed(sheet, Rc::new(texture))
+            .map_err(|e| format!("Error loading file: {} :: {}", filename, e))
+    }
+
+    /**
+     * Parses JSON for an already loaded texture, meta.image is ignored
+    */
+    pub fn from_json(json: &str, texture: Rc<Texture>) -> Result<Self, String> {
+        let sheet: JsonSheet = serde_json::from_str(json).map_err(|e| format!("SpriteSheetError: {}", e))?;
+        SpriteSheet::from_parsed(sheet, texture)
+    }
+
+    fn from_parsed(sheet: JsonSheet, texture: Rc<Texture>) -> Result<Self, String> {
+        let frames: Vec<(String, JsonFrame)> = match sheet.frames {
+            JsonFrames::Array(frames) => frames.into_iter()
+                .enumerate()
+                .map(|(i, f)| (f.filename.clone().unwrap_or_else(|| i.to_string()), f))
+                .collect(),
+            JsonFrames::Hash(map) => map.into_iter()
+                .map(|(name, v)| serde_json::from_value::<JsonFrame>(v)
+                    .map(|f| (name.clone(), f))
+                    .map_err(|e| format!("SpriteSheetError: frame '{}': {}", name, e)))
+                .collect::<Result<_, _>>()?,
+        };
+
+        let frames: Vec<SpriteFrame> = frames.into_iter().map(|(name, f)| f.into_frame(name)).collect();
+        let mut names = HashMap::with_capacity(frames.len());
+        for (i, f) in frames.iter().enumerate() {
+            if names.insert(f.name.clone(), i).is_some() {
+                return Err(format!("SpriteSheetError: duplicate frame name '{}'", f.name));
+            }
+        }
+
+        let tags = sheet.meta.frame_tags.into_iter()
+            .map(|t| {
+                let direction = match t.direction.as_str() {
+                    "" | "forward" => TagDirection::Forward,
+                    "reverse" => TagDirection::Reverse,
+                    "pingpong" => TagDirection::PingPong,
+                    "pingpong_reverse" => TagDirection::PingPongReverse,
+                    other => return Err(format!("SpriteSheetError: tag '{}' has unknown direction '{}'", t.name, other))
+                };
+                if t.from > t.to || t.to >= frames.len() {
+                    return Err(format!("SpriteSheetError: tag '{}' covers frames {}..{} of {}", t.name, t.from, t.to, frames.len()));
+                }
+                Ok(FrameTag { name: t.name, from: t.from, to: t.to, direction })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(SpriteSheet { texture, frames, names, tags })
+    }
+
+    pub fn texture(&self) -> &Rc<Texture> { &self.texture }
+    pub fn frames(&self) -> &[SpriteFrame] { &self.frames }
+    pub fn tags(&self) -> &[FrameTag] { &self.tags }
+
+    pub fn frame(&self, name: &str) -> Option<&SpriteFrame> {
+        self.names.get(name).map(|i| &self.frames[*i])
+    }
+
+    pub fn frame_index(&self, name: &str) -> Option<usize> {
+        self.names.get(name).copied()
+    }
+
+    /**
+     * Texture rect for Quad::new() / Quad::with_xform(). Rotated frames need quad() instead
+    */
+    pub fn region(&self, name: &str) -> Option<Rectui> {
+        self.frame(name).map(|f| f.rect)
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&FrameTag> {
+        self.tags.iter().find(|t| t.name == name)
+    }
+
+    /**
+     * Frames covered by the named tag, in sheet order
+    */
+    pub fn tag_frames(&self, name: &str) -> Option<&[SpriteFrame]> {
+        self.tag(name).map(|t| &self.frames[t.from..=t.to])
+    }
+
+    /**
+     * Quad for the named frame. xform places the untrimmed sprite the same way it would place
+     * Quad::default_verts(), so scale it by source_size for pixel sized sprites. Trimmed frames are
+     * offset into place, the pivot is moved to the transform's origin and rotated frames are turned back upright.
+     * Texture coordinates are normalized
+    */
+    pub fn quad(&self, name: &str, xform: &Transform) -> Option<Quad> {
+        self.frame(name).map(|f| self.frame_quad(f, xform))
+    }
+
+    pub fn frame_quad(&self, frame: &SpriteFrame, xform: &Transform) -> Quad {
+        let source = glm::vec2(frame.source_size.x.max(1) as f32, frame.source_size.y.max(1) as f32);
+        let r = frame.source_rect;
+        // Trimmed area in unit quad space, y up, shifted so the pivot sits at 0, 0
+        let left = r.x as f32 / source.x - frame.pivot.x;
+        let right = (r.x + r.w) as f32 / source.x - frame.pivot.x;
+        let top = frame.pivot.y - r.y as f32 / source.y;
+        let bottom = frame.pivot.y - (r.y + r.h) as f32 / source.y;
+
+        let mut verts = Quad::default_verts();
+        let corners = [(left, top), (left, bottom), (right, top), (right, bottom)];
+        for (v, (x, y)) in verts.iter_mut().zip(corners.iter()) {
+            v.position.x = *x;
+            v.position.y = *y;
+        }
+        verts.iter_mut().calc_texture_coords(&frame.rect);
+
+        if frame.rotated {
+            // Stored 90 degrees clockwise, so the sprite's top left is the stored rect's top right
+            let uv: Vec<Vert2DTextureCoord> = verts.iter().map(|v| v.text_coord).collect();
+            let (tl, bl, tr, br) = (0, 1, 2, 3);
+            verts[tl].text_coord = uv[tr];
+            verts[tr].text_coord = uv[br];
+            verts[br].text_coord = uv[bl];
+            verts[bl].text_coord = uv[tl];
+        }
+
+        verts.iter_mut().translate(xform);
+        let size = glm::vec2(self.texture.size.x as f32, self.texture.size.y as f32);
+        verts.iter_mut().normalize_texture_coords(size);
+        Quad::with_verts(&verts)
+    }
+}
+
+#[derive(Deserialize)]
+struct JsonRect {
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+}
+
+#[derive(Deserialize)]
+struct JsonSize {
+    w: u32,
+    h: u32,
+}
+
+#[derive(Deserialize)]
+struct JsonPoint {
+    x: f32,
+    y: f32,
+}
+
+#[derive(Deserialize)]
+struct JsonFrame {
+    filename: Option<String>,
+    frame: JsonRect,
+    #[serde(default)]
+    rotated: bool,
+    #[serde(default)]
+    trimmed: bool,
+    #[serde(rename = "spriteSourceSize")]
+    sprite_source_size: Option<JsonRect>,
+    #[serde(rename = "sourceSize")]
+    source_size: Option<JsonSize>,
+    pivot: Option<JsonPoint>,
+    /**
+     * Milliseconds
+    */
+    duration: Option<f32>,
+}
+
+impl JsonFrame {
+    fn into_frame(self, name: String) -> SpriteFrame {
+        let rect = Rectui::new(self.frame.x, self.frame.y, self.frame.w, self.frame.h);
+        let upright = if self.rotated { glm::vec2(rect.h, rect.w) } else { glm::vec2(rect.w, rect.h) };
+        let source_size = self.source_size.map(|s| glm::vec2(s.w, s.h)).unwrap_or(upright);
+        let source_rect = self.sprite_source_size
+            .map(|r| Rectui::new(r.x, r.y, r.w, r.h))
+            .unwrap_or_else(|| Rectui::new(0, 0, upright.x, upright.y));
+
+        SpriteFrame {
+            name,
+            rect,
+            rotated: self.rotated,
+            trimmed: self.trimmed,
+            source_size,
+            source_rect,
+            pivot: self.pivot.map(|p| glm::vec2(p.x, p.y)).unwrap_or_else(|| glm::vec2(0.5, 0.5)),
+            duration: self.duration.unwrap_or(0.) / 1000.,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonFrames {
+    Array(Vec<JsonFrame>),
+    /**
+     * Kept as raw values so file order is preserved (serde_json preserve_order)
+    */
+    Hash(serde_json::Map<String, serde_json::Value>),
+}
+
+#[derive(Deserialize)]
+struct JsonTag {
+    name: String,
+    from: usize,
+    to: usize,
+    #[serde(default)]
+    direction: String,
+}
+
+#[derive(Deserialize, Default)]
+struct JsonMeta {
+    image: Option<String>,
+    #[serde(rename = "frameTags", default)]
+    frame_tags: Vec<JsonTag>,
+}
+
+#[derive(Deserialize)]
+struct JsonSheet {
+    frames: JsonFrames,
+    #[serde(default)]
+    meta: JsonMeta,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sheet(json: &str) -> Result<SpriteSheet, String> {
+        SpriteSheet::from_json(json, Rc::new(Texture::unloaded(64, 64)))
+    }
+
+    const HASH: &str = r#"{
+        "frames": {
+            "walk_1": { "frame": { "x": 0, "y": 0, "w": 16, "h": 16 }, "duration": 100 },
+            "walk_0": { "frame": { "x": 16, "y": 0, "w": 8, "h": 8 }, "trimmed": true,
+                "spriteSourceSize": { "x": 4, "y": 2, "w": 8, "h": 8 }, "sourceSize": { "w": 16, "h": 16 } },
+            "idle": { "frame": { "x": 0, "y": 16, "w": 10, "h": 20 }, "rotated": true, "pivot": { "x": 0, "y": 1 } }
+        },
+        "meta": { "image": "ignored.png", "frameTags": [
+            { "name": "walk", "from": 0, "to": 1, "direction": "pingpong" },
+            { "name": "idle", "from": 2, "to": 2 }
+        ] }
+    }"#;
+
+    #[test]
+    fn hash_keeps_file_order() {
+        let s = sheet(HASH).unwrap();
+        let names: Vec<&str> = s.frames().iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["walk_1", "walk_0", "idle"]);
+        assert_eq!(s.frame_index("idle"), Some(2));
+        assert_eq!(s.frame("walk_1").unwrap().duration, 0.1);
+        assert_eq!(s.region("walk_0"), Some(Rectui::new(16, 0, 8, 8)));
+        assert!(s.frame("run").is_none());
+    }
+
+    #[test]
+    fn array_names_missing_filenames_by_index() {
+        let s = sheet(r#"{ "frames": [
+            { "filename": "a", "frame": { "x": 0, "y": 0, "w": 4, "h": 4 } },
+            { "frame": { "x": 4, "y": 0, "w": 4, "h": 4 } }
+        ] }"#).unwrap();
+        assert_eq!(s.frames()[0].name, "a");
+        assert_eq!(s.frames()[1].name, "1");
+        assert_eq!(s.frames()[1].source_size, glm::vec2(4, 4));
+        assert_eq!(s.frames()[1].pivot, glm::vec2(0.5, 0.5));
+        assert!(s.tags().is_empty());
+    }
+
+    #[test]
+    fn tags() {
+        let s = sheet(HASH).unwrap();
+        assert_eq!(s.tag("walk").unwrap().direction, TagDirection::PingPong);
+        assert_eq!(s.tag("idle").unwrap().direction, TagDirection::Forward);
+        assert_eq!(s.tag_frames("walk").unwrap().len(), 2);
+
+        assert!(sheet(&HASH.replace("\"to\": 1", "\"to\": 3")).is_err());
+        assert!(sheet(&HASH.replace("pingpong", "sideways")).is_err());
+    }
+
+    #[test]
+    fn duplicate_names_are_errors() {
+        let json = r#"{ "frames": [
+            { "filename": "a", "frame": { "x": 0, "y": 0, "w": 4, "h": 4 } },
+            { "filename": "a", "frame": { "x": 4, "y": 0, "w": 4, "h": 4 } }
+        ] }"#;
+        assert!(sheet(json).err().unwrap().contains("duplicate"));
+    }
+
+    #[test]
+    fn trimmed_frames_are_offset_into_place() {
+        let s = sheet(HASH).unwrap();
+        let f = s.frame("walk_0").unwrap();
+        assert!(f.trimmed);
+        assert_eq!(f.source_rect, Rectui::new(4, 2, 8, 8));
+
+        let q = s.quad("walk_0", &Transform::default()).unwrap();
+        let pos: Vec<(f32, f32)> = q.verts.iter().map(|v| (v.position.x, v.position.y)).collect();
+        assert_eq!(pos, vec![(-0.25, 0.375), (-0.25, -0.125), (0.25, 0.375), (0.25, -0.125)]);
+    }
+
+    #[test]
+    fn rotated_frames_are_turned_upright() {
+        let s = sheet(HASH).unwrap();
+        let f = s.frame("idle").unwrap();
+        assert!(f.rotated);
+        assert_eq!(f.size(), glm::vec2(20, 10));
+        assert_eq!(f.source_size, glm::vec2(20, 10));
+
+        // Pivot at the bottom left puts the sprite in 0..1 on both axes
+        let rotated = s.quad("idle", &Transform::default()).unwrap();
+        let pos: Vec<(f32, f32)> = rotated.verts.iter().map(|v| (v.position.x, v.position.y)).collect();
+        assert_eq!(pos, vec![(0., 1.), (0., 0.), (1., 1.), (1., 0.)]);
+
+        let upright = s.frame_quad(&SpriteFrame { rotated: false, ..f.clone() }, &Transform::default());
+        let uv = |q: &Quad, i: usize| (q.verts[i].text_coord.u, q.verts[i].text_coord.v);
+        let (tl, bl, tr, br) = (0, 1, 2, 3);
+        assert_eq!(uv(&rotated, tl), uv(&upright, tr));
+        assert_eq!(uv(&rotated, tr), uv(&upright, br));
+        assert_eq!(uv(&rotated, br), uv(&upright, bl));
+        assert_eq!(uv(&rotated, bl), uv(&upright, tl));
+    }
+}
@@ -0,0 +1,175 @@
+use crate::opengl::*;
+use crate::sys::Recti;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BlendFactor {
+    Zero = gl::ZERO as isize,
+    One = gl::ONE as isize,
+    SrcColor = gl::SRC_COLOR as isize,
+    OneMinusSrcColor = gl::ONE_MINUS_SRC_COLOR as isize,
+    DstColor = gl::DST_COLOR as isize,
+    OneMinusDstColor = gl::ONE_MINUS_DST_COLOR as isize,
+    SrcAlpha = gl::SRC_ALPHA as isize,
+    OneMinusSrcAlpha = gl::ONE_MINUS_SRC_ALPHA as isize,
+    DstAlpha = gl::DST_ALPHA as isize,
+    OneMinusDstAlpha = gl::ONE_MINUS_DST_ALPHA as isize,
+    ConstantColor = gl::CONSTANT_COLOR as isize,
+    OneMinusConstantColor = gl::ONE_MINUS_CONSTANT_COLOR as isize,
+    ConstantAlpha = gl::CONSTANT_ALPHA as isize,
+    OneMinusConstantAlpha = gl::ONE_MINUS_CONSTANT_ALPHA as isize,
+    SrcAlphaSaturate = gl::SRC_ALPHA_SATURATE as isize,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BlendEquation {
+    Add = gl::FUNC_ADD as isize,
+    Subtract = gl::FUNC_SUBTRACT as isize,
+    ReverseSubtract = gl::FUNC_REVERSE_SUBTRACT as isize,
+    Min = gl::MIN as isize,
+    Max = gl::MAX as isize,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum BlendMode {
+    /**
+     * Standard alpha blending for straight (non-premultiplied) colors, the same
+     * SRC_ALPHA, ONE_MINUS_SRC_ALPHA factors for rgb and alpha. Renderer default
+    */
+    #[default]
+    Alpha,
+    PremultipliedAlpha,
+    Additive,
+    Multiply,
+    Screen,
+    /**
+     * Disables blending, source overwrites destination
+    */
+    Replace,
+    Custom {
+        src_rgb: BlendFactor, dst_rgb: BlendFactor, equation_rgb: BlendEquation,
+        src_alpha: BlendFactor, dst_alpha: BlendFactor, equation_alpha: BlendEquation,
+    }
+}
+
+impl BlendMode {
+
+    /**
+     * Custom blend mode using the same factors and equation for both rgb and alpha
+    */
+    pub fn custom(src: BlendFactor, dst: BlendFactor, equation: BlendEquation) -> Self {
+        BlendMode::Custom {
+            src_rgb: src, dst_rgb: dst, equation_rgb: equation,
+            src_alpha: src, dst_alpha: dst, equation_alpha: equation
+        }
+    }
+
+    pub fn apply(&self) {
+        use BlendFactor::*;
+        use BlendEquation::*;
+
+        let (src_rgb, dst_rgb, eq_rgb, src_alpha, dst_alpha, eq_alpha) = match *self {
+            BlendMode::Alpha => (SrcAlpha, OneMinusSrcAlpha, Add, SrcAlpha, OneMinusSrcAlpha, Add),
+            BlendMode::PremultipliedAlpha => (One, OneMinusSrcAlpha, Add, One, OneMinusSrcAlpha, Add),
+            BlendMode::Additive => (SrcAlpha, One, Add, One, One, Add),
+            BlendMode::Multiply => (DstColor, OneMinusSrcAlpha, Add, DstAlpha, OneMinusSrcAlpha, Add),
+            BlendMode::Screen => (One, OneMinusSrcColor, Add, One, OneMinusSrcAlpha, Add),
+            BlendMode::Replace => {
+                unsafe { opengl().Disable(gl::BLEND) };
+                return;
+            },
+            BlendMode::Custom { src_rgb, dst_rgb, equation_rgb, src_alpha, dst_alpha, equation_alpha } =>
+                (src_rgb, dst_rgb, equation_rgb, src_alpha, dst_alpha, equation_alpha)
+        };
+
+        unsafe {
+            let gl = opengl();
+            gl.Enable(gl::BLEND);
+            gl.BlendFuncSeparate(src_rgb as u32, dst_rgb as u32, src_alpha as u32, dst_alpha as u32);
+            gl.BlendEquationSeparate(eq_rgb as u32, eq_alpha as u32);
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CullFace {
+    Front = gl::FRONT as isize,
+    Back = gl::BACK as isize,
+    FrontAndBack = gl::FRONT_AND_BACK as isize,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ColorMask {
+    pub r: bool, pub g: bool, pub b: bool, pub a: bool
+}
+
+impl ColorMask {
+    pub const fn all() -> Self { ColorMask { r: true, g: true, b: true, a: true } }
+    pub const fn none() -> Self { ColorMask { r: false, g: false, b: false, a: false } }
+}
+
+impl Default for ColorMask {
+    fn default() -> Self { ColorMask::all() }
+}
+
+/**
+ * Fixed function state applied before drawing. Scissor rect is in window pixels
+ * with origin at bottom left, same as glScissor
+*/
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RenderState {
+    pub blend: BlendMode,
+    pub depth_test: bool,
+    pub depth_write: bool,
+    pub cull_face: Option<CullFace>,
+    pub scissor: Option<Recti>,
+    pub color_mask: ColorMask,
+}
+
+impl RenderState {
+
+    pub fn with_blend(blend: BlendMode) -> Self {
+        RenderState { blend, ..RenderState::default() }
+    }
+
+    pub fn apply(&self) {
+        self.blend.apply();
+
+        unsafe {
+            let gl = opengl();
+            if self.depth_test { gl.Enable(gl::DEPTH_TEST) } else { gl.Disable(gl::DEPTH_TEST) }
+            gl.DepthMask(self.depth_write as u8);
+
+            match self.cull_face {
+                Some(face) => {
+                    gl.Enable(gl::CULL_FACE);
+                    gl.CullFace(face as u32);
+                },
+                None => gl.Disable(gl::CULL_FACE)
+            };
+
+            match self.scissor {
+                Some(r) => {
+                    gl.Enable(gl::SCISSOR_TEST);
+                    gl.Scissor(r.x, r.y, r.w, r.h);
+                },
+                None => gl.Disable(gl::SCISSOR_TEST)
+            };
+
+            let m = self.color_mask;
+            gl.ColorMask(m.r as u8, m.g as u8, m.b as u8, m.a as u8);
+        }
+    }
+}
+
+impl Default for RenderState {
+    fn default() -> Self {
+        RenderState {
+            blend: BlendMode::Alpha,
+            depth_test: true,
+            depth_write: true,
+            cull_face: None,
+            scissor: None,
+            color_mask: ColorMask::all(),
+        }
+    }
+}
@@ -0,0 +1,848 @@
+use crate::buffers::*;
+use crate::graphics::Mesh;
+use crate::sys::{parse_hex_color, radians, read_file, TWO_PI};
+use crate::vertex::*;
+use nalgebra_glm as glm;
+
+/**
+ * Max distance in SVG user units between a flattened curve and the real curve
+*/
+pub const DEFAULT_TOLERANCE: f32 = 0.25;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FillRule {
+    NonZero,
+    EvenOdd,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SvgContour {
+    pub points: Vec<glm::Vec2>,
+    pub closed: bool,
+}
+
+/**
+ * A single path, rect, circle, ellipse, line, polyline or polygon element, flattened into
+ * contours with all transforms already applied
+*/
+#[derive(Debug, Clone)]
+pub struct SvgShape {
+    pub id: Option<String>,
+    pub contours: Vec<SvgContour>,
+    pub fill: Option<glm::Vec4>,
+    pub fill_rule: FillRule,
+    pub stroke: Option<glm::Vec4>,
+    pub stroke_style: StrokeStyle,
+}
+
+/**
+ * Subset of SVG covering path, rect, circle, ellipse, line, polyline and polygon elements inside
+ * nested groups, with fill and stroke colors, opacity and transforms. SVG y points down, so points are
+ * flipped on load to keep the image upright in y-up world space, with the viewBox's top left at the origin
+*/
+#[derive(Debug, Clone)]
+pub struct SvgDocument {
+    pub width: f32,
+    pub height: f32,
+    pub shapes: Vec<SvgShape>,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Style {
+    fill: Option<glm::Vec4>,
+    fill_rule: FillRule,
+    stroke: Option<glm::Vec4>,
+    stroke_width: f32,
+    join: LineJoin,
+    cap: LineCap,
+    miter_limit: f32,
+    opacity: f32,
+    fill_opacity: f32,
+    stroke_opacity: f32,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Style {
+            fill: Some(glm::vec4(0., 0., 0., 1.)), fill_rule: FillRule::NonZero,
+            stroke: None, stroke_width: 1.,
+            join: LineJoin::Miter, cap: LineCap::Butt, miter_limit: 4.,
+            opacity: 1., fill_opacity: 1., stroke_opacity: 1.
+        }
+    }
+}
+
+impl SvgDocument {
+    pub fn from_file(filename: &str) -> Result<Self, String> {
+        let contents = read_file(filename).map_err(|e| format!("Error loading file: {} :: {}", filename, e))?;
+        SvgDocument::from_str(&contents, DEFAULT_TOLERANCE)
+            .map_err(|e| format!("Error loading file: {} :: {}", filename, e))
+    }
+
+    /**
+     * tolerance controls how finely curves and arcs are flattened, see DEFAULT_TOLERANCE
+    */
+    pub fn from_str(svg: &str, tolerance: f32) -> Result<Self, String> {
+        let doc = roxmltree::Document::parse(svg).map_err(|e| format!("SvgError: {}", e))?;
+        let root = doc.root_element();
+        if root.tag_name().name() != "svg" {
+            return Err("SvgError: root element is not <svg>".into());
+        }
+
+        let view_box: Option<Vec<f32>> = root.attribute("viewBox")
+            .map(parse_numbers)
+            .filter(|v| v.len() == 4);
+        let width = root.attribute("width").and_then(parse_length);
+        let height = root.attribute("height").and_then(parse_length);
+
+        let (vb_x, vb_y, vb_w, vb_h) = match view_box.as_ref() {
+            Some(v) => (v[0], v[1], v[2], v[3]),
+            None => (0., 0., width.unwrap_or(100.), height.unwrap_or(100.))
+        };
+        let (width, height) = (width.unwrap_or(vb_w), height.unwrap_or(vb_h));
+
+        // viewBox -> viewport, then flip y so the top left ends up at the origin with y up
+        let to_viewport = mat_translate(-vb_x, -vb_y);
+        let scale = mat_scale(width / vb_w, height / vb_h);
+        let flip = mat_scale(1., -1.);
+        let base = flip * scale * to_viewport;
+
+        let mut shapes = Vec::new();
+        parse_children(root, &base, Style::default(), tolerance.max(0.001), &mut shapes)?;
+        Ok(SvgDocument { width, height, shapes })
+    }
+
+    pub fn find(&self, id: &str) -> Option<&SvgShape> {
+        self.shapes.iter().find(|s| s.id.as_deref() == Some(id))
+    }
+
+    /**
+     * Fills and strokes every shape in document order into a single tessellation
+    */
+    pub fn tessellate(&self) -> Tessellation {
+        let mut t = Tessellation::new();
+        for s in self.shapes.iter() {
+            t.append(&s.tessellate());
+        }
+        t
+    }
+
+    pub fn to_mesh(&self, usage: DrawUsage) -> Mesh {
+        tessellation_to_mesh(&self.tessellate(), usage)
+    }
+}
+
+impl SvgShape {
+    pub fn tessellate(&self) -> Tessellation {
+        let mut t = Tessellation::new();
+        if let Some(fill) = self.fill {
+            for (outer, holes) in group_contours(&self.contours, self.fill_rule) {
+                t.append(&triangulate_polygon_with_holes(&outer, &holes, &fill));
+            }
+        }
+        if let Some(stroke) = self.stroke {
+            for c in self.contours.iter() {
+                t.append(&stroke_polyline(&c.points, c.closed, &self.stroke_style, &stroke));
+            }
+        }
+        t
+    }
+
+    pub fn to_mesh(&self, usage: DrawUsage) -> Mesh {
+        tessellation_to_mesh(&self.tessellate(), usage)
+    }
+}
+
+fn tessellation_to_mesh(t: &Tessellation, usage: DrawUsage) -> Mesh {
+    let mut mesh = Mesh::new(VertexBuffer::new(&t.vertices, usage));
+    mesh.indices = Some(ElementBuffer::new_with_draw(&t.indices, usage));
+    mesh
+}
+
+/**
+ * Splits closed contours into outer boundaries and the holes directly inside them, based on
+ * how deeply each contour is nested. Even depths are filled, odd depths are holes
+*/
+fn group_contours(contours: &[SvgContour], rule: FillRule) -> Vec<(Vec<glm::Vec2>, Vec<Vec<glm::Vec2>>)> {
+    let fillable: Vec<&SvgContour> = contours.iter().filter(|c| c.points.len() >= 3).collect();
+    let depth: Vec<usize> = fillable.iter().enumerate()
+        .map(|(i, c)| fillable.iter().enumerate()
+            .filter(|(j, other)| *j != i && point_in_polygon(c.points[0], &other.points))
+            .count())
+        .collect();
+
+    let mut groups = Vec::new();
+    for (i, c) in fillable.iter().enumerate() {
+        let is_outer = match rule {
+            FillRule::EvenOdd => depth[i].is_multiple_of(2),
+            // Without winding numbers per region, treat nonzero like even-odd for nesting purposes,
+            // except contours wound the same way as their parent, which stay filled
+            FillRule::NonZero => depth[i].is_multiple_of(2) || !opposite_parent_winding(i, &fillable, &depth),
+        };
+        if !is_outer {
+            continue;
+        }
+        let holes = fillable.iter().enumerate()
+            .filter(|(j, h)| depth[*j] == depth[i] + 1 && point_in_polygon(h.points[0], &c.points))
+            .filter(|(j, _)| rule == FillRule::EvenOdd || opposite_parent_winding(*j, &fillable, &depth))
+            .map(|(_, h)| h.points.clone())
+            .collect();
+        groups.push((c.points.clone(), holes));
+    }
+    groups
+}
+
+fn opposite_parent_winding(i: usize, contours: &[&SvgContour], depth: &[usize]) -> bool {
+    if depth[i] == 0 {
+        return false;
+    }
+    let parent = contours.iter().enumerate()
+        .find(|(j, other)| depth[*j] + 1 == depth[i] && point_in_polygon(contours[i].points[0], &other.points));
+    match parent {
+        Some((_, p)) => (signed_area(&p.points) > 0.) != (signed_area(&contours[i].points) > 0.),
+        None => true
+    }
+}
+
+fn point_in_polygon(p: glm::Vec2, poly: &[glm::Vec2]) -> bool {
+    let mut inside = false;
+    let mut j = poly.len() - 1;
+    for i in 0..poly.len() {
+        let (a, b) = (poly[i], poly[j]);
+        if (a.y > p.y) != (b.y > p.y) && p.x < (b.x - a.x) * (p.y - a.y) / (b.y - a.y) + a.x {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+fn parse_children(node: roxmltree::Node, parent_xform: &glm::Mat3, parent_style: Style, tolerance: f32, out: &mut Vec<SvgShape>) -> Result<(), String> {
+    for child in node.children().filter(|n| n.is_element()) {
+        let name = child.tag_name().name();
+        if name == "defs" || name == "clipPath" || name == "mask" || name == "symbol" {
+            continue;
+        }
+
+        let xform = match child.attribute("transform") {
+            Some(t) => parent_xform * parse_transform(t)?,
+            None => *parent_xform
+        };
+        let style = parse_style(child, parent_style);
+        if child.attribute("display") == Some("none") {
+            continue;
+        }
+
+        if name == "g" || name == "svg" {
+            parse_children(child, &xform, style, tolerance, out)?;
+            continue;
+        }
+
+        let attr = |n: &str| child.attribute(n).and_then(parse_length).unwrap_or(0.);
+        let contours = match name {
+            "path" => parse_path(child.attribute("d").unwrap_or(""), tolerance)?,
+            "rect" => {
+                let (x, y, w, h) = (attr("x"), attr("y"), attr("width"), attr("height"));
+                let (rx, ry) = match (child.attribute("rx").and_then(parse_length), child.attribute("ry").and_then(parse_length)) {
+                    (Some(rx), Some(ry)) => (rx, ry),
+                    (Some(r), None) | (None, Some(r)) => (r, r),
+                    (None, None) => (0., 0.)
+                };
+                vec![rect_contour(x, y, w, h, rx.min(w * 0.5), ry.min(h * 0.5), tolerance)]
+            },
+            "circle" => {
+                let r = attr("r");
+                vec![ellipse_contour(glm::vec2(attr("cx"), attr("cy")), glm::vec2(r, r), tolerance)]
+            },
+            "ellipse" => vec![ellipse_contour(glm::vec2(attr("cx"), attr("cy")), glm::vec2(attr("rx"), attr("ry")), tolerance)],
+            "line" => vec![SvgContour {
+                points: vec![glm::vec2(attr("x1"), attr("y1")), glm::vec2(attr("x2"), attr("y2"))],
+                closed: false
+            }],
+            "polyline" | "polygon" => {
+                let nums = parse_numbers(child.attribute("points").unwrap_or(""));
+                let points = nums.chunks_exact(2).map(|p| glm::vec2(p[0], p[1])).collect();
+                vec![SvgContour { points, closed: name == "polygon" }]
+            },
+            _ => continue
+        };
+
+        let contours = contours.into_iter()
+            .map(|c| SvgContour { points: c.points.iter().map(|p| transform_point(&xform, *p)).collect(), closed: c.closed })
+            .collect();
+
+        // Lines and polylines are never filled
+        let fill = if name == "line" || name == "polyline" { None } else { style.fill };
+        let stroke_scale = xform.determinant().abs().sqrt();
+
+        out.push(SvgShape {
+            id: child.attribute("id").map(String::from),
+            contours,
+            fill: fill.map(|c| glm::vec4(c.x, c.y, c.z, c.w * style.fill_opacity * style.opacity)),
+            fill_rule: style.fill_rule,
+            stroke: style.stroke.filter(|_| style.stroke_width > 0.)
+                .map(|c| glm::vec4(c.x, c.y, c.z, c.w * style.stroke_opacity * style.opacity)),
+            stroke_style: StrokeStyle {
+                thickness: style.stroke_width * stroke_scale, join: style.join, cap: style.cap,
+                miter_limit: style.miter_limit, ..StrokeStyle::default()
+            },
+        });
+    }
+    Ok(())
+}
+
+fn parse_style(node: roxmltree::Node, parent: Style) -> Style {
+    let mut style = Style { opacity: 1., ..parent };
+    let mut apply = |key: &str, value: &str| {
+        let value = value.trim();
+        match key.trim() {
+            "fill" => style.fill = parse_paint(value, parent.fill),
+            "stroke" => style.stroke = parse_paint(value, parent.stroke),
+            "stroke-width" => style.stroke_width = parse_length(value).unwrap_or(style.stroke_width),
+            "fill-rule" => style.fill_rule = if value == "evenodd" { FillRule::EvenOdd } else { FillRule::NonZero },
+            "stroke-linejoin" => style.join = match value { "round" => LineJoin::Round, "bevel" => LineJoin::Bevel, _ => LineJoin::Miter },
+            "stroke-linecap" => style.cap = match value { "round" => LineCap::Round, "square" => LineCap::Square, _ => LineCap::Butt },
+            "stroke-miterlimit" => style.miter_limit = value.parse().unwrap_or(style.miter_limit),
+            "opacity" => style.opacity = parse_opacity(value),
+            "fill-opacity" => style.fill_opacity = parse_opacity(value),
+            "stroke-opacity" => style.stroke_opacity = parse_opacity(value),
+            _ => {}
+        }
+    };
+
+    for a in node.attributes() {
+        apply(a.name(), a.value());
+    }
+    // style="" wins over presentation attributes
+    if let Some(css) = node.attribute("style") {
+        for decl in css.split(';') {
+            let mut kv = decl.splitn(2, ':');
+            if let (Some(k), Some(v)) = (kv.next(), kv.next()) {
+                apply(k, v);
+            }
+        }
+    }
+    // Group opacity multiplies down the tree
+    style.opacity *= parent.opacity;
+    style
+}
+
+fn parse_opacity(value: &str) -> f32 {
+    let v = value.trim();
+    let n = match v.strip_suffix('%') {
+        Some(p) => p.parse::<f32>().map(|p| p / 100.),
+        None => v.parse::<f32>()
+    };
+    n.unwrap_or(1.).clamp(0., 1.)
+}
+
+fn parse_paint(value: &str, inherited: Option<glm::Vec4>) -> Option<glm::Vec4> {
+    match value {
+        "none" | "transparent" => None,
+        "inherit" => inherited,
+        // currentColor would need the color property tracked as well, black is what most exporters mean
+        "currentColor" => Some(glm::vec4(0., 0., 0., 1.)),
+        v if v.starts_with("url(") => inherited,
+        v => parse_color(v)
+    }
+}
+
+/**
+ * CSS color as used in fill and stroke: #RGB, #RGBA, #RRGGBB, #RRGGBBAA, rgb() / rgba() or a basic named color
+*/
+pub fn parse_color(value: &str) -> Option<glm::Vec4> {
+    let value = value.trim();
+    if value.starts_with('#') {
+        return parse_hex_color(value);
+    }
+
+    if value.starts_with("rgb") {
+        let inner = value.split_once('(')?.1.trim_end_matches(')');
+        let parts: Vec<f32> = inner.split([',', ' ', '/'])
+            .filter(|s| !s.is_empty())
+            .map(|s| match s.strip_suffix('%') {
+                Some(p) => p.parse::<f32>().map(|n| n * 2.55).unwrap_or(0.),
+                None => s.parse::<f32>().unwrap_or(0.)
+            })
+            .collect();
+        if parts.len() < 3 {
+            return None;
+        }
+        let alpha = parts.get(3).map(|a| if *a > 1. { a / 255. } else { *a }).unwrap_or(1.);
+        return Some(glm::vec4(parts[0] / 255., parts[1] / 255., parts[2] / 255., alpha));
+    }
+
+    let rgb = match value.to_ascii_lowercase().as_str() {
+        "black" => (0, 0, 0),
+        "white" => (255, 255, 255),
+        "red" => (255, 0, 0),
+        "lime" => (0, 255, 0),
+        "green" => (0, 128, 0),
+        "blue" => (0, 0, 255),
+        "yellow" => (255, 255, 0),
+        "cyan" | "aqua" => (0, 255, 255),
+        "magenta" | "fuchsia" => (255, 0, 255),
+        "gray" | "grey" => (128, 128, 128),
+        "silver" => (192, 192, 192),
+        "maroon" => (128, 0, 0),
+        "olive" => (128, 128, 0),
+        "navy" => (0, 0, 128),
+        "purple" => (128, 0, 128),
+        "teal" => (0, 128, 128),
+        "orange" => (255, 165, 0),
+        "pink" => (255, 192, 203),
+        "brown" => (165, 42, 42),
+        _ => return None
+    };
+    Some(glm::vec4(rgb.0 as f32 / 255., rgb.1 as f32 / 255., rgb.2 as f32 / 255., 1.))
+}
+
+/**
+ * Parses a number with an optional px unit. Other units are treated as user units
+*/
+fn parse_length(value: &str) -> Option<f32> {
+    let v = value.trim().trim_end_matches(|c: char| c.is_ascii_alphabetic() || c == '%');
+    v.parse().ok()
+}
+
+fn parse_numbers(value: &str) -> Vec<f32> {
+    let mut lexer = PathLexer::new(value);
+    let mut out = Vec::new();
+    while let Some(n) = lexer.number() {
+        out.push(n);
+    }
+    out
+}
+
+fn mat_translate(x: f32, y: f32) -> glm::Mat3 {
+    glm::mat3(1., 0., x, 0., 1., y, 0., 0., 1.)
+}
+
+fn mat_scale(x: f32, y: f32) -> glm::Mat3 {
+    glm::mat3(x, 0., 0., 0., y, 0., 0., 0., 1.)
+}
+
+fn transform_point(m: &glm::Mat3, p: glm::Vec2) -> glm::Vec2 {
+    let v = m * glm::vec3(p.x, p.y, 1.);
+    glm::vec2(v.x, v.y)
+}
+
+fn parse_transform(value: &str) -> Result<glm::Mat3, String> {
+    let mut result = glm::Mat3::identity();
+    for part in value.split(')') {
+        let mut kv = part.splitn(2, '(');
+        let (name, args) = match (kv.next(), kv.next()) {
+            (Some(n), Some(a)) => (n.trim().trim_start_matches(',').trim(), parse_numbers(a)),
+            _ => continue
+        };
+        let arg = |i: usize, default: f32| args.get(i).copied().unwrap_or(default);
+
+        let m = match name {
+            "matrix" if args.len() == 6 => glm::mat3(args[0], args[2], args[4], args[1], args[3], args[5], 0., 0., 1.),
+            "translate" => mat_translate(arg(0, 0.), arg(1, 0.)),
+            "scale" => mat_scale(arg(0, 1.), arg(1, arg(0, 1.))),
+            "rotate" => {
+                let (s, c) = radians(arg(0, 0.)).sin_cos();
+                let (cx, cy) = (arg(1, 0.), arg(2, 0.));
+                mat_translate(cx, cy) * glm::mat3(c, -s, 0., s, c, 0., 0., 0., 1.) * mat_translate(-cx, -cy)
+            },
+            "skewX" => glm::mat3(1., radians(arg(0, 0.)).tan(), 0., 0., 1., 0., 0., 0., 1.),
+            "skewY" => glm::mat3(1., 0., 0., radians(arg(0, 0.)).tan(), 1., 0., 0., 0., 1.),
+            _ => return Err(format!("SvgError: invalid transform '{}'", part.trim()))
+        };
+        result *= m;
+    }
+    Ok(result)
+}
+
+fn rect_contour(x: f32, y: f32, w: f32, h: f32, rx: f32, ry: f32, tolerance: f32) -> SvgContour {
+    if rx <= 0. || ry <= 0. {
+        return SvgContour {
+            points: vec![glm::vec2(x, y), glm::vec2(x + w, y), glm::vec2(x + w, y + h), glm::vec2(x, y + h)],
+            closed: true
+        };
+    }
+
+    let segments = arc_segments(rx.max(ry), std::f32::consts::FRAC_PI_2, tolerance);
+    let corners = [
+        (glm::vec2(x + w - rx, y + ry), -std::f32::consts::FRAC_PI_2),
+        (glm::vec2(x + w - rx, y + h - ry), 0.),
+        (glm::vec2(x + rx, y + h - ry), std::f32::consts::FRAC_PI_2),
+        (glm::vec2(x + rx, y + ry), std::f32::consts::PI),
+    ];
+    let mut points = Vec::new();
+    for (c, start) in corners.iter() {
+        for i in 0..=segments {
+            let a = start + std::f32::consts::FRAC_PI_2 * i as f32 / segments as f32;
+            points.push(c + glm::vec2(a.cos() * rx, a.sin() * ry));
+        }
+    }
+    SvgContour { points, closed: true }
+}
+
+fn ellipse_contour(center: glm::Vec2, radii: glm::Vec2, tolerance: f32) -> SvgContour {
+    let segments = arc_segments(radii.x.max(radii.y), TWO_PI, tolerance).max(8);
+    let points = (0..segments)
+        .map(|i| {
+            let a = TWO_PI * i as f32 / segments as f32;
+            center + glm::vec2(a.cos() * radii.x, a.sin() * radii.y)
+        })
+        .collect();
+    SvgContour { points, closed: true }
+}
+
+/**
+ * Segments needed to keep an arc within tolerance of the true curve
+*/
+fn arc_segments(radius: f32, sweep: f32, tolerance: f32) -> u32 {
+    if radius <= tolerance {
+        return 1;
+    }
+    let step = 2. * (1. - tolerance / radius).acos();
+    ((sweep.abs() / step).ceil() as u32).clamp(1, 256)
+}
+
+fn curve_segments(control_polygon_len: f32, tolerance: f32) -> u32 {
+    ((control_polygon_len / tolerance).sqrt().ceil() as u32).clamp(1, 256)
+}
+
+struct PathLexer<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> PathLexer<'a> {
+    fn new(s: &'a str) -> Self {
+        PathLexer { bytes: s.as_bytes(), pos: 0 }
+    }
+
+    fn skip_separators(&mut self) {
+        while self.pos < self.bytes.len() && (self.bytes[self.pos].is_ascii_whitespace() || self.bytes[self.pos] == b',') {
+            self.pos += 1;
+        }
+    }
+
+    fn command(&mut self) -> Option<u8> {
+        self.skip_separators();
+        let c = *self.bytes.get(self.pos)?;
+        if c.is_ascii_alphabetic() && c != b'e' && c != b'E' {
+            self.pos += 1;
+            Some(c)
+        } else {
+            None
+        }
+    }
+
+    fn at_number(&mut self) -> bool {
+        self.skip_separators();
+        match self.bytes.get(self.pos) {
+            Some(c) => c.is_ascii_digit() || *c == b'-' || *c == b'+' || *c == b'.',
+            None => false
+        }
+    }
+
+    fn number(&mut self) -> Option<f32> {
+        if !self.at_number() {
+            return None;
+        }
+        let start = self.pos;
+        let b = self.bytes;
+        if b[self.pos] == b'-' || b[self.pos] == b'+' {
+            self.pos += 1;
+        }
+        let mut seen_dot = false;
+        while self.pos < b.len() && (b[self.pos].is_ascii_digit() || (b[self.pos] == b'.' && !seen_dot)) {
+            seen_dot |= b[self.pos] == b'.';
+            self.pos += 1;
+        }
+        if self.pos < b.len() && (b[self.pos] == b'e' || b[self.pos] == b'E') {
+            let exp_start = self.pos;
+            self.pos += 1;
+            if self.pos < b.len() && (b[self.pos] == b'-' || b[self.pos] == b'+') {
+                self.pos += 1;
+            }
+            if self.pos < b.len() && b[self.pos].is_ascii_digit() {
+                while self.pos < b.len() && b[self.pos].is_ascii_digit() {
+                    self.pos += 1;
+                }
+            } else {
+                self.pos = exp_start;
+            }
+        }
+        std::str::from_utf8(&b[start..self.pos]).ok()?.parse().ok()
+    }
+
+    /**
+     * Arc flags can be written without separators, e.g. "a1 1 0 01 5 5"
+    */
+    fn flag(&mut self) -> Option<bool> {
+        self.skip_separators();
+        match self.bytes.get(self.pos) {
+            Some(b'0') => { self.pos += 1; Some(false) },
+            Some(b'1') => { self.pos += 1; Some(true) },
+            _ => None
+        }
+    }
+}
+
+fn parse_path(d: &str, tolerance: f32) -> Result<Vec<SvgContour>, String> {
+    let mut lexer = PathLexer::new(d);
+    let mut contours: Vec<SvgContour> = Vec::new();
+    let mut current: Vec<glm::Vec2> = Vec::new();
+    let mut pos = glm::vec2(0., 0.);
+    let mut start = pos;
+    // Reflected control point for S/s and T/t
+    let mut last_control: Option<(u8, glm::Vec2)> = None;
+    let mut cmd: Option<u8> = None;
+
+    let err = || format!("SvgError: malformed path data '{}'", d);
+    let finish = |current: &mut Vec<glm::Vec2>, contours: &mut Vec<SvgContour>, closed: bool| {
+        if current.len() > 1 {
+            contours.push(SvgContour { points: std::mem::take(current), closed });
+        } else {
+            current.clear();
+        }
+    };
+
+    loop {
+        let c = match lexer.command() {
+            Some(c) => c,
+            // Repeated arguments reuse the previous command, M becomes L after the first pair
+            None if lexer.at_number() => match cmd {
+                Some(b'M') => b'L',
+                Some(b'm') => b'l',
+                Some(c) if c != b'Z' && c != b'z' => c,
+                _ => return Err(err())
+            },
+            None => break
+        };
+        cmd = Some(c);
+        let relative = c.is_ascii_lowercase();
+        let base = if relative { pos } else { glm::vec2(0., 0.) };
+        let point = |lexer: &mut PathLexer| -> Result<glm::Vec2, String> {
+            let x = lexer.number().ok_or_else(err)?;
+            let y = lexer.number().ok_or_else(err)?;
+            Ok(base + glm::vec2(x, y))
+        };
+
+        match c.to_ascii_uppercase() {
+            b'M' => {
+                finish(&mut current, &mut contours, false);
+                pos = point(&mut lexer)?;
+                start = pos;
+                current.push(pos);
+            },
+            b'L' => {
+                pos = point(&mut lexer)?;
+                current.push(pos);
+            },
+            b'H' => {
+                let x = lexer.number().ok_or_else(err)?;
+                pos.x = if relative { pos.x + x } else { x };
+                current.push(pos);
+            },
+            b'V' => {
+                let y = lexer.number().ok_or_else(err)?;
+                pos.y = if relative { pos.y + y } else { y };
+                current.push(pos);
+            },
+            b'C' | b'S' => {
+                let c1 = if c.eq_ignore_ascii_case(&b'C') {
+                    point(&mut lexer)?
+                } else {
+                    match last_control {
+                        Some((b'C', ctrl)) => pos * 2. - ctrl,
+                        _ => pos
+                    }
+                };
+                let c2 = point(&mut lexer)?;
+                let end = point(&mut lexer)?;
+                if current.is_empty() { current.push(pos); }
+                flatten_cubic(&mut current, pos, c1, c2, end, tolerance);
+                last_control = Some((b'C', c2));
+                pos = end;
+                continue;
+            },
+            b'Q' | b'T' => {
+                let ctrl = if c.eq_ignore_ascii_case(&b'Q') {
+                    point(&mut lexer)?
+                } else {
+                    match last_control {
+                        Some((b'Q', ctrl)) => pos * 2. - ctrl,
+                        _ => pos
+                    }
+                };
+                let end = point(&mut lexer)?;
+                if current.is_empty() { current.push(pos); }
+                flatten_quadratic(&mut current, pos, ctrl, end, tolerance);
+                last_control = Some((b'Q', ctrl));
+                pos = end;
+                continue;
+            },
+            b'A' => {
+                let rx = lexer.number().ok_or_else(err)?;
+                let ry = lexer.number().ok_or_else(err)?;
+                let rotation = lexer.number().ok_or_else(err)?;
+                let large_arc = lexer.flag().ok_or_else(err)?;
+                let sweep = lexer.flag().ok_or_else(err)?;
+                let end = point(&mut lexer)?;
+                if current.is_empty() { current.push(pos); }
+                let arc = ArcParams { radii: glm::vec2(rx, ry), rotation_degrees: rotation, large_arc, sweep };
+                flatten_arc(&mut current, pos, end, &arc, tolerance);
+                pos = end;
+            },
+            b'Z' => {
+                finish(&mut current, &mut contours, true);
+                pos = start;
+                // Drawing after Z without a moveto starts from the closed subpath's start point
+                current.push(pos);
+            },
+            _ => return Err(format!("SvgError: unsupported path command '{}'", c as char))
+        }
+        last_control = None;
+    }
+
+    finish(&mut current, &mut contours, false);
+    Ok(contours)
+}
+
+fn flatten_quadratic(out: &mut Vec<glm::Vec2>, p0: glm::Vec2, p1: glm::Vec2, p2: glm::Vec2, tolerance: f32) {
+    let n = curve_segments(glm::distance(&p0, &p1) + glm::distance(&p1, &p2), tolerance);
+    for i in 1..=n {
+        let t = i as f32 / n as f32;
+        let mt = 1. - t;
+        out.push(p0 * (mt * mt) + p1 * (2. * mt * t) + p2 * (t * t));
+    }
+}
+
+fn flatten_cubic(out: &mut Vec<glm::Vec2>, p0: glm::Vec2, p1: glm::Vec2, p2: glm::Vec2, p3: glm::Vec2, tolerance: f32) {
+    let n = curve_segments(glm::distance(&p0, &p1) + glm::distance(&p1, &p2) + glm::distance(&p2, &p3), tolerance);
+    for i in 1..=n {
+        let t = i as f32 / n as f32;
+        let mt = 1. - t;
+        out.push(p0 * (mt * mt * mt) + p1 * (3. * mt * mt * t) + p2 * (3. * mt * t * t) + p3 * (t * t * t));
+    }
+}
+
+/**
+ * Arguments of a path's A command, besides the end point
+*/
+struct ArcParams {
+    radii: glm::Vec2,
+    rotation_degrees: f32,
+    large_arc: bool,
+    sweep: bool,
+}
+
+/**
+ * Endpoint to center parameterization from the SVG spec (implementation notes, F.6.5)
+*/
+fn flatten_arc(out: &mut Vec<glm::Vec2>, p0: glm::Vec2, p1: glm::Vec2, arc: &ArcParams, tolerance: f32) {
+    let (large_arc, sweep) = (arc.large_arc, arc.sweep);
+    let (mut rx, mut ry) = (arc.radii.x.abs(), arc.radii.y.abs());
+    if rx < 1e-6 || ry < 1e-6 || p0 == p1 {
+        out.push(p1);
+        return;
+    }
+
+    let (sin_phi, cos_phi) = radians(arc.rotation_degrees).sin_cos();
+    let half = (p0 - p1) * 0.5;
+    let x1 = cos_phi * half.x + sin_phi * half.y;
+    let y1 = -sin_phi * half.x + cos_phi * half.y;
+
+    // Scale radii up if they cannot span the endpoints
+    let lambda = (x1 * x1) / (rx * rx) + (y1 * y1) / (ry * ry);
+    if lambda > 1. {
+        rx *= lambda.sqrt();
+        ry *= lambda.sqrt();
+    }
+
+    let num = rx * rx * ry * ry - rx * rx * y1 * y1 - ry * ry * x1 * x1;
+    let den = rx * rx * y1 * y1 + ry * ry * x1 * x1;
+    let mut coef = (num / den).max(0.).sqrt();
+    if large_arc == sweep {
+        coef = -coef;
+    }
+    let cx1 = coef * rx * y1 / ry;
+    let cy1 = -coef * ry * x1 / rx;
+
+    let mid = (p0 + p1) * 0.5;
+    let center = glm::vec2(cos_phi * cx1 - sin_phi * cy1 + mid.x, sin_phi * cx1 + cos_phi * cy1 + mid.y);
+
+    let angle = |u: glm::Vec2, v: glm::Vec2| (u.x * v.y - u.y * v.x).atan2(u.x * v.x + u.y * v.y);
+    let u = glm::vec2((x1 - cx1) / rx, (y1 - cy1) / ry);
+    let v = glm::vec2((-x1 - cx1) / rx, (-y1 - cy1) / ry);
+    let theta = angle(glm::vec2(1., 0.), u);
+    let mut delta = angle(u, v);
+    if !sweep && delta > 0. {
+        delta -= TWO_PI;
+    } else if sweep && delta < 0. {
+        delta += TWO_PI;
+    }
+
+    let n = arc_segments(rx.max(ry), delta, tolerance);
+    for i in 1..=n {
+        let a = theta + delta * i as f32 / n as f32;
+        let (s, c) = a.sin_cos();
+        let p = glm::vec2(rx * c, ry * s);
+        out.push(center + glm::vec2(cos_phi * p.x - sin_phi * p.y, sin_phi * p.x + cos_phi * p.y));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: glm::Vec4, b: glm::Vec4) -> bool {
+        (a - b).iter().all(|d| d.abs() < 0.01)
+    }
+
+    #[test]
+    fn hex_colors() {
+        assert!(close(parse_color("#f00").unwrap(), glm::vec4(1., 0., 0., 1.)));
+        assert!(close(parse_color("#f008").unwrap(), glm::vec4(1., 0., 0., 0.533)));
+        assert!(close(parse_color("#00ff00").unwrap(), glm::vec4(0., 1., 0., 1.)));
+        assert!(close(parse_color("#0000ff80").unwrap(), glm::vec4(0., 0., 1., 0.502)));
+    }
+
+    #[test]
+    fn malformed_colors_are_none() {
+        assert_eq!(parse_color("#ñ0"), None);
+        assert_eq!(parse_color("#ffñff"), None);
+        assert_eq!(parse_color("#12345"), None);
+        assert_eq!(parse_color("#"), None);
+        assert_eq!(parse_color("rgb(1, 2)"), None);
+        assert_eq!(parse_color("notacolor"), None);
+    }
+
+    #[test]
+    fn functional_and_named_colors() {
+        assert!(close(parse_color("rgb(255, 0, 0)").unwrap(), glm::vec4(1., 0., 0., 1.)));
+        assert!(close(parse_color("rgba(0, 0, 255, 0.5)").unwrap(), glm::vec4(0., 0., 1., 0.5)));
+        assert!(close(parse_color("rgb(100%, 0%, 0%)").unwrap(), glm::vec4(1., 0., 0., 1.)));
+        assert!(close(parse_color("Navy").unwrap(), glm::vec4(0., 0., 0.502, 1.)));
+    }
+
+    #[test]
+    fn path_contours() {
+        let contours = parse_path("M0 0 L10 0 L10 10 Z m20 0 h5 v5", DEFAULT_TOLERANCE).unwrap();
+        assert_eq!(contours.len(), 2);
+        assert!(contours[0].closed);
+        assert_eq!(contours[0].points.len(), 3);
+        assert!(!contours[1].closed);
+        assert_eq!(contours[1].points.last(), Some(&glm::vec2(25., 5.)));
+        assert!(parse_path("M0 0 L10", DEFAULT_TOLERANCE).is_err());
+    }
+
+    #[test]
+    fn document_flips_y_and_applies_fill() {
+        let svg = r##"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 10 10" width="10" height="10">
+            <rect id="box" x="0" y="0" width="4" height="2" fill="#ff000080"/>
+        </svg>"##;
+        let doc = SvgDocument::from_str(svg, DEFAULT_TOLERANCE).unwrap();
+        let shape = doc.find("box").unwrap();
+        assert!(close(shape.fill.unwrap(), glm::vec4(1., 0., 0., 0.502)));
+        let ys: Vec<f32> = shape.contours[0].points.iter().map(|p| p.y).collect();
+        assert!(ys.iter().all(|y| y.abs() < 1e-4 || (*y + 2.).abs() < 1e-4));
+    }
+}
@@ -37,6 +37,7 @@ pub fn clamp_s(s: f32, smin: f32, smax: f32) -> f32 {
     min_f(max_f(s, smin), smax)
 }
 
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Rect<T: NumDefault> {
     pub x: T, pub y: T, pub w: T, pub h: T
 }
@@ -55,6 +56,32 @@ impl<T> Rect<T> where T: NumDefault {
     }
 }
 
+impl<T> Rect<T> where T: NumDefault + PartialOrd {
+
+    /**
+     * Overlapping area of both rects, None if they do not overlap
+    */
+    pub fn intersection(&self, other: &Self) -> Option<Self> {
+        let max = |a: T, b: T| if a > b { a } else { b };
+        let min = |a: T, b: T| if a < b { a } else { b };
+
+        let x = max(self.x, other.x);
+        let y = max(self.y, other.y);
+        let right = min(self.right(), other.right());
+        let bottom = min(self.bottom(), other.bottom());
+
+        if right > x && bottom > y {
+            Some(Rect::new(x, y, right - x, bottom - y))
+        } else {
+            None
+        }
+    }
+
+    pub fn contains(&self, x: T, y: T) -> bool {
+        x >= self.x && x < self.right() && y >= self.y && y < self.bottom()
+    }
+}
+
 impl<T> Default for Rect<T> where T: NumDefault {
     
     fn default() -> Self { 
@@ -72,6 +99,7 @@ pub type Rectf = Rect<f32>;
 pub type Recti = Rect<i32>;
 pub type Rectui = Rect<u32>;
 
+#[derive(Copy, Clone)]
 pub struct Quad {
     pub verts: [Vertex2D; 4]
 }
@@ -283,3 +311,29 @@ pub fn read_file(path: &str) -> std::io::Result<String> {
     reader.read_to_string(&mut contents)?;
     Ok(contents)
 }
+
+/**
+ * Parses #RGB, #RGBA, #RRGGBB or #RRGGBBAA (the # is optional) into 0 to 1 rgba,
+ * alpha is 1 when left out. None for anything else
+*/
+pub fn parse_hex_color(s: &str) -> Option<glm::Vec4> {
+    let s = s.trim();
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let (digits, channels) = match hex.len() {
+        3 => (1, 3),
+        4 => (1, 4),
+        6 => (2, 3),
+        8 => (2, 4),
+        _ => return None
+    };
+    let channel = |i: usize| {
+        let v = u8::from_str_radix(&hex[i * digits..(i + 1) * digits], 16).ok()?;
+        // Single digits stand for the digit repeated, f is ff
+        Some(if digits == 1 { v * 17 } else { v } as f32 / 255.)
+    };
+    let alpha = if channels == 4 { channel(3)? } else { 1. };
+    Some(glm::vec4(channel(0)?, channel(1)?, channel(2)?, alpha))
+}
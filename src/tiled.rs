@@ -0,0 +1,1024 @@
+use crate::graphics::Texture;
+use crate::sys::{Quad, Rectui, parse_hex_color, read_file};
+use crate::tilemap::{Tile, TileFrame, TileMap, Tileset, apply_tile_flips};
+use crate::vertex::*;
+use flate2::read::{GzDecoder, ZlibDecoder};
+use nalgebra_glm as glm;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+use std::rc::Rc;
+
+/**
+ * Custom property set in the Tiled editor. File paths are resolved relative to the file they came from
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropertyValue {
+    String(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Color(glm::Vec4),
+    File(String),
+    /**
+     * Id of an object in the map, 0 for none
+    */
+    Object(u32),
+    Class(Properties),
+}
+
+pub type Properties = HashMap<String, PropertyValue>;
+
+/**
+ * Tiled's 120 degree rotation bit for hexagonal maps. Not supported, but it has to be masked off the gid
+*/
+const ROTATED_HEXAGONAL_120: u32 = 0x1000_0000;
+const GID_FLAGS: u32 = Tile::FLAGS | ROTATED_HEXAGONAL_120;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TiledTile {
+    /**
+     * Local id in the tileset
+    */
+    pub id: u32,
+    pub class: String,
+    pub properties: Properties,
+    pub animation: Vec<TileFrame>,
+    /**
+     * Only set in image collection tilesets, where every tile has its own image
+    */
+    pub image: Option<String>,
+    pub image_width: u32,
+    pub image_height: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TiledTileset {
+    /**
+     * Global id of the tileset's first tile, layers and tile objects refer to tiles by global id
+    */
+    pub first_gid: u32,
+    pub name: String,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub spacing: u32,
+    pub margin: u32,
+    pub tile_count: u32,
+    pub columns: u32,
+    /**
+     * None for image collection tilesets
+    */
+    pub image: Option<String>,
+    pub image_width: u32,
+    pub image_height: u32,
+    /**
+     * Only tiles that have properties, animations or their own image are listed
+    */
+    pub tiles: Vec<TiledTile>,
+    pub properties: Properties,
+    /**
+     * Path of the external .tsx / .tsj file, None when embedded in the map
+    */
+    pub source: Option<String>,
+}
+
+impl TiledTileset {
+    pub fn tile(&self, id: u32) -> Option<&TiledTile> {
+        self.tiles.iter().find(|t| t.id == id)
+    }
+
+    /**
+     * Pixel rect of a tile in its image with origin at the top left
+    */
+    pub fn tile_rect(&self, id: u32) -> Option<Rectui> {
+        if self.image.is_none() {
+            return self.tile(id).map(|t| Rectui::new(0, 0, t.image_width, t.image_height));
+        }
+        if self.columns == 0 || id >= self.tile_count {
+            return None;
+        }
+        let (col, row) = (id % self.columns, id / self.columns);
+        Some(Rectui::new(
+            self.margin + col * (self.tile_width + self.spacing),
+            self.margin + row * (self.tile_height + self.spacing),
+            self.tile_width,
+            self.tile_height,
+        ))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObjectShape {
+    Rectangle,
+    Ellipse,
+    Point,
+    /**
+     * Points relative to the object's position, in pixels with y down
+    */
+    Polygon(Vec<glm::Vec2>),
+    Polyline(Vec<glm::Vec2>),
+    Text(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TiledObject {
+    pub id: u32,
+    pub name: String,
+    pub class: String,
+    /**
+     * Pixels with origin at the top left of the map and y down, like the editor.
+     * Tile objects are positioned by their bottom left corner, everything else by the top left
+    */
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    /**
+     * Degrees clockwise
+    */
+    pub rotation: f32,
+    pub visible: bool,
+    /**
+     * Global tile id with flip flags in the top bits (see Tile), 0 when this isn't a tile object
+    */
+    pub gid: u32,
+    pub shape: ObjectShape,
+    pub properties: Properties,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TiledLayerKind {
+    /**
+     * Global tile ids with flip flags in row order, 0 for empty
+    */
+    Tiles { width: u32, height: u32, data: Vec<u32> },
+    Objects(Vec<TiledObject>),
+    Image { image: Option<String>, repeat_x: bool, repeat_y: bool },
+    Group(Vec<TiledLayer>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TiledLayer {
+    pub id: u32,
+    pub name: String,
+    pub class: String,
+    pub visible: bool,
+    pub opacity: f32,
+    /**
+     * Pixels, y down
+    */
+    pub offset: glm::Vec2,
+    pub parallax: glm::Vec2,
+    pub tint: Option<glm::Vec4>,
+    pub properties: Properties,
+    pub kind: TiledLayerKind,
+}
+
+/**
+ * Map made in the Tiled editor, loaded from TMX (XML) or TMJ (JSON) along with any external tilesets.
+ * Everything is kept in Tiled's units (pixels, y down); to_tile_map() and object_quad() convert
+ * to world space with y up and the map's bottom left corner at 0, 0
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct TiledMap {
+    pub orientation: String,
+    pub width: u32,
+    pub height: u32,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub background_color: Option<glm::Vec4>,
+    pub tilesets: Vec<TiledTileset>,
+    pub layers: Vec<TiledLayer>,
+    pub properties: Properties,
+}
+
+impl TiledMap {
+    /**
+     * Picks the format from the extension, .tmx for XML and .tmj or .json for JSON
+    */
+    pub fn from_file(filename: &str) -> Result<Self, String> {
+        let contents = read_file(filename).map_err(|e| format!("Error loading file: {} :: {}", filename, e))?;
+        let dir = parent_dir(filename);
+        let ext = Path::new(filename).extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+
+        match ext.as_str() {
+            "tmx" | "xml" => TiledMap::from_tmx(&contents, &dir),
+            "tmj" | "json" => TiledMap::from_json(&contents, &dir),
+            _ => Err(format!("TiledError: unknown map extension '{}'", ext))
+        }.map_err(|e| format!("Error loading file: {} :: {}", filename, e))
+    }
+
+    /**
+     * base_dir is where external tilesets and images are looked up
+    */
+    pub fn from_tmx(xml: &str, base_dir: &str) -> Result<Self, String> {
+        let doc = roxmltree::Document::parse(xml).map_err(|e| format!("TiledError: {}", e))?;
+        let root = doc.root_element();
+        if !root.has_tag_name("map") {
+            return Err("TiledError: root element is not <map>".into());
+        }
+        if attr_bool(root, "infinite", false) {
+            return Err("TiledError: infinite maps are not supported".into());
+        }
+
+        let mut tilesets = Vec::new();
+        for node in root.children().filter(|n| n.has_tag_name("tileset")) {
+            let first_gid = attr_u32(node, "firstgid");
+            tilesets.push(match node.attribute("source") {
+                Some(source) => load_external_tileset(base_dir, source, first_gid)?,
+                None => tmx_tileset(node, first_gid, base_dir)?
+            });
+        }
+
+        check_map_size(attr_u32(root, "width"), attr_u32(root, "height"), attr_u32(root, "tilewidth"), attr_u32(root, "tileheight"))?;
+        Ok(TiledMap {
+            orientation: root.attribute("orientation").unwrap_or("orthogonal").to_string(),
+            width: attr_u32(root, "width"),
+            height: attr_u32(root, "height"),
+            tile_width: attr_u32(root, "tilewidth"),
+            tile_height: attr_u32(root, "tileheight"),
+            background_color: root.attribute("backgroundcolor").and_then(parse_color),
+            tilesets,
+            layers: tmx_layers(root, base_dir)?,
+            properties: tmx_properties(root, base_dir)?,
+        })
+    }
+
+    pub fn from_json(json: &str, base_dir: &str) -> Result<Self, String> {
+        let root: Value = serde_json::from_str(json).map_err(|e| format!("TiledError: {}", e))?;
+        if json_bool(&root, "infinite", false) {
+            return Err("TiledError: infinite maps are not supported".into());
+        }
+
+        let mut tilesets = Vec::new();
+        for ts in json_array(&root, "tilesets") {
+            let first_gid = json_u32(ts, "firstgid");
+            tilesets.push(match ts.get("source").and_then(Value::as_str) {
+                Some(source) => load_external_tileset(base_dir, source, first_gid)?,
+                None => json_tileset(ts, first_gid, base_dir)?
+            });
+        }
+
+        check_map_size(json_u32(&root, "width"), json_u32(&root, "height"), json_u32(&root, "tilewidth"), json_u32(&root, "tileheight"))?;
+        Ok(TiledMap {
+            orientation: json_str(&root, "orientation").unwrap_or("orthogonal").to_string(),
+            width: json_u32(&root, "width"),
+            height: json_u32(&root, "height"),
+            tile_width: json_u32(&root, "tilewidth"),
+            tile_height: json_u32(&root, "tileheight"),
+            background_color: json_str(&root, "backgroundcolor").and_then(parse_color),
+            tilesets,
+            layers: json_layers(&root, base_dir)?,
+            properties: json_properties(&root, base_dir)?,
+        })
+    }
+
+    /**
+     * Tileset holding a global tile id (flags are ignored), with the tileset's index and the local id
+    */
+    pub fn tileset_for_gid(&self, gid: u32) -> Option<(usize, &TiledTileset, u32)> {
+        let id = gid & !GID_FLAGS;
+        if id == 0 {
+            return None;
+        }
+        // Tilesets are sorted by first_gid, so the last one starting at or before id holds it
+        self.tilesets.iter().enumerate().rev()
+            .find(|(_, t)| t.first_gid <= id)
+            .map(|(i, t)| (i, t, id - t.first_gid))
+    }
+
+    pub fn layer(&self, name: &str) -> Option<&TiledLayer> {
+        find_layer(&self.layers, name)
+    }
+
+    /**
+     * Height of the map in pixels, used to flip Tiled's y down coordinates
+    */
+    pub fn pixel_height(&self) -> f32 {
+        self.height as f32 * self.tile_height as f32
+    }
+
+    /**
+     * Tiled pixel position to world space (y up, map's bottom left at 0, 0)
+    */
+    pub fn to_world(&self, pos: glm::Vec2) -> glm::Vec2 {
+        glm::vec2(pos.x, self.pixel_height() - pos.y)
+    }
+
+    /**
+     * Loads every tileset image and builds a TileMap with one tile per pixel of the map's tile size.
+     * Group layers are flattened and layers using several tilesets are split into one layer per tileset,
+     * all keeping the Tiled layer's name. Tiles from image collection tilesets are skipped, draw those
+     * with object_quad() instead
+    */
+    pub fn to_tile_map(&self) -> Result<TileMap, String> {
+        if self.orientation != "orthogonal" {
+            return Err(format!("TiledError: {} maps are not supported, only orthogonal", self.orientation));
+        }
+
+        let mut map = TileMap::new(self.width, self.height, glm::vec2(self.tile_width as f32, self.tile_height as f32));
+        map.set_origin(glm::vec2(0., self.pixel_height()));
+
+        let mut indices = Vec::with_capacity(self.tilesets.len());
+        for ts in self.tilesets.iter() {
+            let image = match &ts.image {
+                Some(image) => image,
+                None => {
+                    indices.push(None);
+                    continue;
+                }
+            };
+            let mut tileset = Tileset::new(Rc::new(Texture::from_file(image)?), glm::vec2(ts.tile_width, ts.tile_height), ts.margin, ts.spacing)?;
+            for tile in ts.tiles.iter().filter(|t| !t.animation.is_empty()) {
+                tileset.add_animation(tile.id, tile.animation.clone());
+            }
+            indices.push(Some(map.add_tileset(tileset)));
+        }
+
+        self.add_tile_layers(&mut map, &self.layers, &indices, glm::vec2(0., 0.), 1., true)?;
+        Ok(map)
+    }
+
+    /**
+     * Quad for a tile object in world space, with texture coordinates in pixels of its tileset image
+     * (normalize_texture_coords() them with the texture's size). Also returns the index into tilesets.
+     * None for objects without a tile
+    */
+    pub fn object_quad(&self, object: &TiledObject) -> Option<(usize, Quad)> {
+        let (index, tileset, id) = self.tileset_for_gid(object.gid)?;
+        let rect = tileset.tile_rect(id)?;
+        let (w, h) = if object.width > 0. && object.height > 0. {
+            (object.width, object.height)
+        } else {
+            (rect.w as f32, rect.h as f32)
+        };
+
+        // Tile objects sit on their bottom left corner and rotate clockwise around it
+        let origin = self.to_world(glm::vec2(object.x, object.y));
+        let (sin, cos) = (-object.rotation.to_radians()).sin_cos();
+        let corner = |x: f32, y: f32| origin + glm::vec2(x * cos - y * sin, x * sin + y * cos);
+        let corners = [corner(0., h), corner(0., 0.), corner(w, h), corner(w, 0.)];
+
+        let (x0, y0) = (rect.x as f32, rect.y as f32);
+        let (x1, y1) = ((rect.x + rect.w) as f32, (rect.y + rect.h) as f32);
+        let uv = |u: f32, v: f32| Vert2DTextureCoord { u, v };
+        let mut uvs = [uv(x0, y0), uv(x0, y1), uv(x1, y0), uv(x1, y1)];
+        apply_tile_flips(Tile(object.gid), &mut uvs);
+
+        let mut verts = Quad::default_verts();
+        for (i, v) in verts.iter_mut().enumerate() {
+            v.position = Vert2DPosition { x: corners[i].x, y: corners[i].y, z: 0. };
+            v.text_coord = uvs[i];
+        }
+        Some((index, Quad::with_verts(&verts)))
+    }
+
+    fn add_tile_layers(&self, map: &mut TileMap, layers: &[TiledLayer], indices: &[Option<usize>],
+        offset: glm::Vec2, opacity: f32, visible: bool) -> Result<(), String> {
+        for layer in layers.iter() {
+            let offset = offset + layer.offset;
+            let opacity = opacity * layer.opacity;
+            let visible = visible && layer.visible;
+
+            let (width, height, data) = match &layer.kind {
+                TiledLayerKind::Tiles { width, height, data } => (*width, *height, data),
+                TiledLayerKind::Group(children) => {
+                    self.add_tile_layers(map, children, indices, offset, opacity, visible)?;
+                    continue;
+                },
+                _ => continue
+            };
+            if width != self.width || height != self.height {
+                return Err(format!("TiledError: layer '{}' is {}x{} but the map is {}x{}", layer.name, width, height, self.width, self.height));
+            }
+
+            let tint = layer.tint.unwrap_or_else(|| glm::vec4(1., 1., 1., 1.));
+            let mut per_tileset: Vec<(usize, Vec<Tile>)> = Vec::new();
+            for (i, gid) in data.iter().enumerate() {
+                let (ts, id) = match self.tileset_for_gid(*gid) {
+                    Some((ts, _, id)) => (ts, id),
+                    None => continue
+                };
+                let target = match indices[ts] {
+                    Some(t) => t,
+                    None => continue
+                };
+                let tiles = match per_tileset.iter().position(|(t, _)| *t == target) {
+                    Some(p) => &mut per_tileset[p].1,
+                    None => {
+                        per_tileset.push((target, vec![Tile::EMPTY; data.len()]));
+                        &mut per_tileset.last_mut().unwrap().1
+                    }
+                };
+                tiles[i] = Tile((id + 1) | (gid & Tile::FLAGS));
+            }
+
+            for (tileset, tiles) in per_tileset {
+                let index = map.add_layer(&layer.name, tileset)?;
+                let l = map.layer_mut(index).unwrap();
+                l.set_tiles(&tiles)?;
+                l.set_visible(visible)
+                    .set_color(glm::vec4(tint.x, tint.y, tint.z, tint.w * opacity))
+                    .set_offset(glm::vec2(offset.x, -offset.y));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn find_layer<'a>(layers: &'a [TiledLayer], name: &str) -> Option<&'a TiledLayer> {
+    for layer in layers.iter() {
+        if layer.name == name {
+            return Some(layer);
+        }
+        if let TiledLayerKind::Group(children) = &layer.kind {
+            if let Some(found) = find_layer(children, name) {
+                return Some(found);
+            }
+        }
+    }
+    None
+}
+
+fn parent_dir(filename: &str) -> String {
+    Path::new(filename).parent().map(|p| p.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+fn resolve(base_dir: &str, path: &str) -> String {
+    Path::new(base_dir).join(path).to_string_lossy().into_owned()
+}
+
+/**
+ * Tiled writes colors as #RRGGBB or #AARRGGBB, alpha first
+*/
+fn parse_color(s: &str) -> Option<glm::Vec4> {
+    let c = parse_hex_color(s)?;
+    match s.trim().trim_start_matches('#').len() {
+        6 => Some(c),
+        8 => Some(glm::vec4(c.y, c.z, c.w, c.x)),
+        _ => None
+    }
+}
+
+fn load_external_tileset(base_dir: &str, source: &str, first_gid: u32) -> Result<TiledTileset, String> {
+    let filename = resolve(base_dir, source);
+    let contents = read_file(&filename).map_err(|e| format!("Error loading file: {} :: {}", filename, e))?;
+    let dir = parent_dir(&filename);
+
+    let mut tileset = if source.to_lowercase().ends_with(".tsx") {
+        let doc = roxmltree::Document::parse(&contents).map_err(|e| format!("Error loading file: {} :: TiledError: {}", filename, e))?;
+        tmx_tileset(doc.root_element(), first_gid, &dir)
+    } else {
+        let root: Value = serde_json::from_str(&contents).map_err(|e| format!("Error loading file: {} :: TiledError: {}", filename, e))?;
+        json_tileset(&root, first_gid, &dir)
+    }.map_err(|e| format!("Error loading file: {} :: {}", filename, e))?;
+
+    tileset.source = Some(filename);
+    Ok(tileset)
+}
+
+fn tile_count(width: u32, height: u32) -> Result<usize, String> {
+    width.checked_mul(height)
+        .map(|n| n as usize)
+        .ok_or_else(|| format!("TiledError: layer size {}x{} is too large", width, height))
+}
+
+/**
+ * Rejects maps whose size in pixels doesn't fit in a u32
+*/
+fn check_map_size(width: u32, height: u32, tile_width: u32, tile_height: u32) -> Result<(), String> {
+    let too_large = || format!("TiledError: map size {}x{} with {}x{} tiles is too large", width, height, tile_width, tile_height);
+    tile_count(width, height).map_err(|_| too_large())?;
+    width.checked_mul(tile_width).ok_or_else(too_large)?;
+    height.checked_mul(tile_height).ok_or_else(too_large)?;
+    Ok(())
+}
+
+/**
+ * Layer data as global ids. Encoding is csv or base64 (optionally zlib or gzip compressed),
+ * base64 stores each id as 4 little endian bytes
+*/
+fn decode_tile_data(text: &str, encoding: Option<&str>, compression: Option<&str>, expected: usize) -> Result<Vec<u32>, String> {
+    let data: Vec<u32> = match encoding {
+        Some("csv") => text.split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| s.parse::<u32>().map_err(|e| format!("TiledError: bad tile id '{}': {}", s, e)))
+            .collect::<Result<_, _>>()?,
+        Some("base64") => {
+            let compact: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+            let raw = base64::decode(&compact).map_err(|e| format!("TiledError: {}", e))?;
+            let bytes = match compression {
+                None | Some("") => raw,
+                Some("zlib") => inflate(ZlibDecoder::new(&raw[..]))?,
+                Some("gzip") => inflate(GzDecoder::new(&raw[..]))?,
+                Some(other) => return Err(format!("TiledError: unsupported compression '{}'", other))
+            };
+            if bytes.len() % 4 != 0 {
+                return Err(format!("TiledError: tile data is {} bytes, not a multiple of 4", bytes.len()));
+            }
+            bytes.chunks(4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect()
+        },
+        Some(other) => return Err(format!("TiledError: unsupported encoding '{}'", other)),
+        None => return Err("TiledError: tile data has no encoding".into())
+    };
+
+    if data.len() != expected {
+        return Err(format!("TiledError: expected {} tiles, found {}", expected, data.len()));
+    }
+    Ok(data)
+}
+
+fn inflate<R: Read>(mut decoder: R) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    decoder.read_to_end(&mut out).map_err(|e| format!("TiledError: {}", e))?;
+    Ok(out)
+}
+
+fn parse_points(s: &str) -> Vec<glm::Vec2> {
+    s.split_whitespace()
+        .filter_map(|p| {
+            let mut it = p.split(',').map(|n| n.trim().parse::<f32>());
+            match (it.next(), it.next()) {
+                (Some(Ok(x)), Some(Ok(y))) => Some(glm::vec2(x, y)),
+                _ => None
+            }
+        })
+        .collect()
+}
+
+fn property_value(kind: &str, value: &str, base_dir: &str) -> Result<PropertyValue, String> {
+    let bad = |e: &dyn std::fmt::Display| format!("TiledError: bad {} property '{}': {}", kind, value, e);
+    Ok(match kind {
+        "" | "string" => PropertyValue::String(value.to_string()),
+        "int" => PropertyValue::Int(value.parse().map_err(|e| bad(&e))?),
+        "float" => PropertyValue::Float(value.parse().map_err(|e| bad(&e))?),
+        "bool" => PropertyValue::Bool(value == "true"),
+        "color" => PropertyValue::Color(if value.is_empty() { glm::vec4(0., 0., 0., 0.) } else { parse_color(value).ok_or_else(|| bad(&"not a color"))? }),
+        "file" => PropertyValue::File(if value.is_empty() { String::new() } else { resolve(base_dir, value) }),
+        "object" => PropertyValue::Object(if value.is_empty() { 0 } else { value.parse().map_err(|e| bad(&e))? }),
+        other => return Err(format!("TiledError: unknown property type '{}'", other))
+    })
+}
+
+/*
+ * TMX (XML)
+*/
+
+fn attr_u32(node: roxmltree::Node, name: &str) -> u32 {
+    node.attribute(name).and_then(|v| v.parse().ok()).unwrap_or(0)
+}
+
+fn attr_f32(node: roxmltree::Node, name: &str, default: f32) -> f32 {
+    node.attribute(name).and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+fn attr_bool(node: roxmltree::Node, name: &str, default: bool) -> bool {
+    match node.attribute(name) {
+        Some(v) => v == "1" || v == "true",
+        None => default
+    }
+}
+
+fn attr_string(node: roxmltree::Node, name: &str) -> String {
+    node.attribute(name).unwrap_or("").to_string()
+}
+
+fn child<'a, 'input>(node: roxmltree::Node<'a, 'input>, name: &str) -> Option<roxmltree::Node<'a, 'input>> {
+    node.children().find(|n| n.has_tag_name(name))
+}
+
+/**
+ * Properties of node, from its <properties> child
+*/
+fn tmx_properties(node: roxmltree::Node, base_dir: &str) -> Result<Properties, String> {
+    let mut props = Properties::new();
+    let list = match child(node, "properties") {
+        Some(p) => p,
+        None => return Ok(props)
+    };
+
+    for p in list.children().filter(|n| n.has_tag_name("property")) {
+        let name = attr_string(p, "name");
+        let kind = p.attribute("type").unwrap_or("string");
+        let value = if kind == "class" {
+            PropertyValue::Class(tmx_properties(p, base_dir)?)
+        } else {
+            // Multi line strings are written as text instead of the value attribute
+            let text = p.attribute("value").or_else(|| p.text()).unwrap_or("");
+            property_value(kind, text, base_dir)?
+        };
+        props.insert(name, value);
+    }
+    Ok(props)
+}
+
+fn tmx_tileset(node: roxmltree::Node, first_gid: u32, base_dir: &str) -> Result<TiledTileset, String> {
+    if !node.has_tag_name("tileset") {
+        return Err("TiledError: expected a <tileset> element".into());
+    }
+
+    let image = child(node, "image");
+    let mut tiles = Vec::new();
+    for t in node.children().filter(|n| n.has_tag_name("tile")) {
+        let animation = child(t, "animation")
+            .map(|a| a.children()
+                .filter(|n| n.has_tag_name("frame"))
+                .map(|f| TileFrame { index: attr_u32(f, "tileid"), duration: attr_u32(f, "duration") as f32 / 1000. })
+                .collect())
+            .unwrap_or_default();
+        let tile_image = child(t, "image");
+
+        tiles.push(TiledTile {
+            id: attr_u32(t, "id"),
+            class: t.attribute("class").or_else(|| t.attribute("type")).unwrap_or("").to_string(),
+            properties: tmx_properties(t, base_dir)?,
+            animation,
+            image: tile_image.and_then(|i| i.attribute("source")).map(|s| resolve(base_dir, s)),
+            image_width: tile_image.map(|i| attr_u32(i, "width")).unwrap_or(0),
+            image_height: tile_image.map(|i| attr_u32(i, "height")).unwrap_or(0),
+        });
+    }
+
+    Ok(TiledTileset {
+        first_gid,
+        name: attr_string(node, "name"),
+        tile_width: attr_u32(node, "tilewidth"),
+        tile_height: attr_u32(node, "tileheight"),
+        spacing: attr_u32(node, "spacing"),
+        margin: attr_u32(node, "margin"),
+        tile_count: attr_u32(node, "tilecount"),
+        columns: attr_u32(node, "columns"),
+        image: image.and_then(|i| i.attribute("source")).map(|s| resolve(base_dir, s)),
+        image_width: image.map(|i| attr_u32(i, "width")).unwrap_or(0),
+        image_height: image.map(|i| attr_u32(i, "height")).unwrap_or(0),
+        tiles,
+        properties: tmx_properties(node, base_dir)?,
+        source: None,
+    })
+}
+
+fn tmx_layers(node: roxmltree::Node, base_dir: &str) -> Result<Vec<TiledLayer>, String> {
+    let mut layers = Vec::new();
+    for n in node.children().filter(|n| n.is_element()) {
+        let kind = match n.tag_name().name() {
+            "layer" => {
+                let (width, height) = (attr_u32(n, "width"), attr_u32(n, "height"));
+                let data = child(n, "data").ok_or_else(|| format!("TiledError: layer '{}' has no data", attr_string(n, "name")))?;
+                let expected = tile_count(width, height)?;
+                let tiles = match data.attribute("encoding") {
+                    // Plain XML, one <tile gid=""> per tile
+                    None => {
+                        let tiles: Vec<u32> = data.children().filter(|t| t.has_tag_name("tile")).map(|t| attr_u32(t, "gid")).collect();
+                        if tiles.len() != expected {
+                            return Err(format!("TiledError: expected {} tiles, found {}", expected, tiles.len()));
+                        }
+                        tiles
+                    },
+                    encoding => decode_tile_data(data.text().unwrap_or(""), encoding, data.attribute("compression"), expected)?
+                };
+                TiledLayerKind::Tiles { width, height, data: tiles }
+            },
+            "objectgroup" => TiledLayerKind::Objects(
+                n.children().filter(|o| o.has_tag_name("object")).map(|o| tmx_object(o, base_dir)).collect::<Result<_, _>>()?
+            ),
+            "imagelayer" => TiledLayerKind::Image {
+                image: child(n, "image").and_then(|i| i.attribute("source")).map(|s| resolve(base_dir, s)),
+                repeat_x: attr_bool(n, "repeatx", false),
+                repeat_y: attr_bool(n, "repeaty", false),
+            },
+            "group" => TiledLayerKind::Group(tmx_layers(n, base_dir)?),
+            _ => continue
+        };
+
+        layers.push(TiledLayer {
+            id: attr_u32(n, "id"),
+            name: attr_string(n, "name"),
+            class: attr_string(n, "class"),
+            visible: attr_bool(n, "visible", true),
+            opacity: attr_f32(n, "opacity", 1.),
+            offset: glm::vec2(attr_f32(n, "offsetx", 0.), attr_f32(n, "offsety", 0.)),
+            parallax: glm::vec2(attr_f32(n, "parallaxx", 1.), attr_f32(n, "parallaxy", 1.)),
+            tint: n.attribute("tintcolor").and_then(parse_color),
+            properties: tmx_properties(n, base_dir)?,
+            kind,
+        });
+    }
+    Ok(layers)
+}
+
+fn tmx_object(node: roxmltree::Node, base_dir: &str) -> Result<TiledObject, String> {
+    let shape = if child(node, "ellipse").is_some() {
+        ObjectShape::Ellipse
+    } else if child(node, "point").is_some() {
+        ObjectShape::Point
+    } else if let Some(p) = child(node, "polygon") {
+        ObjectShape::Polygon(parse_points(p.attribute("points").unwrap_or("")))
+    } else if let Some(p) = child(node, "polyline") {
+        ObjectShape::Polyline(parse_points(p.attribute("points").unwrap_or("")))
+    } else if let Some(t) = child(node, "text") {
+        ObjectShape::Text(t.text().unwrap_or("").to_string())
+    } else {
+        ObjectShape::Rectangle
+    };
+
+    Ok(TiledObject {
+        id: attr_u32(node, "id"),
+        name: attr_string(node, "name"),
+        class: node.attribute("class").or_else(|| node.attribute("type")).unwrap_or("").to_string(),
+        x: attr_f32(node, "x", 0.),
+        y: attr_f32(node, "y", 0.),
+        width: attr_f32(node, "width", 0.),
+        height: attr_f32(node, "height", 0.),
+        rotation: attr_f32(node, "rotation", 0.),
+        visible: attr_bool(node, "visible", true),
+        gid: attr_u32(node, "gid"),
+        shape,
+        properties: tmx_properties(node, base_dir)?,
+    })
+}
+
+/*
+ * TMJ (JSON)
+*/
+
+fn json_u32(v: &Value, key: &str) -> u32 {
+    v.get(key).and_then(Value::as_u64).unwrap_or(0) as u32
+}
+
+fn json_f32(v: &Value, key: &str, default: f32) -> f32 {
+    v.get(key).and_then(Value::as_f64).map(|f| f as f32).unwrap_or(default)
+}
+
+fn json_bool(v: &Value, key: &str, default: bool) -> bool {
+    v.get(key).and_then(Value::as_bool).unwrap_or(default)
+}
+
+fn json_str<'a>(v: &'a Value, key: &str) -> Option<&'a str> {
+    v.get(key).and_then(Value::as_str)
+}
+
+fn json_array<'a>(v: &'a Value, key: &str) -> &'a [Value] {
+    v.get(key).and_then(Value::as_array).map(|a| a.as_slice()).unwrap_or(&[])
+}
+
+/**
+ * Class (and older type) name, whichever is present
+*/
+fn json_class(v: &Value) -> String {
+    json_str(v, "class").or_else(|| json_str(v, "type")).unwrap_or("").to_string()
+}
+
+fn json_properties(v: &Value, base_dir: &str) -> Result<Properties, String> {
+    let mut props = Properties::new();
+    for p in json_array(v, "properties") {
+        let name = json_str(p, "name").unwrap_or("").to_string();
+        let kind = json_str(p, "type").unwrap_or("string");
+        let value = p.get("value").unwrap_or(&Value::Null);
+        props.insert(name, json_property_value(kind, value, base_dir)?);
+    }
+    Ok(props)
+}
+
+fn json_property_value(kind: &str, value: &Value, base_dir: &str) -> Result<PropertyValue, String> {
+    Ok(match (kind, value) {
+        ("class", Value::Object(members)) => {
+            // Class members carry no type, so it's guessed from the JSON value
+            let mut props = Properties::new();
+            for (name, v) in members.iter() {
+                let guessed = match v {
+                    Value::Bool(_) => "bool",
+                    Value::Number(n) if n.is_i64() || n.is_u64() => "int",
+                    Value::Number(_) => "float",
+                    Value::Object(_) => "class",
+                    _ => "string"
+                };
+                props.insert(name.clone(), json_property_value(guessed, v, base_dir)?);
+            }
+            PropertyValue::Class(props)
+        },
+        ("class", _) => PropertyValue::Class(Properties::new()),
+        (_, Value::String(s)) => property_value(kind, s, base_dir)?,
+        (_, Value::Null) => property_value(kind, "", base_dir)?,
+        (_, other) => property_value(kind, &other.to_string(), base_dir)?,
+    })
+}
+
+fn json_tileset(v: &Value, first_gid: u32, base_dir: &str) -> Result<TiledTileset, String> {
+    let mut tiles = Vec::new();
+    for t in json_array(v, "tiles") {
+        let animation = json_array(t, "animation").iter()
+            .map(|f| TileFrame { index: json_u32(f, "tileid"), duration: json_u32(f, "duration") as f32 / 1000. })
+            .collect();
+        tiles.push(TiledTile {
+            id: json_u32(t, "id"),
+            class: json_class(t),
+            properties: json_properties(t, base_dir)?,
+            animation,
+            image: json_str(t, "image").map(|s| resolve(base_dir, s)),
+            image_width: json_u32(t, "imagewidth"),
+            image_height: json_u32(t, "imageheight"),
+        });
+    }
+
+    Ok(TiledTileset {
+        first_gid,
+        name: json_str(v, "name").unwrap_or("").to_string(),
+        tile_width: json_u32(v, "tilewidth"),
+        tile_height: json_u32(v, "tileheight"),
+        spacing: json_u32(v, "spacing"),
+        margin: json_u32(v, "margin"),
+        tile_count: json_u32(v, "tilecount"),
+        columns: json_u32(v, "columns"),
+        image: json_str(v, "image").map(|s| resolve(base_dir, s)),
+        image_width: json_u32(v, "imagewidth"),
+        image_height: json_u32(v, "imageheight"),
+        tiles,
+        properties: json_properties(v, base_dir)?,
+        source: None,
+    })
+}
+
+fn json_layers(v: &Value, base_dir: &str) -> Result<Vec<TiledLayer>, String> {
+    let mut layers = Vec::new();
+    for l in json_array(v, "layers") {
+        let kind = match json_str(l, "type").unwrap_or("") {
+            "tilelayer" => {
+                let (width, height) = (json_u32(l, "width"), json_u32(l, "height"));
+                let expected = tile_count(width, height)?;
+                let data = match l.get("data") {
+                    Some(Value::Array(ids)) => {
+                        let ids: Vec<u32> = ids.iter().map(|id| id.as_u64().unwrap_or(0) as u32).collect();
+                        if ids.len() != expected {
+                            return Err(format!("TiledError: expected {} tiles, found {}", expected, ids.len()));
+                        }
+                        ids
+                    },
+                    Some(Value::String(s)) => decode_tile_data(s, Some(json_str(l, "encoding").unwrap_or("base64")), json_str(l, "compression"), expected)?,
+                    _ => return Err(format!("TiledError: layer '{}' has no data", json_str(l, "name").unwrap_or("")))
+                };
+                TiledLayerKind::Tiles { width, height, data }
+            },
+            "objectgroup" => TiledLayerKind::Objects(
+                json_array(l, "objects").iter().map(|o| json_object(o, base_dir)).collect::<Result<_, _>>()?
+            ),
+            "imagelayer" => TiledLayerKind::Image {
+                image: json_str(l, "image").filter(|s| !s.is_empty()).map(|s| resolve(base_dir, s)),
+                repeat_x: json_bool(l, "repeatx", false),
+                repeat_y: json_bool(l, "repeaty", false),
+            },
+            "group" => TiledLayerKind::Group(json_layers(l, base_dir)?),
+            _ => continue
+        };
+
+        layers.push(TiledLayer {
+            id: json_u32(l, "id"),
+            name: json_str(l, "name").unwrap_or("").to_string(),
+            class: json_str(l, "class").unwrap_or("").to_string(),
+            visible: json_bool(l, "visible", true),
+            opacity: json_f32(l, "opacity", 1.),
+            offset: glm::vec2(json_f32(l, "offsetx", 0.), json_f32(l, "offsety", 0.)),
+            parallax: glm::vec2(json_f32(l, "parallaxx", 1.), json_f32(l, "parallaxy", 1.)),
+            tint: json_str(l, "tintcolor").and_then(parse_color),
+            properties: json_properties(l, base_dir)?,
+            kind,
+        });
+    }
+    Ok(layers)
+}
+
+fn json_object(v: &Value, base_dir: &str) -> Result<TiledObject, String> {
+    let points = |key: &str| json_array(v, key).iter()
+        .map(|p| glm::vec2(json_f32(p, "x", 0.), json_f32(p, "y", 0.)))
+        .collect();
+
+    let shape = if json_bool(v, "ellipse", false) {
+        ObjectShape::Ellipse
+    } else if json_bool(v, "point", false) {
+        ObjectShape::Point
+    } else if v.get("polygon").is_some() {
+        ObjectShape::Polygon(points("polygon"))
+    } else if v.get("polyline").is_some() {
+        ObjectShape::Polyline(points("polyline"))
+    } else if let Some(t) = v.get("text") {
+        ObjectShape::Text(json_str(t, "text").unwrap_or("").to_string())
+    } else {
+        ObjectShape::Rectangle
+    };
+
+    Ok(TiledObject {
+        id: json_u32(v, "id"),
+        name: json_str(v, "name").unwrap_or("").to_string(),
+        class: json_class(v),
+        x: json_f32(v, "x", 0.),
+        y: json_f32(v, "y", 0.),
+        width: json_f32(v, "width", 0.),
+        height: json_f32(v, "height", 0.),
+        rotation: json_f32(v, "rotation", 0.),
+        visible: json_bool(v, "visible", true),
+        gid: json_u32(v, "gid"),
+        shape,
+        properties: json_properties(v, base_dir)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::{GzEncoder, ZlibEncoder};
+    use flate2::Compression;
+    use std::io::Write;
+
+    const IDS: [u32; 4] = [1, 2, 0, 0x8000_0003];
+
+    fn le_bytes() -> Vec<u8> {
+        IDS.iter().flat_map(|id| id.to_le_bytes()).collect()
+    }
+
+    fn compress<W: Write>(mut encoder: W) -> W {
+        encoder.write_all(&le_bytes()).unwrap();
+        encoder
+    }
+
+    fn tmx(data: &str) -> String {
+        format!(r#"<map orientation="orthogonal" width="2" height="2" tilewidth="16" tileheight="16">
+            <tileset firstgid="1" name="a" tilewidth="16" tileheight="16" tilecount="4" columns="2"/>
+            <tileset firstgid="5" name="b" tilewidth="16" tileheight="16" tilecount="4" columns="2"/>
+            <layer id="1" name="ground" width="2" height="2">{}</layer>
+        </map>"#, data)
+    }
+
+    fn ground(map: &TiledMap) -> Vec<u32> {
+        match &map.layer("ground").unwrap().kind {
+            TiledLayerKind::Tiles { data, .. } => data.clone(),
+            _ => panic!("ground is not a tile layer")
+        }
+    }
+
+    #[test]
+    fn decodes_csv() {
+        let data = decode_tile_data(" 1,2,\n0,2147483651 ", Some("csv"), None, 4).unwrap();
+        assert_eq!(data, IDS);
+        assert!(decode_tile_data("1,x,0,0", Some("csv"), None, 4).is_err());
+        assert!(decode_tile_data("1,2,0", Some("csv"), None, 4).is_err());
+    }
+
+    #[test]
+    fn decodes_base64() {
+        let text = format!("\n  {}\n", base64::encode(le_bytes()));
+        assert_eq!(decode_tile_data(&text, Some("base64"), None, 4).unwrap(), IDS);
+        assert!(decode_tile_data(&base64::encode([1u8, 2, 3]), Some("base64"), None, 1).is_err());
+    }
+
+    #[test]
+    fn decodes_zlib_and_gzip() {
+        let zlib = base64::encode(compress(ZlibEncoder::new(Vec::new(), Compression::default())).finish().unwrap());
+        let gzip = base64::encode(compress(GzEncoder::new(Vec::new(), Compression::default())).finish().unwrap());
+        assert_eq!(decode_tile_data(&zlib, Some("base64"), Some("zlib"), 4).unwrap(), IDS);
+        assert_eq!(decode_tile_data(&gzip, Some("base64"), Some("gzip"), 4).unwrap(), IDS);
+        assert!(decode_tile_data(&zlib, Some("base64"), Some("zstd"), 4).is_err());
+    }
+
+    #[test]
+    fn loads_tmx_and_json_layers() {
+        let map = TiledMap::from_tmx(&tmx(r#"<data encoding="csv">1,2,0,2147483651</data>"#), "").unwrap();
+        assert_eq!(ground(&map), IDS);
+
+        let xml = tmx(r#"<data><tile gid="1"/><tile gid="2"/><tile/><tile gid="2147483651"/></data>"#);
+        assert_eq!(ground(&TiledMap::from_tmx(&xml, "").unwrap()), IDS);
+
+        let json = r#"{ "width": 2, "height": 2, "tilewidth": 16, "tileheight": 16, "tilesets": [],
+            "layers": [{ "type": "tilelayer", "name": "ground", "width": 2, "height": 2, "data": [1, 2, 0, 2147483651] }] }"#;
+        assert_eq!(ground(&TiledMap::from_json(json, "").unwrap()), IDS);
+    }
+
+    #[test]
+    fn oversized_maps_are_errors() {
+        let xml = r#"<map width="65536" height="65536" tilewidth="16" tileheight="16"></map>"#;
+        assert!(TiledMap::from_tmx(xml, "").unwrap_err().starts_with("TiledError"));
+
+        let json = r#"{ "width": 2, "height": 2, "tilewidth": 16, "tileheight": 16, "tilesets": [],
+            "layers": [{ "type": "tilelayer", "name": "ground", "width": 65536, "height": 65536, "data": [] }] }"#;
+        assert!(TiledMap::from_json(json, "").unwrap_err().starts_with("TiledError"));
+    }
+
+    #[test]
+    fn colors_put_alpha_first() {
+        assert_eq!(parse_color("#ff0000"), Some(glm::vec4(1., 0., 0., 1.)));
+        assert_eq!(parse_color("#00ff0000"), Some(glm::vec4(1., 0., 0., 0.)));
+        assert_eq!(parse_color("#f00"), None);
+        assert_eq!(parse_color("#ñ00000"), None);
+    }
+
+    #[test]
+    fn tileset_for_gid_ignores_flags() {
+        let map = TiledMap::from_tmx(&tmx(r#"<data encoding="csv">1,2,0,3</data>"#), "").unwrap();
+        let local = |gid: u32| map.tileset_for_gid(gid).map(|(i, _, id)| (i, id));
+        assert_eq!(local(0), None);
+        assert_eq!(local(3), Some((0, 2)));
+        assert_eq!(local(6 | Tile::FLIP_X | Tile::FLIP_DIAGONAL), Some((1, 1)));
+        assert_eq!(local(6 | ROTATED_HEXAGONAL_120), Some((1, 1)));
+        assert_eq!(local(ROTATED_HEXAGONAL_120), None);
+    }
+}
@@ -0,0 +1,631 @@
+use crate::Renderable;
+use crate::Renderer;
+use crate::buffers::{DrawUsage, VertexBuffer};
+use crate::graphics::Texture;
+use crate::sys::{Rectf, Rectui};
+use crate::vertex::*;
+use nalgebra_glm as glm;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+pub const DEFAULT_CHUNK_SIZE: u32 = 16;
+
+/**
+ * Tile in a layer. 0 is empty, otherwise the low bits hold the tileset index + 1 and the top three
+ * bits are flip flags, laid out the same as Tiled's global tile ids
+*/
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Tile(pub u32);
+
+impl Tile {
+    pub const EMPTY: Tile = Tile(0);
+    pub const FLIP_X: u32 = 0x8000_0000;
+    pub const FLIP_Y: u32 = 0x4000_0000;
+    /**
+     * Swaps x and y (transpose), applied before FLIP_X and FLIP_Y. Together they give 90 degree rotations
+    */
+    pub const FLIP_DIAGONAL: u32 = 0x2000_0000;
+    pub const FLAGS: u32 = Self::FLIP_X | Self::FLIP_Y | Self::FLIP_DIAGONAL;
+
+    /**
+     * Tile showing tileset index (0 based)
+    */
+    pub fn new(index: u32) -> Self {
+        Tile((index + 1) & !Self::FLAGS)
+    }
+
+    pub fn is_empty(self) -> bool { self.0 & !Self::FLAGS == 0 }
+
+    /**
+     * Tileset index, None for empty tiles
+    */
+    pub fn index(self) -> Option<u32> {
+        match self.0 & !Self::FLAGS {
+            0 => None,
+            id => Some(id - 1)
+        }
+    }
+
+    pub fn flip_x(self) -> bool { self.0 & Self::FLIP_X != 0 }
+    pub fn flip_y(self) -> bool { self.0 & Self::FLIP_Y != 0 }
+    pub fn flip_diagonal(self) -> bool { self.0 & Self::FLIP_DIAGONAL != 0 }
+
+    pub fn with_flip(self, flip_x: bool, flip_y: bool, flip_diagonal: bool) -> Self {
+        let mut raw = self.0 & !Self::FLAGS;
+        if flip_x { raw |= Self::FLIP_X; }
+        if flip_y { raw |= Self::FLIP_Y; }
+        if flip_diagonal { raw |= Self::FLIP_DIAGONAL; }
+        Tile(raw)
+    }
+
+    /**
+     * Same tile turned 90 degrees clockwise on screen
+    */
+    pub fn rotated_cw(self) -> Self {
+        self.with_flip(!self.flip_y(), self.flip_x(), !self.flip_diagonal())
+    }
+
+    pub fn rotated_ccw(self) -> Self {
+        self.with_flip(self.flip_y(), !self.flip_x(), !self.flip_diagonal())
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TileFrame {
+    /**
+     * Tileset index shown during this frame
+    */
+    pub index: u32,
+    /**
+     * Seconds
+    */
+    pub duration: f32,
+}
+
+/**
+ * Texture cut into a grid of equally sized tiles. Indices run left to right, top to bottom
+*/
+pub struct Tileset {
+    texture: Rc<Texture>,
+    tile_size: glm::TVec2<u32>,
+    margin: u32,
+    spacing: u32,
+    columns: u32,
+    tile_count: u32,
+    animations: HashMap<u32, Vec<TileFrame>>,
+}
+
+impl Tileset {
+    /**
+     * margin is the border around the whole grid and spacing the gap between tiles, both in pixels
+    */
+    pub fn new(texture: Rc<Texture>, tile_size: glm::TVec2<u32>, margin: u32, spacing: u32) -> Result<Self, String> {
+        if tile_size.x == 0 || tile_size.y == 0 {
+            return Err("TileMapError: tile size must be non zero".to_string());
+        }
+        let grid = |size: u32, tile: u32| (size.saturating_sub(margin * 2) + spacing) / (tile + spacing);
+        let columns = grid(texture.size.x, tile_size.x);
+        let rows = grid(texture.size.y, tile_size.y);
+
+        Ok(Tileset { texture, tile_size, margin, spacing, columns, tile_count: columns * rows, animations: HashMap::new() })
+    }
+
+    pub fn texture(&self) -> &Rc<Texture> { &self.texture }
+    pub fn tile_size(&self) -> glm::TVec2<u32> { self.tile_size }
+    pub fn columns(&self) -> u32 { self.columns }
+    pub fn tile_count(&self) -> u32 { self.tile_count }
+    pub fn animations(&self) -> &HashMap<u32, Vec<TileFrame>> { &self.animations }
+
+    /**
+     * Pixel rect of the tile with origin at the top left, None when index is past the last tile
+    */
+    pub fn tile_rect(&self, index: u32) -> Option<Rectui> {
+        if index >= self.tile_count {
+            return None;
+        }
+        let (col, row) = (index % self.columns, index / self.columns);
+        Some(Rectui::new(
+            self.margin + col * (self.tile_size.x + self.spacing),
+            self.margin + row * (self.tile_size.y + self.spacing),
+            self.tile_size.x,
+            self.tile_size.y,
+        ))
+    }
+
+    /**
+     * Every tile showing index cycles through frames, all in step with TileMap::update()
+    */
+    pub fn add_animation(&mut self, index: u32, frames: Vec<TileFrame>) -> &mut Self {
+        if frames.is_empty() {
+            self.animations.remove(&index);
+        } else {
+            self.animations.insert(index, frames);
+        }
+        self
+    }
+
+    /**
+     * Index actually shown for index at time seconds into the map's animation clock
+    */
+    pub fn animated_index(&self, index: u32, time: f32) -> u32 {
+        let frames = match self.animations.get(&index) {
+            Some(f) => f,
+            None => return index
+        };
+        let total: f32 = frames.iter().map(|f| f.duration.max(0.)).sum();
+        if total <= 0. {
+            return frames[0].index;
+        }
+
+        let mut t = time.rem_euclid(total);
+        for f in frames.iter() {
+            if t < f.duration {
+                return f.index;
+            }
+            t -= f.duration.max(0.);
+        }
+        frames[frames.len() - 1].index
+    }
+}
+
+struct TileChunk {
+    buffer: RefCell<Option<VertexBuffer>>,
+    dirty: Cell<bool>,
+    /**
+     * Holds at least one animated tile, so it's rebuilt whenever an animation changes frame
+    */
+    animated: Cell<bool>,
+}
+
+impl TileChunk {
+    fn new() -> Self {
+        TileChunk { buffer: RefCell::new(None), dirty: Cell::new(true), animated: Cell::new(false) }
+    }
+}
+
+/**
+ * Grid of tiles drawn with one tileset. Tiles are grouped into square chunks, each kept in its own
+ * static vertex buffer that is rebuilt only after one of its tiles changes
+*/
+pub struct TileLayer {
+    name: String,
+    tileset: usize,
+    width: u32,
+    height: u32,
+    chunk_size: u32,
+    tiles: Vec<Tile>,
+    chunks: Vec<TileChunk>,
+    visible: bool,
+    color: glm::Vec4,
+    offset: glm::Vec2,
+}
+
+impl TileLayer {
+    fn new(name: &str, tileset: usize, width: u32, height: u32, chunk_size: u32) -> Result<Self, String> {
+        let tile_count = width.checked_mul(height)
+            .ok_or_else(|| format!("TileMapError: layer '{}' size {}x{} is too large", name, width, height))?;
+        let chunk_count = chunks_along(width, chunk_size) * chunks_along(height, chunk_size);
+        Ok(TileLayer {
+            name: name.to_string(),
+            tileset,
+            width,
+            height,
+            chunk_size,
+            tiles: vec![Tile::EMPTY; tile_count as usize],
+            chunks: (0..chunk_count).map(|_| TileChunk::new()).collect(),
+            visible: true,
+            color: glm::vec4(1., 1., 1., 1.),
+            offset: glm::vec2(0., 0.),
+        })
+    }
+
+    pub fn name(&self) -> &str { &self.name }
+    pub fn tileset(&self) -> usize { self.tileset }
+    pub fn width(&self) -> u32 { self.width }
+    pub fn height(&self) -> u32 { self.height }
+    pub fn tiles(&self) -> &[Tile] { &self.tiles }
+    pub fn visible(&self) -> bool { self.visible }
+    pub fn color(&self) -> glm::Vec4 { self.color }
+    pub fn offset(&self) -> glm::Vec2 { self.offset }
+
+    /**
+     * Empty for coordinates outside the layer. Row 0 is the top row
+    */
+    pub fn tile(&self, x: u32, y: u32) -> Tile {
+        if x < self.width && y < self.height { self.tiles[(y * self.width + x) as usize] } else { Tile::EMPTY }
+    }
+
+    pub fn set_tile(&mut self, x: u32, y: u32, tile: Tile) -> &mut Self {
+        if x < self.width && y < self.height {
+            let i = (y * self.width + x) as usize;
+            if self.tiles[i] != tile {
+                self.tiles[i] = tile;
+                self.chunks[self.chunk_index(x, y)].dirty.set(true);
+            }
+        }
+        self
+    }
+
+    /**
+     * Replaces every tile, tiles must hold width * height entries in row order
+    */
+    pub fn set_tiles(&mut self, tiles: &[Tile]) -> Result<&mut Self, String> {
+        if tiles.len() != self.tiles.len() {
+            return Err(format!("TileMapError: layer '{}' needs {} tiles, got {}", self.name, self.tiles.len(), tiles.len()));
+        }
+        self.tiles.copy_from_slice(tiles);
+        Ok(self.invalidate())
+    }
+
+    pub fn fill(&mut self, tile: Tile) -> &mut Self {
+        self.tiles.iter_mut().for_each(|t| *t = tile);
+        self.invalidate()
+    }
+
+    pub fn set_visible(&mut self, visible: bool) -> &mut Self {
+        self.visible = visible;
+        self
+    }
+
+    /**
+     * Multiplied with the texture color, alpha fades the whole layer
+    */
+    pub fn set_color(&mut self, color: glm::Vec4) -> &mut Self {
+        if self.color != color {
+            self.color = color;
+            self.invalidate();
+        }
+        self
+    }
+
+    /**
+     * World space offset from the map's origin, for parallax or Tiled layer offsets
+    */
+    pub fn set_offset(&mut self, offset: glm::Vec2) -> &mut Self {
+        if self.offset != offset {
+            self.offset = offset;
+            self.invalidate();
+        }
+        self
+    }
+
+    fn chunks_x(&self) -> u32 { chunks_along(self.width, self.chunk_size) }
+
+    fn chunk_index(&self, x: u32, y: u32) -> usize {
+        ((y / self.chunk_size) * self.chunks_x() + x / self.chunk_size) as usize
+    }
+
+    fn invalidate(&mut self) -> &mut Self {
+        self.chunks.iter().for_each(|c| c.dirty.set(true));
+        self
+    }
+}
+
+/**
+ * Layers of tiles over one or more tilesets. origin is the world position of the map's top left
+ * corner and rows run downward from it (y up world space). Only chunks overlapping the camera's
+ * view are drawn. Call update() every frame for animated tiles
+*/
+pub struct TileMap {
+    width: u32,
+    height: u32,
+    tile_size: glm::Vec2,
+    origin: glm::Vec2,
+    chunk_size: u32,
+    tilesets: Vec<Tileset>,
+    layers: Vec<TileLayer>,
+    time: f32,
+}
+
+impl TileMap {
+    /**
+     * width and height are in tiles, tile_size is the world size of one tile
+    */
+    pub fn new(width: u32, height: u32, tile_size: glm::Vec2) -> Self {
+        TileMap::with_chunk_size(width, height, tile_size, DEFAULT_CHUNK_SIZE)
+    }
+
+    pub fn with_chunk_size(width: u32, height: u32, tile_size: glm::Vec2, chunk_size: u32) -> Self {
+        TileMap {
+            width,
+            height,
+            tile_size,
+            origin: glm::vec2(0., 0.),
+            chunk_size: chunk_size.max(1),
+            tilesets: Vec::new(),
+            layers: Vec::new(),
+            time: 0.,
+        }
+    }
+
+    pub fn width(&self) -> u32 { self.width }
+    pub fn height(&self) -> u32 { self.height }
+    pub fn tile_size(&self) -> glm::Vec2 { self.tile_size }
+    pub fn origin(&self) -> glm::Vec2 { self.origin }
+    pub fn chunk_size(&self) -> u32 { self.chunk_size }
+    pub fn tilesets(&self) -> &[Tileset] { &self.tilesets }
+    pub fn layers(&self) -> &[TileLayer] { &self.layers }
+
+    pub fn set_origin(&mut self, origin: glm::Vec2) -> &mut Self {
+        if self.origin != origin {
+            self.origin = origin;
+            self.layers.iter_mut().for_each(|l| { l.invalidate(); });
+        }
+        self
+    }
+
+    /**
+     * Returns the tileset's index for add_layer()
+    */
+    pub fn add_tileset(&mut self, tileset: Tileset) -> usize {
+        self.tilesets.push(tileset);
+        self.tilesets.len() - 1
+    }
+
+    pub fn tileset_mut(&mut self, index: usize) -> Option<&mut Tileset> {
+        // Animations may have changed, so anything animated has to be rebuilt
+        for l in self.layers.iter_mut().filter(|l| l.tileset == index) {
+            l.invalidate();
+        }
+        self.tilesets.get_mut(index)
+    }
+
+    /**
+     * Adds an empty layer on top of the others, returning its index
+    */
+    pub fn add_layer(&mut self, name: &str, tileset: usize) -> Result<usize, String> {
+        if tileset >= self.tilesets.len() {
+            return Err(format!("TileMapError: layer '{}' uses tileset {} but there are {}", name, tileset, self.tilesets.len()));
+        }
+        self.layers.push(TileLayer::new(name, tileset, self.width, self.height, self.chunk_size)?);
+        Ok(self.layers.len() - 1)
+    }
+
+    pub fn layer(&self, index: usize) -> Option<&TileLayer> { self.layers.get(index) }
+    pub fn layer_mut(&mut self, index: usize) -> Option<&mut TileLayer> { self.layers.get_mut(index) }
+
+    pub fn layer_by_name(&self, name: &str) -> Option<&TileLayer> {
+        self.layers.iter().find(|l| l.name == name)
+    }
+
+    pub fn layer_by_name_mut(&mut self, name: &str) -> Option<&mut TileLayer> {
+        self.layers.iter_mut().find(|l| l.name == name)
+    }
+
+    /**
+     * Tile coordinates under a world position, ignoring layer offsets
+    */
+    pub fn world_to_tile(&self, pos: glm::Vec2) -> Option<(u32, u32)> {
+        let x = ((pos.x - self.origin.x) / self.tile_size.x).floor();
+        let y = ((self.origin.y - pos.y) / self.tile_size.y).floor();
+        if x < 0. || y < 0. || x >= self.width as f32 || y >= self.height as f32 {
+            return None;
+        }
+        Some((x as u32, y as u32))
+    }
+
+    /**
+     * World rect covered by a tile, x, y at its bottom left
+    */
+    pub fn tile_rect(&self, x: u32, y: u32) -> Rectf {
+        Rectf::new(
+            self.origin.x + x as f32 * self.tile_size.x,
+            self.origin.y - (y + 1) as f32 * self.tile_size.y,
+            self.tile_size.x,
+            self.tile_size.y,
+        )
+    }
+
+    /**
+     * Advances the animation clock, rebuilding chunks whose animated tiles changed frame
+    */
+    pub fn update(&mut self, dt: f32) {
+        let (before, after) = (self.time, self.time + dt);
+        self.time = after;
+
+        for (i, tileset) in self.tilesets.iter().enumerate() {
+            let changed = tileset.animations.keys()
+                .any(|index| tileset.animated_index(*index, before) != tileset.animated_index(*index, after));
+            if !changed {
+                continue;
+            }
+            for layer in self.layers.iter().filter(|l| l.tileset == i) {
+                layer.chunks.iter().filter(|c| c.animated.get()).for_each(|c| c.dirty.set(true));
+            }
+        }
+    }
+
+    /**
+     * Draws the chunks overlapping view (world space), rebuilding any that changed
+    */
+    pub fn draw_in(&self, renderer: &Renderer, view: &Rectf) {
+        for layer in self.layers.iter().filter(|l| l.visible) {
+            let tileset = &self.tilesets[layer.tileset];
+            let chunks_x = layer.chunks_x();
+
+            for (i, chunk) in layer.chunks.iter().enumerate() {
+                let (cx, cy) = (i as u32 % chunks_x, i as u32 / chunks_x);
+                let bounds = self.chunk_rect(layer, cx, cy);
+                if bounds.intersection(view).is_none() {
+                    continue;
+                }
+
+                if chunk.dirty.get() {
+                    self.build_chunk(layer, tileset, cx, cy, chunk);
+                }
+                if let Some(buffer) = chunk.buffer.borrow().as_ref() {
+                    renderer.draw_buffer(buffer, 0, tileset.texture.as_ref());
+                }
+            }
+        }
+    }
+
+    fn chunk_rect(&self, layer: &TileLayer, cx: u32, cy: u32) -> Rectf {
+        let (x0, y0) = (cx * self.chunk_size, cy * self.chunk_size);
+        let (w, h) = ((x0 + self.chunk_size).min(layer.width) - x0, (y0 + self.chunk_size).min(layer.height) - y0);
+        let top_left = self.origin + layer.offset + glm::vec2(x0 as f32 * self.tile_size.x, -(y0 as f32) * self.tile_size.y);
+        Rectf::new(top_left.x, top_left.y - h as f32 * self.tile_size.y, w as f32 * self.tile_size.x, h as f32 * self.tile_size.y)
+    }
+
+    fn build_chunk(&self, layer: &TileLayer, tileset: &Tileset, cx: u32, cy: u32, chunk: &TileChunk) {
+        let (x0, y0) = (cx * self.chunk_size, cy * self.chunk_size);
+        let (x1, y1) = ((x0 + self.chunk_size).min(layer.width), (y0 + self.chunk_size).min(layer.height));
+        let size = glm::vec2(tileset.texture.size.x as f32, tileset.texture.size.y as f32);
+        let color = Vert2DColor::from(&layer.color);
+
+        let mut verts = Vec::with_capacity((self.chunk_size * self.chunk_size * 6) as usize);
+        let mut animated = false;
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let tile = layer.tile(x, y);
+                let index = match tile.index() {
+                    Some(i) => i,
+                    None => continue
+                };
+                animated |= tileset.animations.contains_key(&index);
+                let rect = match tileset.tile_rect(tileset.animated_index(index, self.time)) {
+                    Some(r) => r,
+                    None => continue
+                };
+
+                let r = self.tile_rect(x, y);
+                let (left, bottom) = (r.x + layer.offset.x, r.y + layer.offset.y);
+                let (right, top) = (left + r.w, bottom + r.h);
+                let uv = tile_uvs(tile, rect, size);
+                let vert = |x: f32, y: f32, uv: Vert2DTextureCoord| Vertex2D {
+                    position: Vert2DPosition { x, y, z: 0. }, text_coord: uv, color
+                };
+
+                let (tl, bl) = (vert(left, top, uv[0]), vert(left, bottom, uv[1]));
+                let (tr, br) = (vert(right, top, uv[2]), vert(right, bottom, uv[3]));
+                verts.extend_from_slice(&[tl, bl, br, tl, br, tr]);
+            }
+        }
+
+        let mut buffer = chunk.buffer.borrow_mut();
+        if verts.is_empty() {
+            *buffer = None;
+        } else {
+            // Chunks with animations get rewritten often, so hint the driver
+            let usage = if animated { DrawUsage::Dynamic } else { DrawUsage::Static };
+            match buffer.as_mut() {
+                Some(b) => b.alloc(&verts, usage),
+                None => *buffer = Some(VertexBuffer::new(&verts, usage))
+            }
+        }
+        chunk.animated.set(animated);
+        chunk.dirty.set(false);
+    }
+}
+
+impl Renderable for TileMap {
+    fn draw(&self, renderer: &Renderer) {
+        self.draw_in(renderer, &renderer.visible_world_rect());
+    }
+}
+
+fn chunks_along(tiles: u32, chunk_size: u32) -> u32 {
+    tiles.div_ceil(chunk_size)
+}
+
+/**
+ * Normalized texture coordinates for the top left, bottom left, top right and bottom right
+ * corners with the tile's flip flags applied
+*/
+fn tile_uvs(tile: Tile, rect: Rectui, texture_size: glm::Vec2) -> [Vert2DTextureCoord; 4] {
+    let (u0, u1) = (rect.x as f32 / texture_size.x, (rect.x + rect.w) as f32 / texture_size.x);
+    // Textures are stored flipped, so the top of the rect has the higher v
+    let (v0, v1) = (1. - rect.y as f32 / texture_size.y, 1. - (rect.y + rect.h) as f32 / texture_size.y);
+    let uv = |u: f32, v: f32| Vert2DTextureCoord { u, v };
+    let mut uvs = [uv(u0, v0), uv(u0, v1), uv(u1, v0), uv(u1, v1)];
+    apply_tile_flips(tile, &mut uvs);
+    uvs
+}
+
+/**
+ * Rearranges texture coordinates given in top left, bottom left, top right, bottom right order
+ * (Quad vertex order) to show the tile flipped and rotated by its flags
+*/
+pub fn apply_tile_flips(tile: Tile, uvs: &mut [Vert2DTextureCoord; 4]) {
+    // Same order as Tiled: diagonal first, then horizontal, then vertical
+    let (tl, bl, tr, br) = (0, 1, 2, 3);
+    if tile.flip_diagonal() {
+        uvs.swap(bl, tr);
+    }
+    if tile.flip_x() {
+        uvs.swap(tl, tr);
+        uvs.swap(bl, br);
+    }
+    if tile.flip_y() {
+        uvs.swap(tl, bl);
+        uvs.swap(tr, br);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(width: u32, height: u32) -> TileMap {
+        let mut map = TileMap::new(width, height, glm::vec2(16., 16.));
+        map.add_tileset(Tileset::new(Rc::new(Texture::unloaded(32, 32)), glm::vec2(16, 16), 0, 0).unwrap());
+        map
+    }
+
+    /**
+     * Corner each uv came from, in Quad vertex order
+    */
+    fn corners(tile: Tile) -> [u8; 4] {
+        let mut uvs = [0., 1., 2., 3.].map(|u| Vert2DTextureCoord { u, v: 0. });
+        apply_tile_flips(tile, &mut uvs);
+        uvs.map(|uv| uv.u as u8)
+    }
+
+    fn all_flips() -> Vec<Tile> {
+        (0..8).map(|i| Tile::new(5).with_flip(i & 1 != 0, i & 2 != 0, i & 4 != 0)).collect()
+    }
+
+    #[test]
+    fn flips_swap_corners() {
+        let tile = Tile::new(5);
+        assert_eq!(corners(tile), [0, 1, 2, 3]);
+        assert_eq!(corners(tile.with_flip(true, false, false)), [2, 3, 0, 1]);
+        assert_eq!(corners(tile.with_flip(false, true, false)), [1, 0, 3, 2]);
+        assert_eq!(corners(tile.with_flip(false, false, true)), [0, 2, 1, 3]);
+        assert_eq!(corners(tile.with_flip(true, true, false)), [3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn flags_keep_the_index() {
+        for tile in all_flips() {
+            assert_eq!(tile.index(), Some(5));
+            assert_eq!(tile.rotated_cw().index(), Some(5));
+        }
+        assert!(Tile::EMPTY.with_flip(true, true, true).is_empty());
+        assert_eq!(Tile::new(0).with_flip(true, false, true).with_flip(false, false, false), Tile::new(0));
+    }
+
+    #[test]
+    fn rotated_cw_turns_the_tile_clockwise() {
+        // Turned clockwise, the top left shows what was at the bottom left and so on
+        let (tl, bl, tr, br) = (0, 1, 2, 3);
+        for tile in all_flips() {
+            let before = corners(tile);
+            let after = corners(tile.rotated_cw());
+            assert_eq!(after, [before[bl], before[br], before[tl], before[tr]], "{:?}", tile);
+            assert_eq!(tile.rotated_cw().rotated_ccw(), tile);
+            assert_eq!(tile.rotated_cw().rotated_cw().rotated_cw().rotated_cw(), tile);
+            assert_eq!(corners(tile.rotated_cw().rotated_cw()), corners(tile.rotated_ccw().rotated_ccw()));
+        }
+        assert_eq!(corners(Tile::new(5).rotated_cw()), [1, 3, 0, 2]);
+    }
+
+    #[test]
+    fn oversized_layers_are_errors() {
+        assert!(map(65536, 65536).add_layer("ground", 0).unwrap_err().starts_with("TileMapError"));
+        let mut small = map(3, 2);
+        let index = small.add_layer("ground", 0).unwrap();
+        assert_eq!(small.layer(index).unwrap().tiles().len(), 6);
+    }
+}
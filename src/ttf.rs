@@ -0,0 +1,675 @@
+use crate::atlas::SkylinePacker;
+use crate::buffers::DataType;
+use crate::font::TextAlign;
+use crate::graphics::*;
+use crate::sys::{Rectf, Rectui};
+use crate::vertex::*;
+use nalgebra_glm as glm;
+use std::cell::{Ref, RefCell};
+use std::collections::HashMap;
+use std::ops::Range;
+
+/**
+ * Empty pixels kept around each glyph so linear filtering doesn't bleed neighbours in
+*/
+const GLYPH_PADDING: u32 = 1;
+
+/**
+ * Growable RGBA texture glyphs are rasterized into. Glyphs are stored white with coverage in alpha
+ * so the default shader tints them with vertex color. Pixels are kept on the CPU so the atlas can be
+ * reuploaded when it doubles in size, glyph rects stay valid across growth
+*/
+pub struct GlyphAtlas {
+    texture: Texture,
+    pixels: Vec<u8>,
+    size: u32,
+    max_size: u32,
+    packer: SkylinePacker,
+}
+
+impl GlyphAtlas {
+    pub fn new(initial_size: u32, max_size: u32) -> Self {
+        let size = initial_size.max(16);
+        let pixels = vec![0; (size * size * 4) as usize];
+        let texture = Texture::new_empty(size, size, ColorFormat::Rgba8);
+        texture.write(glm::vec2(0, 0), size as i32, size as i32, TextureFormat::Rgba, DataType::UByte, pixels.clone());
+
+        GlyphAtlas { texture, pixels, size, max_size: max_size.max(size), packer: SkylinePacker::new(size, size) }
+    }
+
+    pub fn texture(&self) -> &Texture { &self.texture }
+
+    /**
+     * Width and height in pixels, the atlas is always square
+    */
+    pub fn size(&self) -> u32 { self.size }
+
+    /**
+     * Copies 8 bit coverage into a free area of the atlas, growing it if needed. Returns the
+     * area in pixels with origin at the top left
+    */
+    pub fn insert(&mut self, width: u32, height: u32, coverage: &[u8]) -> Result<Rectui, String> {
+        let rect = self.allocate(width, height)?;
+        if width == 0 || height == 0 {
+            return Ok(rect);
+        }
+
+        let mut region = Vec::with_capacity((width * height * 4) as usize);
+        for (i, a) in coverage.iter().take((width * height) as usize).enumerate() {
+            let (x, y) = (rect.x + i as u32 % width, rect.y + i as u32 / width);
+            let p = ((y * self.size + x) * 4) as usize;
+            self.pixels[p..p + 4].copy_from_slice(&[255, 255, 255, *a]);
+            region.extend_from_slice(&[255, 255, 255, *a]);
+        }
+        self.texture.write(glm::vec2(rect.x as i32, rect.y as i32), width as i32, height as i32, TextureFormat::Rgba, DataType::UByte, region);
+        Ok(rect)
+    }
+
+    /**
+     * Forgets every glyph, keeping the current size
+    */
+    pub fn clear(&mut self) {
+        self.packer = SkylinePacker::new(self.size, self.size);
+        self.pixels.iter_mut().for_each(|p| *p = 0);
+        self.texture.write(glm::vec2(0, 0), self.size as i32, self.size as i32, TextureFormat::Rgba, DataType::UByte, self.pixels.clone());
+    }
+
+    fn allocate(&mut self, width: u32, height: u32) -> Result<Rectui, String> {
+        let (w, h) = (width + GLYPH_PADDING * 2, height + GLYPH_PADDING * 2);
+        loop {
+            if let Some(r) = self.packer.pack(w, h) {
+                return Ok(Rectui::new(r.x + GLYPH_PADDING, r.y + GLYPH_PADDING, width, height));
+            }
+            self.grow(w, h)?;
+        }
+    }
+
+    fn grow(&mut self, w: u32, h: u32) -> Result<(), String> {
+        let new_size = self.size * 2;
+        if new_size > self.max_size {
+            return Err(format!("FontError: glyph atlas is full ({}x{}), cannot fit {}x{} glyph", self.size, self.size, w, h));
+        }
+
+        let mut pixels = vec![0; (new_size * new_size * 4) as usize];
+        let row = (self.size * 4) as usize;
+        for y in 0..self.size as usize {
+            let dst = y * new_size as usize * 4;
+            pixels[dst..dst + row].copy_from_slice(&self.pixels[y * row..(y + 1) * row]);
+        }
+
+        let texture = Texture::new_empty(new_size, new_size, ColorFormat::Rgba8);
+        texture.write(glm::vec2(0, 0), new_size as i32, new_size as i32, TextureFormat::Rgba, DataType::UByte, pixels.clone());
+
+        self.texture = texture;
+        self.pixels = pixels;
+        self.size = new_size;
+        self.packer.grow(new_size, new_size);
+        Ok(())
+    }
+}
+
+/**
+ * Glyph rasterized at a specific pixel size. offset is from the pen position on the baseline
+ * to the bottom left of the bitmap, y up
+*/
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AtlasGlyph {
+    pub rect: Rectui,
+    pub offset: glm::Vec2,
+    pub advance: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorSpan {
+    /**
+     * Byte range into the laid out text
+    */
+    pub range: Range<usize>,
+    pub color: glm::Vec4,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextStyle {
+    /**
+     * Pixel height glyphs are rasterized at, rounded to whole pixels for caching
+    */
+    pub size: f32,
+    pub color: glm::Vec4,
+    pub align: TextAlign,
+    /**
+     * When set, lines wrap at word boundaries (or mid word if a word doesn't fit) and alignment
+     * happens inside the box from pos.x to pos.x + max_width
+    */
+    pub max_width: Option<f32>,
+    /**
+     * Multiplier on the font's line height
+    */
+    pub line_spacing: f32,
+    /**
+     * Overrides color for ranges of text, later spans win where they overlap
+    */
+    pub spans: Vec<ColorSpan>,
+}
+
+impl TextStyle {
+    pub fn new(size: f32, color: glm::Vec4) -> Self {
+        TextStyle { size, color, align: TextAlign::Left, max_width: None, line_spacing: 1., spans: Vec::new() }
+    }
+
+    pub fn with_align(mut self, align: TextAlign) -> Self {
+        self.align = align;
+        self
+    }
+
+    pub fn with_max_width(mut self, max_width: f32) -> Self {
+        self.max_width = Some(max_width);
+        self
+    }
+
+    pub fn with_span(mut self, range: Range<usize>, color: glm::Vec4) -> Self {
+        self.spans.push(ColorSpan { range, color });
+        self
+    }
+
+    fn color_at(&self, byte_index: usize) -> glm::Vec4 {
+        self.spans.iter().rev()
+            .find(|s| s.range.contains(&byte_index))
+            .map(|s| s.color)
+            .unwrap_or(self.color)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PositionedGlyph {
+    pub c: char,
+    pub byte_index: usize,
+    /**
+     * World space, y up with x, y at the bottom left
+    */
+    pub rect: Rectf,
+    pub atlas_rect: Rectui,
+    pub color: glm::Vec4,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextLayout {
+    pub glyphs: Vec<PositionedGlyph>,
+    /**
+     * Byte ranges of each line after wrapping
+    */
+    pub lines: Vec<Range<usize>>,
+    pub size: glm::Vec2,
+}
+
+impl TextLayout {
+    /**
+     * Triangle list for Renderer::draw_triangles(), texture coordinates are computed against the
+     * atlas' current size so layouts stay valid after the atlas grows
+    */
+    pub fn triangles(&self, atlas_size: u32) -> Vec<Vertex2D> {
+        let s = atlas_size as f32;
+        let mut verts = Vec::with_capacity(self.glyphs.len() * 6);
+        for g in self.glyphs.iter() {
+            let (r, a) = (g.rect, g.atlas_rect);
+            // Atlas rows are uploaded top first, so the top of a glyph is at the lower v
+            let (u0, u1) = (a.x as f32 / s, (a.x + a.w) as f32 / s);
+            let (v_top, v_bottom) = (a.y as f32 / s, (a.y + a.h) as f32 / s);
+            let vert = |x: f32, y: f32, u: f32, v: f32| {
+                let mut vert = Vertex2D::colored(glm::vec2(x, y), &g.color);
+                vert.text_coord = glm::vec2(u, v).into();
+                vert
+            };
+            let tl = vert(r.x, r.y + r.h, u0, v_top);
+            let bl = vert(r.x, r.y, u0, v_bottom);
+            let tr = vert(r.x + r.w, r.y + r.h, u1, v_top);
+            let br = vert(r.x + r.w, r.y, u1, v_bottom);
+            verts.extend_from_slice(&[tl, bl, tr, tr, bl, br]);
+        }
+        verts
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+struct GlyphKey {
+    c: char,
+    size: u32,
+}
+
+/**
+ * Glyphs are rasterized once at base_size and stored as signed distance fields, then scaled to
+ * whatever size is drawn. spread is how far in pixels (at base_size) the field extends past the outline,
+ * which also limits how wide outlines, glows and shadows can be
+*/
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SdfSettings {
+    pub base_size: f32,
+    pub spread: u32,
+}
+
+impl Default for SdfSettings {
+    fn default() -> Self {
+        SdfSettings { base_size: 48., spread: 8 }
+    }
+}
+
+/**
+ * TrueType / OpenType font rasterized on demand into a shared GlyphAtlas. Glyphs are cached per
+ * pixel size, so a handful of sizes per font is cheap but animating size is not. Fonts created with
+ * SdfSettings keep a single distance field per glyph instead and must be drawn with Shader::sdf_text()
+*/
+pub struct TtfFont {
+    font: fontdue::Font,
+    atlas: RefCell<GlyphAtlas>,
+    glyphs: RefCell<HashMap<GlyphKey, AtlasGlyph>>,
+    sdf: Option<SdfSettings>,
+}
+
+impl TtfFont {
+    pub const DEFAULT_ATLAS_SIZE: u32 = 256;
+    pub const MAX_ATLAS_SIZE: u32 = 4096;
+
+    pub fn from_file(filename: &str) -> Result<Self, String> {
+        let data = std::fs::read(filename).map_err(|e| format!("Error loading file: {} :: {}", filename, e))?;
+        TtfFont::from_bytes(&data).map_err(|e| format!("Error loading file: {} :: {}", filename, e))
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, String> {
+        TtfFont::with_settings(data, None)
+    }
+
+    pub fn from_file_sdf(filename: &str, settings: SdfSettings) -> Result<Self, String> {
+        let data = std::fs::read(filename).map_err(|e| format!("Error loading file: {} :: {}", filename, e))?;
+        TtfFont::from_bytes_sdf(&data, settings).map_err(|e| format!("Error loading file: {} :: {}", filename, e))
+    }
+
+    pub fn from_bytes_sdf(data: &[u8], settings: SdfSettings) -> Result<Self, String> {
+        TtfFont::with_settings(data, Some(settings))
+    }
+
+    fn with_settings(data: &[u8], sdf: Option<SdfSettings>) -> Result<Self, String> {
+        let font = fontdue::Font::from_bytes(data, fontdue::FontSettings::default())
+            .map_err(|e| format!("FontError: {}", e))?;
+        Ok(TtfFont {
+            font,
+            atlas: RefCell::new(GlyphAtlas::new(Self::DEFAULT_ATLAS_SIZE, Self::MAX_ATLAS_SIZE)),
+            glyphs: RefCell::new(HashMap::new()),
+            sdf,
+        })
+    }
+
+    pub fn atlas(&self) -> Ref<'_, GlyphAtlas> { self.atlas.borrow() }
+
+    pub fn sdf_settings(&self) -> Option<&SdfSettings> { self.sdf.as_ref() }
+    pub fn is_sdf(&self) -> bool { self.sdf.is_some() }
+
+    /**
+     * Scale from cached glyph metrics to size, only differs from 1 for distance field fonts
+    */
+    pub fn glyph_scale(&self, size: f32) -> f32 {
+        match self.sdf {
+            Some(sdf) => size.round().max(1.) / sdf.base_size,
+            None => 1.
+        }
+    }
+
+    /**
+     * Drops every cached glyph, e.g. after switching to a different set of sizes
+    */
+    pub fn clear_cache(&self) {
+        self.glyphs.borrow_mut().clear();
+        self.atlas.borrow_mut().clear();
+    }
+
+    /**
+     * Returns glyph at the given size, rasterizing it into the atlas the first time. Distance field
+     * fonts always return the glyph at base_size, scale it with glyph_scale()
+    */
+    pub fn glyph(&self, c: char, size: f32) -> Result<AtlasGlyph, String> {
+        let size = self.sdf.map(|s| s.base_size).unwrap_or(size);
+        let key = GlyphKey { c, size: size.round().max(1.) as u32 };
+        if let Some(g) = self.glyphs.borrow().get(&key) {
+            return Ok(*g);
+        }
+
+        let (metrics, coverage) = self.font.rasterize(c, key.size as f32);
+        let (w, h) = (metrics.width as u32, metrics.height as u32);
+        let mut offset = glm::vec2(metrics.xmin as f32, metrics.ymin as f32);
+
+        let rect = match self.sdf {
+            Some(sdf) if w > 0 && h > 0 => {
+                let field = distance_field(&coverage, w, h, sdf.spread);
+                offset -= glm::vec2(sdf.spread as f32, sdf.spread as f32);
+                self.atlas.borrow_mut().insert(w + sdf.spread * 2, h + sdf.spread * 2, &field)?
+            },
+            _ => self.atlas.borrow_mut().insert(w, h, &coverage)?
+        };
+        let glyph = AtlasGlyph { rect, offset, advance: metrics.advance_width };
+        self.glyphs.borrow_mut().insert(key, glyph);
+        Ok(glyph)
+    }
+
+    /**
+     * Returns (ascent, descent, line height) in pixels, descent is negative
+    */
+    pub fn line_metrics(&self, size: f32) -> (f32, f32, f32) {
+        let size = size.round().max(1.);
+        match self.font.horizontal_line_metrics(size) {
+            Some(m) => (m.ascent, m.descent, m.new_line_size),
+            None => (size, 0., size)
+        }
+    }
+
+    pub fn kerning(&self, first: char, second: char, size: f32) -> f32 {
+        self.font.horizontal_kern(first, second, size.round().max(1.)).unwrap_or(0.)
+    }
+
+    fn advance(&self, c: char, prev: Option<char>, size: f32) -> f32 {
+        let size = size.round().max(1.);
+        self.font.metrics(c, size).advance_width + prev.map(|p| self.kerning(p, c, size)).unwrap_or(0.)
+    }
+
+    /**
+     * Width of text without wrapping or line breaks
+    */
+    pub fn line_width(&self, text: &str, size: f32) -> f32 {
+        let mut prev = None;
+        text.chars().fold(0., |w, c| {
+            let w = w + self.advance(c, prev, size);
+            prev = Some(c);
+            w
+        })
+    }
+
+    /**
+     * Size of the laid out text block in pixels
+    */
+    pub fn measure(&self, text: &str, style: &TextStyle) -> glm::Vec2 {
+        let lines = self.wrap(text, style.size, style.max_width);
+        let width = lines.iter()
+            .map(|l| self.line_width(text[l.clone()].trim_end(), style.size))
+            .fold(0., f32::max);
+        let (_, _, line_height) = self.line_metrics(style.size);
+        glm::vec2(width, lines.len() as f32 * line_height * style.line_spacing)
+    }
+
+    /**
+     * Splits text into lines on '\n' and, when max_width is set, at the last whitespace that keeps
+     * each line inside max_width. Words longer than max_width are broken between characters.
+     * Returns byte ranges into text, without the newline or the whitespace a line was broken at
+    */
+    pub fn wrap(&self, text: &str, size: f32, max_width: Option<f32>) -> Vec<Range<usize>> {
+        wrap_lines(text, max_width, |c, prev| self.advance(c, prev, size))
+    }
+
+    /**
+     * Positions every visible glyph, rasterizing any that aren't cached yet. pos is the top of the first
+     * line, and the left edge, center or right edge of each line depending on style.align
+     * (or of the wrap box when style.max_width is set). World space is y up, lines go down in y
+    */
+    pub fn layout(&self, text: &str, pos: glm::Vec2, style: &TextStyle) -> Result<TextLayout, String> {
+        let lines = self.wrap(text, style.size, style.max_width);
+        let (ascent, _, line_height) = self.line_metrics(style.size);
+        let line_height = line_height * style.line_spacing;
+        let scale = self.glyph_scale(style.size);
+
+        let mut glyphs = Vec::with_capacity(text.len());
+        let mut max_width = 0f32;
+
+        for (line_num, range) in lines.iter().enumerate() {
+            let line = text[range.clone()].trim_end();
+            let width = self.line_width(line, style.size);
+            max_width = max_width.max(width);
+
+            let box_width = style.max_width.unwrap_or(0.);
+            let x = match style.align {
+                TextAlign::Left => pos.x,
+                TextAlign::Center => pos.x + ((box_width - width) * 0.5).floor(),
+                TextAlign::Right => pos.x + box_width - width,
+            };
+            let baseline = (pos.y - ascent - line_num as f32 * line_height).round();
+            let mut pen = x;
+            let mut prev = None;
+
+            for (i, c) in line.char_indices() {
+                pen += prev.map(|p| self.kerning(p, c, style.size)).unwrap_or(0.);
+                prev = Some(c);
+                let g = self.glyph(c, style.size)?;
+
+                if g.rect.w > 0 && g.rect.h > 0 {
+                    let origin = glm::vec2(pen.round() + g.offset.x * scale, baseline + g.offset.y * scale);
+                    glyphs.push(PositionedGlyph {
+                        c,
+                        byte_index: range.start + i,
+                        rect: Rectf::new(origin.x, origin.y, g.rect.w as f32 * scale, g.rect.h as f32 * scale),
+                        atlas_rect: g.rect,
+                        color: style.color_at(range.start + i),
+                    });
+                }
+                pen += g.advance * scale;
+            }
+        }
+
+        Ok(TextLayout {
+            glyphs,
+            size: glm::vec2(max_width, lines.len() as f32 * line_height),
+            lines,
+        })
+    }
+}
+
+/**
+ * TtfFont::wrap with the glyph advance (char, previous char) supplied by the caller
+*/
+fn wrap_lines<F>(text: &str, max_width: Option<f32>, advance: F) -> Vec<Range<usize>> where F: Fn(char, Option<char>) -> f32 {
+    let mut lines = Vec::new();
+    let mut start = 0;
+
+    for paragraph in text.split('\n') {
+        let end = start + paragraph.len();
+        let max_width = match max_width {
+            Some(w) => w,
+            None => {
+                lines.push(start..end);
+                start = end + 1;
+                continue;
+            }
+        };
+
+        let chars: Vec<(usize, char)> = paragraph.char_indices().map(|(i, c)| (start + i, c)).collect();
+        let byte_at = |i: usize| chars.get(i).map(|c| c.0).unwrap_or(end);
+
+        let (mut line_start, mut i) = (0, 0);
+        let mut width = 0.;
+        let mut prev = None;
+        let mut last_space: Option<usize> = None;
+
+        while i < chars.len() {
+            let c = chars[i].1;
+            let advance = advance(c, prev);
+
+            if !c.is_whitespace() && width + advance > max_width && i > line_start {
+                match last_space.filter(|s| *s > line_start) {
+                    Some(space) => {
+                        // Leave the whole run of whitespace out, not just the last one
+                        let mut line_end = space;
+                        while line_end > line_start && chars[line_end - 1].1.is_whitespace() {
+                            line_end -= 1;
+                        }
+                        lines.push(byte_at(line_start)..byte_at(line_end));
+                        line_start = space;
+                        while line_start < chars.len() && chars[line_start].1.is_whitespace() {
+                            line_start += 1;
+                        }
+                    },
+                    None => {
+                        lines.push(byte_at(line_start)..byte_at(i));
+                        line_start = i;
+                    }
+                }
+                i = line_start;
+                width = 0.;
+                prev = None;
+                last_space = None;
+                continue;
+            }
+
+            if c.is_whitespace() {
+                last_space = Some(i);
+            }
+            width += advance;
+            prev = Some(c);
+            i += 1;
+        }
+
+        lines.push(byte_at(line_start)..end);
+        start = end + 1;
+    }
+    lines
+}
+
+/**
+ * Converts 8 bit coverage into a signed distance field padded by spread pixels on every side.
+ * 128 is on the outline, values go up to 255 inside and down to 0 outside at spread pixels away
+*/
+pub fn distance_field(coverage: &[u8], width: u32, height: u32, spread: u32) -> Vec<u8> {
+    let (w, h) = ((width + spread * 2) as usize, (height + spread * 2) as usize);
+    let inside = |x: usize, y: usize| -> bool {
+        let (gx, gy) = (x as i64 - spread as i64, y as i64 - spread as i64);
+        gx >= 0 && gy >= 0 && (gx as u32) < width && (gy as u32) < height
+            && coverage[gy as usize * width as usize + gx as usize] >= 128
+    };
+
+    // Squared distance to the nearest inside pixel, and to the nearest outside pixel
+    let mut to_inside = vec![0f32; w * h];
+    let mut to_outside = vec![0f32; w * h];
+    for y in 0..h {
+        for x in 0..w {
+            let i = inside(x, y);
+            to_inside[y * w + x] = if i { 0. } else { EDT_INF };
+            to_outside[y * w + x] = if i { EDT_INF } else { 0. };
+        }
+    }
+    squared_distance_transform(&mut to_inside, w, h);
+    squared_distance_transform(&mut to_outside, w, h);
+
+    let spread = spread.max(1) as f32;
+    to_inside.iter().zip(to_outside.iter())
+        .map(|(di, dout)| {
+            // Positive outside the glyph, pixel centers are half a pixel from the edge
+            let d = if *di > 0. { di.sqrt() - 0.5 } else { -(dout.sqrt() - 0.5) };
+            ((0.5 - d / (2. * spread)).clamp(0., 1.) * 255.).round() as u8
+        })
+        .collect()
+}
+
+const EDT_INF: f32 = 1e20;
+
+/**
+ * Exact squared euclidean distance transform (Felzenszwalb & Huttenlocher), rows then columns
+*/
+fn squared_distance_transform(grid: &mut [f32], w: usize, h: usize) {
+    let n = w.max(h);
+    let (mut f, mut d) = (vec![0f32; n], vec![0f32; n]);
+    let (mut v, mut z) = (vec![0usize; n], vec![0f32; n + 1]);
+
+    for x in 0..w {
+        for y in 0..h { f[y] = grid[y * w + x]; }
+        distance_transform_1d(&f[..h], &mut d[..h], &mut v, &mut z);
+        for y in 0..h { grid[y * w + x] = d[y]; }
+    }
+    for y in 0..h {
+        f[..w].copy_from_slice(&grid[y * w..(y + 1) * w]);
+        distance_transform_1d(&f[..w], &mut d[..w], &mut v, &mut z);
+        grid[y * w..(y + 1) * w].copy_from_slice(&d[..w]);
+    }
+}
+
+fn distance_transform_1d(f: &[f32], d: &mut [f32], v: &mut [usize], z: &mut [f32]) {
+    let n = f.len();
+    let mut k = 0;
+    v[0] = 0;
+    z[0] = -EDT_INF;
+    z[1] = EDT_INF;
+
+    for q in 1..n {
+        let intersect = |p: usize| ((f[q] + (q * q) as f32) - (f[p] + (p * p) as f32)) / (2. * q as f32 - 2. * p as f32);
+        let mut s = intersect(v[k]);
+        while s <= z[k] {
+            k -= 1;
+            s = intersect(v[k]);
+        }
+        k += 1;
+        v[k] = q;
+        z[k] = s;
+        z[k + 1] = EDT_INF;
+    }
+
+    k = 0;
+    for (q, out) in d.iter_mut().enumerate() {
+        while z[k + 1] < q as f32 {
+            k += 1;
+        }
+        let dq = q as f32 - v[k] as f32;
+        *out = dq * dq + f[v[k]];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str, max_width: Option<f32>) -> Vec<&str> {
+        // Monospaced, every character is 1 wide
+        wrap_lines(text, max_width, |_, _| 1.).into_iter().map(|r| &text[r]).collect()
+    }
+
+    #[test]
+    fn wrap_splits_on_newlines() {
+        assert_eq!(lines("ab\ncd", None), vec!["ab", "cd"]);
+        assert_eq!(lines("ab\n\ncd\n", None), vec!["ab", "", "cd", ""]);
+        assert_eq!(lines("", Some(4.)), vec![""]);
+    }
+
+    #[test]
+    fn wrap_breaks_at_whitespace() {
+        assert_eq!(lines("aaa bbb ccc", Some(7.)), vec!["aaa bbb", "ccc"]);
+        assert_eq!(lines("aaa   bbb", Some(4.)), vec!["aaa", "bbb"]);
+        assert_eq!(lines("aaa bbb\nc", Some(5.)), vec!["aaa", "bbb", "c"]);
+    }
+
+    #[test]
+    fn wrap_breaks_long_words_between_characters() {
+        assert_eq!(lines("abcdefgh", Some(3.)), vec!["abc", "def", "gh"]);
+        assert_eq!(lines("héllo wörld", Some(5.)), vec!["héllo", "wörld"]);
+        assert_eq!(lines("ab", Some(0.)), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn wrap_uses_advance_with_previous_char() {
+        // A pair kerned tight enough fits where it otherwise wouldn't
+        let text = "AVA";
+        let kerned = wrap_lines(text, Some(2.), |c, prev| if prev == Some('A') && c == 'V' { 0.5 } else { 1. });
+        assert_eq!(kerned, vec![0..2, 2..3]);
+    }
+
+    #[test]
+    fn distance_field_pads_and_centers_on_the_edge() {
+        let (w, h, spread) = (4, 4, 2);
+        let field = distance_field(&[255; 16], w, h, spread);
+        let size = (w + spread * 2) as usize;
+        assert_eq!(field.len(), size * size);
+
+        let at = |x: usize, y: usize| field[y * size + x];
+        // First pixel inside and last pixel outside sit either side of 128
+        assert!(at(2, 3) > 128 && at(1, 3) < 128);
+        assert_eq!(at(2, 3) as u32 + at(1, 3) as u32, 255);
+        assert!(at(4, 4) > at(2, 3));
+        assert_eq!(at(0, 0), 0);
+    }
+
+    #[test]
+    fn distance_field_of_empty_coverage_is_outside() {
+        assert!(distance_field(&[0; 9], 3, 3, 2).iter().all(|v| *v == 0));
+        assert!(distance_field(&[127; 9], 3, 3, 2).iter().all(|v| *v == 0));
+    }
+}
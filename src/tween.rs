@@ -0,0 +1,613 @@
+use crate::sys::{PI, HALF_PI, TWO_PI};
+use nalgebra_glm as glm;
+
+/**
+ * Robert Penner's easing equations. All take t in 0 to 1 and return 0 at t = 0 and 1 at t = 1,
+ * Back and Elastic overshoot in between
+*/
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum Easing {
+    #[default]
+    Linear,
+    QuadIn, QuadOut, QuadInOut,
+    CubicIn, CubicOut, CubicInOut,
+    QuartIn, QuartOut, QuartInOut,
+    QuintIn, QuintOut, QuintInOut,
+    SineIn, SineOut, SineInOut,
+    ExpoIn, ExpoOut, ExpoInOut,
+    CircIn, CircOut, CircInOut,
+    BackIn, BackOut, BackInOut,
+    ElasticIn, ElasticOut, ElasticInOut,
+    BounceIn, BounceOut, BounceInOut,
+}
+
+impl Easing {
+    pub fn apply(self, t: f32) -> f32 {
+        use Easing::*;
+        let t = t.clamp(0., 1.);
+        match self {
+            Linear => t,
+
+            QuadIn => t * t,
+            QuadOut => 1. - (1. - t) * (1. - t),
+            QuadInOut => if t < 0.5 { 2. * t * t } else { 1. - (-2. * t + 2.).powi(2) / 2. },
+
+            CubicIn => t * t * t,
+            CubicOut => 1. - (1. - t).powi(3),
+            CubicInOut => if t < 0.5 { 4. * t * t * t } else { 1. - (-2. * t + 2.).powi(3) / 2. },
+
+            QuartIn => t.powi(4),
+            QuartOut => 1. - (1. - t).powi(4),
+            QuartInOut => if t < 0.5 { 8. * t.powi(4) } else { 1. - (-2. * t + 2.).powi(4) / 2. },
+
+            QuintIn => t.powi(5),
+            QuintOut => 1. - (1. - t).powi(5),
+            QuintInOut => if t < 0.5 { 16. * t.powi(5) } else { 1. - (-2. * t + 2.).powi(5) / 2. },
+
+            SineIn => 1. - (t * HALF_PI).cos(),
+            SineOut => (t * HALF_PI).sin(),
+            SineInOut => -((PI * t).cos() - 1.) / 2.,
+
+            ExpoIn => if t == 0. { 0. } else { 2f32.powf(10. * t - 10.) },
+            ExpoOut => if t == 1. { 1. } else { 1. - 2f32.powf(-10. * t) },
+            ExpoInOut => {
+                if t == 0. || t == 1. { t }
+                else if t < 0.5 { 2f32.powf(20. * t - 10.) / 2. }
+                else { (2. - 2f32.powf(-20. * t + 10.)) / 2. }
+            },
+
+            CircIn => 1. - (1. - t * t).sqrt(),
+            CircOut => (1. - (t - 1.) * (t - 1.)).sqrt(),
+            CircInOut => {
+                if t < 0.5 { (1. - (1. - (2. * t).powi(2)).sqrt()) / 2. }
+                else { ((1. - (-2. * t + 2.).powi(2)).sqrt() + 1.) / 2. }
+            },
+
+            BackIn => BACK_C3 * t * t * t - BACK_C1 * t * t,
+            BackOut => 1. + BACK_C3 * (t - 1.).powi(3) + BACK_C1 * (t - 1.).powi(2),
+            BackInOut => {
+                let c2 = BACK_C1 * 1.525;
+                if t < 0.5 { (2. * t).powi(2) * ((c2 + 1.) * 2. * t - c2) / 2. }
+                else { ((2. * t - 2.).powi(2) * ((c2 + 1.) * (t * 2. - 2.) + c2) + 2.) / 2. }
+            },
+
+            ElasticIn => {
+                if t == 0. || t == 1. { t }
+                else { -(2f32.powf(10. * t - 10.)) * ((t * 10. - 10.75) * ELASTIC_C4).sin() }
+            },
+            ElasticOut => {
+                if t == 0. || t == 1. { t }
+                else { 2f32.powf(-10. * t) * ((t * 10. - 0.75) * ELASTIC_C4).sin() + 1. }
+            },
+            ElasticInOut => {
+                let c5 = TWO_PI / 4.5;
+                if t == 0. || t == 1. { t }
+                else if t < 0.5 { -(2f32.powf(20. * t - 10.) * ((20. * t - 11.125) * c5).sin()) / 2. }
+                else { 2f32.powf(-20. * t + 10.) * ((20. * t - 11.125) * c5).sin() / 2. + 1. }
+            },
+
+            BounceIn => 1. - bounce_out(1. - t),
+            BounceOut => bounce_out(t),
+            BounceInOut => {
+                if t < 0.5 { (1. - bounce_out(1. - 2. * t)) / 2. }
+                else { (1. + bounce_out(2. * t - 1.)) / 2. }
+            },
+        }
+    }
+}
+
+const BACK_C1: f32 = 1.70158;
+const BACK_C3: f32 = BACK_C1 + 1.;
+const ELASTIC_C4: f32 = TWO_PI / 3.;
+
+fn bounce_out(t: f32) -> f32 {
+    let (n1, d1) = (7.5625, 2.75);
+    if t < 1. / d1 {
+        n1 * t * t
+    } else if t < 2. / d1 {
+        let t = t - 1.5 / d1;
+        n1 * t * t + 0.75
+    } else if t < 2.5 / d1 {
+        let t = t - 2.25 / d1;
+        n1 * t * t + 0.9375
+    } else {
+        let t = t - 2.625 / d1;
+        n1 * t * t + 0.984375
+    }
+}
+
+/**
+ * Values a Tween can interpolate. Colors are glm::Vec4 like everywhere else in the renderer
+*/
+pub trait Tweenable: Copy {
+    fn lerp(from: &Self, to: &Self, t: f32) -> Self;
+}
+
+impl Tweenable for f32 {
+    fn lerp(from: &Self, to: &Self, t: f32) -> Self { from + (to - from) * t }
+}
+
+impl Tweenable for glm::Vec2 {
+    fn lerp(from: &Self, to: &Self, t: f32) -> Self { from + (to - from) * t }
+}
+
+impl Tweenable for glm::Vec3 {
+    fn lerp(from: &Self, to: &Self, t: f32) -> Self { from + (to - from) * t }
+}
+
+impl Tweenable for glm::Vec4 {
+    fn lerp(from: &Self, to: &Self, t: f32) -> Self { from + (to - from) * t }
+}
+
+/**
+ * Anything that runs over time and can be put in a TweenSequence or TweenGroup
+*/
+pub trait Tweener {
+    /**
+     * Steps forward dt seconds and returns the part of dt left over after finishing,
+     * 0 while still running
+    */
+    fn advance(&mut self, dt: f32) -> f32;
+    fn is_finished(&self) -> bool;
+    /**
+     * Back to the initial state, callbacks are kept
+    */
+    fn reset(&mut self);
+}
+
+pub const REPEAT_FOREVER: i32 = -1;
+
+/**
+ * Interpolates between two values over duration seconds. Read value() after update(), or use
+ * on_update() when the tween is owned by a sequence or group
+*/
+pub struct Tween<T: Tweenable> {
+    from: T,
+    to: T,
+    value: T,
+    duration: f32,
+    delay: f32,
+    easing: Easing,
+    repeat: i32,
+    yoyo: bool,
+
+    elapsed: f32,
+    delay_left: f32,
+    repeats_left: i32,
+    forward: bool,
+    finished: bool,
+
+    on_update: Option<Box<dyn FnMut(T)>>,
+    on_complete: Option<Box<dyn FnMut()>>,
+}
+
+impl<T: Tweenable> Tween<T> {
+    pub fn new(from: T, to: T, duration: f32) -> Self {
+        Tween {
+            from,
+            to,
+            value: from,
+            duration,
+            delay: 0.,
+            easing: Easing::Linear,
+            repeat: 0,
+            yoyo: false,
+            elapsed: 0.,
+            delay_left: 0.,
+            repeats_left: 0,
+            forward: true,
+            finished: false,
+            on_update: None,
+            on_complete: None,
+        }
+    }
+
+    pub fn easing(mut self, easing: Easing) -> Self {
+        self.easing = easing;
+        self
+    }
+
+    /**
+     * Seconds to wait before starting, only applied once and not between repeats
+    */
+    pub fn delay(mut self, delay: f32) -> Self {
+        self.delay = delay.max(0.);
+        self.delay_left = self.delay;
+        self
+    }
+
+    /**
+     * Extra times to play after the first, REPEAT_FOREVER to never finish
+    */
+    pub fn repeat(mut self, repeat: i32) -> Self {
+        self.repeat = repeat;
+        self.repeats_left = repeat;
+        self
+    }
+
+    /**
+     * Every repeat plays in the opposite direction to the one before
+    */
+    pub fn yoyo(mut self, yoyo: bool) -> Self {
+        self.yoyo = yoyo;
+        self
+    }
+
+    /**
+     * Called with the new value every time it changes
+    */
+    pub fn on_update<F: FnMut(T) + 'static>(mut self, f: F) -> Self {
+        self.on_update = Some(Box::new(f));
+        self
+    }
+
+    pub fn on_complete<F: FnMut() + 'static>(mut self, f: F) -> Self {
+        self.on_complete = Some(Box::new(f));
+        self
+    }
+
+    pub fn update(&mut self, dt: f32) -> T {
+        self.advance(dt);
+        self.value
+    }
+
+    pub fn value(&self) -> T { self.value }
+    pub fn from(&self) -> T { self.from }
+    pub fn to(&self) -> T { self.to }
+    pub fn duration(&self) -> f32 { self.duration }
+
+    /**
+     * 0 to 1 through the current play, before easing
+    */
+    pub fn progress(&self) -> f32 {
+        if self.duration <= 0. { 1. } else { (self.elapsed / self.duration).min(1.) }
+    }
+
+    fn set_value(&mut self, t: f32) {
+        let t = if self.forward { t } else { 1. - t };
+        self.value = T::lerp(&self.from, &self.to, self.easing.apply(t));
+        if let Some(f) = self.on_update.as_mut() {
+            f(self.value);
+        }
+    }
+
+    fn finish(&mut self) {
+        self.finished = true;
+        self.elapsed = self.duration.max(0.);
+        self.set_value(1.);
+        if let Some(f) = self.on_complete.as_mut() {
+            f();
+        }
+    }
+}
+
+impl<T: Tweenable> Tweener for Tween<T> {
+    fn advance(&mut self, dt: f32) -> f32 {
+        if self.finished {
+            return dt;
+        }
+
+        let mut dt = dt.max(0.);
+        if self.delay_left > 0. {
+            let used = dt.min(self.delay_left);
+            self.delay_left -= used;
+            dt -= used;
+            if self.delay_left > 0. {
+                return 0.;
+            }
+        }
+
+        // Zero length tweens jump straight to the end, even when repeating forever
+        if self.duration <= 0. {
+            self.finish();
+            return dt;
+        }
+
+        self.elapsed += dt;
+        while self.elapsed >= self.duration {
+            if self.repeats_left == 0 {
+                let left = self.elapsed - self.duration;
+                self.finish();
+                return left;
+            }
+            self.elapsed -= self.duration;
+            if self.repeats_left > 0 {
+                self.repeats_left -= 1;
+            }
+            if self.yoyo {
+                self.forward = !self.forward;
+            }
+        }
+        self.set_value(self.elapsed / self.duration);
+        0.
+    }
+
+    fn is_finished(&self) -> bool { self.finished }
+
+    fn reset(&mut self) {
+        self.elapsed = 0.;
+        self.delay_left = self.delay;
+        self.repeats_left = self.repeat;
+        self.forward = true;
+        self.finished = false;
+        self.value = self.from;
+    }
+}
+
+/**
+ * Does nothing for a while, for gaps in a TweenSequence
+*/
+pub struct TweenDelay {
+    duration: f32,
+    elapsed: f32,
+}
+
+impl TweenDelay {
+    pub fn new(duration: f32) -> Self {
+        TweenDelay { duration: duration.max(0.), elapsed: 0. }
+    }
+}
+
+impl Tweener for TweenDelay {
+    fn advance(&mut self, dt: f32) -> f32 {
+        if self.is_finished() {
+            return dt;
+        }
+        self.elapsed += dt.max(0.);
+        (self.elapsed - self.duration).max(0.)
+    }
+
+    fn is_finished(&self) -> bool { self.elapsed >= self.duration }
+    fn reset(&mut self) { self.elapsed = 0.; }
+}
+
+/**
+ * Runs tweens one after another. Time left over when one finishes carries into the next,
+ * so long frames don't drift the timing
+*/
+pub struct TweenSequence {
+    items: Vec<Box<dyn Tweener>>,
+    current: usize,
+    finished: bool,
+    on_complete: Option<Box<dyn FnMut()>>,
+}
+
+impl TweenSequence {
+    pub fn new() -> Self {
+        TweenSequence { items: Vec::new(), current: 0, finished: false, on_complete: None }
+    }
+
+    pub fn then<W: Tweener + 'static>(mut self, item: W) -> Self {
+        self.items.push(Box::new(item));
+        self
+    }
+
+    pub fn wait(self, seconds: f32) -> Self {
+        self.then(TweenDelay::new(seconds))
+    }
+
+    pub fn on_complete<F: FnMut() + 'static>(mut self, f: F) -> Self {
+        self.on_complete = Some(Box::new(f));
+        self
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.advance(dt);
+    }
+
+    /**
+     * Index of the item currently running
+    */
+    pub fn current(&self) -> usize { self.current }
+    pub fn len(&self) -> usize { self.items.len() }
+    pub fn is_empty(&self) -> bool { self.items.is_empty() }
+}
+
+impl Default for TweenSequence {
+    fn default() -> Self { TweenSequence::new() }
+}
+
+impl Tweener for TweenSequence {
+    fn advance(&mut self, dt: f32) -> f32 {
+        if self.finished {
+            return dt;
+        }
+
+        let mut dt = dt.max(0.);
+        while self.current < self.items.len() {
+            let left = self.items[self.current].advance(dt);
+            if !self.items[self.current].is_finished() {
+                return 0.;
+            }
+            self.current += 1;
+            dt = left;
+        }
+
+        self.finished = true;
+        if let Some(f) = self.on_complete.as_mut() {
+            f();
+        }
+        dt
+    }
+
+    fn is_finished(&self) -> bool { self.finished }
+
+    fn reset(&mut self) {
+        self.items.iter_mut().for_each(|i| i.reset());
+        self.current = 0;
+        self.finished = false;
+    }
+}
+
+/**
+ * Runs tweens at the same time, finishing when the longest one does
+*/
+pub struct TweenGroup {
+    items: Vec<Box<dyn Tweener>>,
+    finished: bool,
+    on_complete: Option<Box<dyn FnMut()>>,
+}
+
+impl TweenGroup {
+    pub fn new() -> Self {
+        TweenGroup { items: Vec::new(), finished: false, on_complete: None }
+    }
+
+    pub fn with<W: Tweener + 'static>(mut self, item: W) -> Self {
+        self.items.push(Box::new(item));
+        self
+    }
+
+    pub fn on_complete<F: FnMut() + 'static>(mut self, f: F) -> Self {
+        self.on_complete = Some(Box::new(f));
+        self
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.advance(dt);
+    }
+
+    pub fn len(&self) -> usize { self.items.len() }
+    pub fn is_empty(&self) -> bool { self.items.is_empty() }
+}
+
+impl Default for TweenGroup {
+    fn default() -> Self { TweenGroup::new() }
+}
+
+impl Tweener for TweenGroup {
+    fn advance(&mut self, dt: f32) -> f32 {
+        if self.finished {
+            return dt;
+        }
+
+        let dt = dt.max(0.);
+        // Already finished items hand back all of dt, so the smallest leftover belongs to the longest running one
+        let left = self.items.iter_mut().map(|i| i.advance(dt)).fold(dt, f32::min);
+        if !self.items.iter().all(|i| i.is_finished()) {
+            return 0.;
+        }
+
+        self.finished = true;
+        if let Some(f) = self.on_complete.as_mut() {
+            f();
+        }
+        left
+    }
+
+    fn is_finished(&self) -> bool { self.finished }
+
+    fn reset(&mut self) {
+        self.items.iter_mut().for_each(|i| i.reset());
+        self.finished = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    const ALL: [Easing; 31] = [
+        Easing::Linear,
+        Easing::QuadIn, Easing::QuadOut, Easing::QuadInOut,
+        Easing::CubicIn, Easing::CubicOut, Easing::CubicInOut,
+        Easing::QuartIn, Easing::QuartOut, Easing::QuartInOut,
+        Easing::QuintIn, Easing::QuintOut, Easing::QuintInOut,
+        Easing::SineIn, Easing::SineOut, Easing::SineInOut,
+        Easing::ExpoIn, Easing::ExpoOut, Easing::ExpoInOut,
+        Easing::CircIn, Easing::CircOut, Easing::CircInOut,
+        Easing::BackIn, Easing::BackOut, Easing::BackInOut,
+        Easing::ElasticIn, Easing::ElasticOut, Easing::ElasticInOut,
+        Easing::BounceIn, Easing::BounceOut, Easing::BounceInOut,
+    ];
+
+    fn near(a: f32, b: f32) -> bool { (a - b).abs() < 1e-4 }
+
+    #[test]
+    fn easings_start_at_0_and_end_at_1() {
+        for e in ALL.iter() {
+            assert!(near(e.apply(0.), 0.), "{:?}(0) = {}", e, e.apply(0.));
+            assert!(near(e.apply(1.), 1.), "{:?}(1) = {}", e, e.apply(1.));
+            assert!(near(e.apply(-1.), 0.) && near(e.apply(2.), 1.), "{:?} is not clamped", e);
+        }
+        assert!(near(Easing::QuadInOut.apply(0.5), 0.5));
+        assert!(Easing::BackIn.apply(0.2) < 0.);
+    }
+
+    #[test]
+    fn delay_then_play() {
+        let mut t = Tween::new(0., 10., 1.).delay(0.5);
+        assert_eq!(t.update(0.25), 0.);
+        assert!(near(t.update(0.5), 2.5));
+        assert_eq!(t.advance(1.), 0.25);
+        assert!(t.is_finished());
+        assert_eq!(t.value(), 10.);
+    }
+
+    #[test]
+    fn repeat_carries_over_into_the_next_play() {
+        let completed = Rc::new(Cell::new(0));
+        let c = completed.clone();
+        let mut t = Tween::new(0., 10., 1.).repeat(2).on_complete(move || c.set(c.get() + 1));
+        assert!(near(t.update(2.5), 5.));
+        assert!(!t.is_finished());
+        assert!(near(t.advance(1.), 0.5));
+        assert!(t.is_finished());
+        assert_eq!(t.value(), 10.);
+        assert_eq!(t.advance(1.), 1.);
+        assert_eq!(completed.get(), 1);
+
+        let mut forever = Tween::new(0., 10., 1.).repeat(REPEAT_FOREVER);
+        assert!(near(forever.update(100.5), 5.));
+        assert!(!forever.is_finished());
+    }
+
+    #[test]
+    fn yoyo_plays_back_and_ends_at_from() {
+        let mut t = Tween::new(0., 10., 1.).repeat(1).yoyo(true);
+        assert!(near(t.update(0.5), 5.));
+        assert!(near(t.update(0.75), 7.5));
+        assert!(near(t.update(0.5), 2.5));
+        assert!(near(t.advance(0.5), 0.25));
+        assert_eq!(t.value(), 0.);
+
+        t.reset();
+        assert_eq!(t.value(), 0.);
+        assert!(near(t.update(0.25), 2.5));
+    }
+
+    #[test]
+    fn sequence_carries_over_between_items() {
+        let value = Rc::new(Cell::new(0.));
+        let v = value.clone();
+        let mut seq = TweenSequence::new()
+            .then(Tween::new(0., 1., 1.))
+            .wait(0.5)
+            .then(Tween::new(0., 10., 1.).on_update(move |x| v.set(x)));
+
+        assert_eq!(seq.advance(2.), 0.);
+        assert_eq!(seq.current(), 2);
+        assert!(near(value.get(), 5.));
+        assert!(near(seq.advance(1.), 0.5));
+        assert!(seq.is_finished());
+        assert_eq!(value.get(), 10.);
+
+        seq.reset();
+        assert_eq!(seq.current(), 0);
+        assert!(near(seq.advance(3.), 0.5));
+    }
+
+    #[test]
+    fn group_finishes_with_the_longest() {
+        let mut group = TweenGroup::new()
+            .with(Tween::new(0., 1., 1.))
+            .with(TweenDelay::new(2.))
+            .with(Tween::new(0., 1., 0.5).repeat(2));
+        assert_eq!(group.advance(1.25), 0.);
+        assert_eq!(group.advance(0.5), 0.);
+        assert!(near(group.advance(1.), 0.75));
+        assert!(group.is_finished());
+    }
+}
@@ -1,6 +1,6 @@
 use nalgebra_glm as glm;
 
-use crate::sys::{Rect, Rectui, Transform};
+use crate::sys::{Rect, Rectui, Transform, PI, TWO_PI};
 
 pub struct TupleVector(f32, f32, f32);
 pub const UP_VECTOR: TupleVector = TupleVector(0., 1., 0.);
@@ -118,6 +118,17 @@ impl Vertex2D {
         Vertex2D { position: Vert2DPosition { x: 0., y: 0., z: 0. }, text_coord: Vert2DTextureCoord { u: 0., v: 0. }, color: Vert2DColor { r: 0., g: 0., b: 0., a: 0. } }      
     }
  
+    /**
+     * Untextured vertex at z = 0
+    */
+    pub fn colored(position: glm::Vec2, color: &glm::Vec4) -> Self {
+        Vertex2D { 
+            position: Vert2DPosition { x: position.x, y: position.y, z: 0. }, 
+            text_coord: Vert2DTextureCoord { u: 0., v: 0. }, 
+            color: color.into() 
+        }
+    }
+
     pub fn as_slice(&self) -> &[f32; 9] {
         unsafe { std::mem::transmute(self) }
     }
@@ -131,4 +142,683 @@ impl Vert2DColor {
     pub const fn white() -> Self {
         Vert2DColor { r: 1., g: 1., b: 1., a: 1. }
     }
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LineJoin {
+    Miter,
+    Bevel,
+    Round,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LineCap {
+    Butt,
+    Square,
+    Round,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct StrokeStyle {
+    pub thickness: f32,
+    pub join: LineJoin,
+    pub cap: LineCap,
+    /**
+     * Miter joins longer than miter_limit * thickness / 2 fall back to bevel
+    */
+    pub miter_limit: f32,
+    /**
+     * Segments used for a full circle by round joins and caps
+    */
+    pub round_segments: u32,
+}
+
+impl StrokeStyle {
+    pub fn new(thickness: f32) -> Self {
+        StrokeStyle { thickness, ..StrokeStyle::default() }
+    }
+}
+
+impl Default for StrokeStyle {
+    fn default() -> Self {
+        StrokeStyle { thickness: 1., join: LineJoin::Miter, cap: LineCap::Butt, miter_limit: 4., round_segments: 32 }
+    }
+}
+
+/**
+ * Indexed triangle list (3 indices per triangle), ready for VertexBuffer::new(&t.vertices, ..) 
+ * and ElementBuffer::new(&t.indices)
+*/
+#[derive(Clone, Default)]
+pub struct Tessellation {
+    pub vertices: Vec<Vertex2D>,
+    pub indices: Vec<u32>,
+}
+
+impl Tessellation {
+    pub fn new() -> Self {
+        Tessellation::default()
+    }
+
+    pub fn is_empty(&self) -> bool { self.indices.is_empty() }
+
+    pub fn push_vertex(&mut self, position: glm::Vec2, color: &glm::Vec4) -> u32 {
+        self.vertices.push(Vertex2D::colored(position, color));
+        (self.vertices.len() - 1) as u32
+    }
+
+    pub fn push_triangle(&mut self, a: u32, b: u32, c: u32) {
+        self.indices.extend_from_slice(&[a, b, c]);
+    }
+
+    /**
+     * Appends other, offsetting its indices past this tessellation's vertices
+    */
+    pub fn append(&mut self, other: &Tessellation) {
+        let offset = self.vertices.len() as u32;
+        self.vertices.extend_from_slice(&other.vertices);
+        self.indices.extend(other.indices.iter().map(|i| i + offset));
+    }
+
+    pub fn set_color(&mut self, color: &glm::Vec4) {
+        self.vertices.iter_mut().set_color(color);
+    }
+
+    /**
+     * Expands indices into a non-indexed triangle list
+    */
+    pub fn to_triangle_list(&self) -> Vec<Vertex2D> {
+        self.indices.iter().map(|i| self.vertices[*i as usize]).collect()
+    }
+}
+
+/**
+ * Fast path for convex polygons, fans out from the first point
+*/
+pub fn triangulate_convex(points: &[glm::Vec2], color: &glm::Vec4) -> Tessellation {
+    let mut t = Tessellation::new();
+    if points.len() < 3 {
+        return t;
+    }
+    for p in points {
+        t.push_vertex(*p, color);
+    }
+    for i in 1..points.len() as u32 - 1 {
+        t.push_triangle(0, i, i + 1);
+    }
+    t
+}
+
+/**
+ * Triangulates a simple (non self-intersecting) polygon of either winding with ear clipping.
+ * Convex polygons take the triangulate_convex() fast path
+*/
+pub fn triangulate_polygon(points: &[glm::Vec2], color: &glm::Vec4) -> Tessellation {
+    triangulate_polygon_with_holes(points, &[], color)
+}
+
+/**
+ * Triangulates outer polygon minus holes. Holes must lie inside outer and not overlap each other.
+ * Each hole is joined to the outer boundary with a bridge edge, then the result is ear clipped
+*/
+pub fn triangulate_polygon_with_holes(outer: &[glm::Vec2], holes: &[Vec<glm::Vec2>], color: &glm::Vec4) -> Tessellation {
+    let outer = clean_contour(outer);
+    if outer.len() < 3 {
+        return Tessellation::new();
+    }
+    if holes.is_empty() && is_convex(&outer) {
+        return triangulate_convex(&outer, color);
+    }
+
+    let mut t = Tessellation::new();
+    let mut positions: Vec<glm::Vec2> = Vec::new();
+
+    let mut polygon: Vec<u32> = oriented_indices(&mut positions, &outer, true);
+
+    let mut hole_rings: Vec<Vec<u32>> = holes.iter()
+        .map(|h| clean_contour(h))
+        .filter(|h| h.len() >= 3)
+        .map(|h| oriented_indices(&mut positions, &h, false))
+        .collect();
+
+    // Bridging rightmost holes first keeps bridges from crossing holes that have not been merged yet
+    let max_x = |ring: &Vec<u32>, positions: &Vec<glm::Vec2>| ring.iter().map(|i| positions[*i as usize].x).fold(f32::MIN, f32::max);
+    hole_rings.sort_by(|a, b| max_x(b, &positions).partial_cmp(&max_x(a, &positions)).unwrap_or(std::cmp::Ordering::Equal));
+    for hole in hole_rings.iter() {
+        bridge_hole(&positions, &mut polygon, hole);
+    }
+
+    for p in positions.iter() {
+        t.push_vertex(*p, color);
+    }
+    t.indices = ear_clip(&positions, polygon);
+    t
+}
+
+/**
+ * Strokes line segments through points into an indexed triangle strip, with joins between
+ * segments and caps on both ends. Caps are ignored when closed
+*/
+pub fn stroke_polyline(points: &[glm::Vec2], closed: bool, style: &StrokeStyle, color: &glm::Vec4) -> Tessellation {
+    let mut t = Tessellation::new();
+    let mut pts = clean_contour(points);
+    if !closed && points.len() >= 2 && pts.len() < points.len() && glm::distance(&points[0], &points[points.len() - 1]) <= f32::EPSILON {
+        // clean_contour() drops a repeated end point, which is only right for closed contours
+        pts.push(points[points.len() - 1]);
+    }
+    if pts.len() < 2 || (closed && pts.len() < 3) {
+        return t;
+    }
+
+    let hw = style.thickness * 0.5;
+    let seg_count = if closed { pts.len() } else { pts.len() - 1 };
+    let dir = |i: usize| glm::normalize(&(pts[(i + 1) % pts.len()] - pts[i]));
+    let left = |d: glm::Vec2| glm::vec2(-d.y, d.x) * hw;
+
+    // Each segment is a quad of [a left, a right, b right, b left]
+    let mut quads: Vec<[u32; 4]> = Vec::with_capacity(seg_count);
+    for i in 0..seg_count {
+        let (a, b) = (pts[i], pts[(i + 1) % pts.len()]);
+        let n = left(dir(i));
+        let q = [t.push_vertex(a + n, color), t.push_vertex(a - n, color), t.push_vertex(b - n, color), t.push_vertex(b + n, color)];
+        t.push_triangle(q[0], q[1], q[2]);
+        t.push_triangle(q[0], q[2], q[3]);
+        quads.push(q);
+    }
+
+    let join_range = if closed { 0..seg_count } else { 1..seg_count };
+    for i in join_range {
+        let prev = if i == 0 { seg_count - 1 } else { i - 1 };
+        let (d0, d1) = (dir(prev), dir(i));
+        let cross = d0.x * d1.y - d0.y * d1.x;
+        if cross.abs() < 1e-6 && glm::dot(&d0, &d1) > 0. {
+            continue;
+        }
+
+        // Turning left opens the gap on the right side of the line
+        let (side, o0, o1) = if cross > 0. {
+            (-1., quads[prev][2], quads[i][1])
+        } else {
+            (1., quads[prev][3], quads[i][0])
+        };
+        let p = pts[i];
+        let center = t.push_vertex(p, color);
+        let n0 = left(d0) * side;
+        let n1 = left(d1) * side;
+
+        match style.join {
+            LineJoin::Bevel => t.push_triangle(center, o0, o1),
+            LineJoin::Miter => {
+                let m = n0 + n1;
+                let m_len = glm::length(&m);
+                let cos_half = if m_len > 1e-6 { glm::dot(&(m / m_len), &(n0 / hw)) } else { 0. };
+                let miter_len = if cos_half > 1e-6 { hw / cos_half } else { f32::INFINITY };
+
+                if miter_len > style.miter_limit * hw {
+                    t.push_triangle(center, o0, o1);
+                } else {
+                    let tip = t.push_vertex(p + m / m_len * miter_len, color);
+                    t.push_triangle(center, o0, tip);
+                    t.push_triangle(center, tip, o1);
+                }
+            },
+            LineJoin::Round => {
+                let a0 = n0.y.atan2(n0.x);
+                let mut sweep = n1.y.atan2(n1.x) - a0;
+                if sweep > PI { sweep -= TWO_PI } else if sweep < -PI { sweep += TWO_PI }
+                push_round(&mut t, (center, o0, o1), RoundArc { p, start: a0, sweep, radius: hw }, style.round_segments, color);
+            }
+        }
+    }
+
+    if !closed {
+        let first = quads[0];
+        let last = quads[seg_count - 1];
+        push_cap(&mut t, pts[0], -dir(0), first[1], first[0], style, color);
+        push_cap(&mut t, pts[pts.len() - 1], dir(seg_count - 1), last[3], last[2], style, color);
+    }
+    t
+}
+
+/**
+ * left and right are the existing stroke corners on either side of p, as seen looking along outward
+*/
+fn push_cap(t: &mut Tessellation, p: glm::Vec2, outward: glm::Vec2, left: u32, right: u32, style: &StrokeStyle, color: &glm::Vec4) {
+    let hw = style.thickness * 0.5;
+    let n = glm::vec2(-outward.y, outward.x) * hw;
+    match style.cap {
+        LineCap::Butt => {},
+        LineCap::Square => {
+            let ext = outward * hw;
+            let a = t.push_vertex(p + n + ext, color);
+            let b = t.push_vertex(p - n + ext, color);
+            t.push_triangle(left, right, b);
+            t.push_triangle(left, b, a);
+        },
+        LineCap::Round => {
+            let center = t.push_vertex(p, color);
+            push_round(t, (center, left, right), RoundArc { p, start: n.y.atan2(n.x), sweep: -PI, radius: hw }, style.round_segments, color);
+        }
+    }
+}
+
+/**
+ * Arc of a round join or cap around p, sweep is in radians from start
+*/
+struct RoundArc {
+    p: glm::Vec2,
+    start: f32,
+    sweep: f32,
+    radius: f32,
+}
+
+/**
+ * Fans around center from first to last (fan is (center, first, last)), which must already sit
+ * at the start and end of the arc
+*/
+fn push_round(t: &mut Tessellation, fan: (u32, u32, u32), arc: RoundArc, segments: u32, color: &glm::Vec4) {
+    let (center, first, last) = fan;
+    let steps = ((arc.sweep.abs() / TWO_PI) * segments.max(3) as f32).ceil().max(1.) as u32;
+    let mut prev = first;
+    for i in 1..steps {
+        let a = arc.start + arc.sweep * i as f32 / steps as f32;
+        let v = t.push_vertex(arc.p + glm::vec2(a.cos(), a.sin()) * arc.radius, color);
+        t.push_triangle(center, prev, v);
+        prev = v;
+    }
+    t.push_triangle(center, prev, last);
+}
+
+/**
+ * Twice the signed area, positive for counter clockwise winding
+*/
+pub fn signed_area(points: &[glm::Vec2]) -> f32 {
+    let mut area = 0.;
+    for i in 0..points.len() {
+        let (a, b) = (points[i], points[(i + 1) % points.len()]);
+        area += a.x * b.y - b.x * a.y;
+    }
+    area * 0.5
+}
+
+pub fn is_convex(points: &[glm::Vec2]) -> bool {
+    let n = points.len();
+    if n < 3 {
+        return false;
+    }
+    let mut sign = 0.;
+    for i in 0..n {
+        let c = cross(points[i], points[(i + 1) % n], points[(i + 2) % n]);
+        if c.abs() < 1e-6 {
+            continue;
+        }
+        if sign == 0. {
+            sign = c.signum();
+        } else if c.signum() != sign {
+            return false;
+        }
+    }
+    sign != 0.
+}
+
+fn cross(o: glm::Vec2, a: glm::Vec2, b: glm::Vec2) -> f32 {
+    (a.x - o.x) * (b.y - o.y) - (a.y - o.y) * (b.x - o.x)
+}
+
+fn point_in_triangle(p: glm::Vec2, a: glm::Vec2, b: glm::Vec2, c: glm::Vec2) -> bool {
+    let (d1, d2, d3) = (cross(a, b, p), cross(b, c, p), cross(c, a, p));
+    let has_neg = d1 < 0. || d2 < 0. || d3 < 0.;
+    let has_pos = d1 > 0. || d2 > 0. || d3 > 0.;
+    !(has_neg && has_pos)
+}
+
+/**
+ * Drops repeated consecutive points, including a last point that closes back onto the first
+*/
+fn clean_contour(points: &[glm::Vec2]) -> Vec<glm::Vec2> {
+    let mut out: Vec<glm::Vec2> = Vec::with_capacity(points.len());
+    for p in points {
+        if out.last().is_none_or(|l| glm::distance(l, p) > f32::EPSILON) {
+            out.push(*p);
+        }
+    }
+    if out.len() > 1 && glm::distance(&out[0], &out[out.len() - 1]) <= f32::EPSILON {
+        out.pop();
+    }
+    out
+}
+
+/**
+ * Appends points to positions, returning their indices wound counter clockwise (or clockwise)
+*/
+fn oriented_indices(positions: &mut Vec<glm::Vec2>, points: &[glm::Vec2], ccw: bool) -> Vec<u32> {
+    let start = positions.len() as u32;
+    positions.extend_from_slice(points);
+    let mut indices: Vec<u32> = (start..start + points.len() as u32).collect();
+    if (signed_area(points) > 0.) != ccw {
+        indices.reverse();
+    }
+    indices
+}
+
+/**
+ * Splices clockwise hole into counter clockwise polygon through a bridge from the hole's 
+ * rightmost vertex to a visible polygon vertex
+*/
+fn bridge_hole(positions: &[glm::Vec2], polygon: &mut Vec<u32>, hole: &[u32]) {
+    let pos = |i: u32| positions[i as usize];
+    let (hole_start, m) = hole.iter().enumerate()
+        .map(|(k, i)| (k, pos(*i)))
+        .fold((0, glm::vec2(f32::MIN, 0.)), |best, cur| if cur.1.x > best.1.x { cur } else { best });
+
+    // Closest edge hit by a ray from m towards +x
+    let n = polygon.len();
+    let mut hit_x = f32::INFINITY;
+    let mut visible = None;
+    for k in 0..n {
+        let (a, b) = (pos(polygon[k]), pos(polygon[(k + 1) % n]));
+        if (a.y - m.y) * (b.y - m.y) > 0. || a.y == b.y {
+            continue;
+        }
+        let x = a.x + (m.y - a.y) * (b.x - a.x) / (b.y - a.y);
+        if x >= m.x && x < hit_x {
+            hit_x = x;
+            visible = Some(if a.x > b.x { k } else { (k + 1) % n });
+        }
+    }
+
+    let mut visible = match visible {
+        Some(v) => v,
+        // Hole is not inside polygon, bridge to the closest vertex so we at least produce something
+        None => (0..n).min_by(|a, b| {
+            glm::distance(&pos(polygon[*a]), &m).partial_cmp(&glm::distance(&pos(polygon[*b]), &m)).unwrap()
+        }).unwrap()
+    };
+
+    // A reflex vertex inside (m, hit, visible) would block the bridge, take the one closest in angle instead
+    if hit_x.is_finite() {
+        let hit = glm::vec2(hit_x, m.y);
+        let p = pos(polygon[visible]);
+        let mut best_angle = f32::MAX;
+        for k in 0..n {
+            if k == visible {
+                continue;
+            }
+            let v = pos(polygon[k]);
+            let reflex = cross(pos(polygon[(k + n - 1) % n]), v, pos(polygon[(k + 1) % n])) < 0.;
+            if reflex && v.x >= m.x && point_in_triangle(v, m, hit, p) {
+                let angle = (v.y - m.y).abs().atan2(v.x - m.x);
+                if angle < best_angle {
+                    best_angle = angle;
+                    visible = k;
+                }
+            }
+        }
+    }
+
+    let mut merged = Vec::with_capacity(n + hole.len() + 2);
+    merged.extend_from_slice(&polygon[..=visible]);
+    for k in 0..=hole.len() {
+        merged.push(hole[(hole_start + k) % hole.len()]);
+    }
+    merged.push(polygon[visible]);
+    merged.extend_from_slice(&polygon[visible + 1..]);
+    *polygon = merged;
+}
+
+/**
+ * Removes vertices on straight runs and the tips of zero width spikes, neither covers any area.
+ * Left in, a spike's tip counts as a convex corner and lets ears reach outside the polygon
+*/
+fn drop_degenerate(positions: &[glm::Vec2], polygon: &mut Vec<u32>) {
+    let pos = |i: u32| positions[i as usize];
+    let (mut k, mut unchanged) = (0, 0);
+    while polygon.len() >= 3 && unchanged < polygon.len() {
+        let n = polygon.len();
+        k %= n;
+        if cross(pos(polygon[(k + n - 1) % n]), pos(polygon[k]), pos(polygon[(k + 1) % n])).abs() <= 1e-9 {
+            polygon.remove(k);
+            // Step back so the previous vertex gets re-tested with its new neighbour
+            k = (k + n - 2) % (n - 1);
+            unchanged = 0;
+        } else {
+            k += 1;
+            unchanged += 1;
+        }
+    }
+}
+
+/**
+ * Ear clips counter clockwise polygon (indices into positions), returning triangle indices
+*/
+fn ear_clip(positions: &[glm::Vec2], mut polygon: Vec<u32>) -> Vec<u32> {
+    let pos = |i: u32| positions[i as usize];
+    let mut out = Vec::with_capacity(polygon.len().saturating_sub(2) * 3);
+    drop_degenerate(positions, &mut polygon);
+    let mut i = 0;
+    let mut misses = 0;
+
+    while polygon.len() > 3 {
+        let n = polygon.len();
+        i %= n;
+        let (ia, ib, ic) = (polygon[(i + n - 1) % n], polygon[i], polygon[(i + 1) % n]);
+        let (a, b, c) = (pos(ia), pos(ib), pos(ic));
+        let area = cross(a, b, c);
+
+        if area.abs() <= 1e-9 {
+            // Clipping can leave new straight runs behind, see drop_degenerate()
+            polygon.remove(i);
+            misses = 0;
+            i = (i + polygon.len() - 1) % polygon.len();
+            continue;
+        }
+
+        let is_ear = area > 1e-9 && !polygon.iter().any(|j| {
+            let p = pos(*j);
+            // Bridges duplicate vertices, anything sitting on a corner does not block the ear
+            *j != ia && *j != ib && *j != ic && p != a && p != b && p != c && point_in_triangle(p, a, b, c)
+        });
+
+        if is_ear {
+            out.extend_from_slice(&[ia, ib, ic]);
+            polygon.remove(i);
+            misses = 0;
+            // Step back so the previous vertex gets re-tested with its new neighbour
+            i = (i + polygon.len() - 1) % polygon.len();
+        } else if misses > n {
+            // No ear left, polygon is self intersecting. Drop the vertex and keep going, only keeping
+            // its triangle when it's wound the right way, reflex corners would cover area outside
+            if area > 1e-9 {
+                out.extend_from_slice(&[ia, ib, ic]);
+            }
+            polygon.remove(i);
+            misses = 0;
+        } else {
+            i += 1;
+            misses += 1;
+        }
+    }
+
+    if polygon.len() == 3 && cross(pos(polygon[0]), pos(polygon[1]), pos(polygon[2])) > 1e-9 {
+        out.extend_from_slice(&polygon);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn white() -> glm::Vec4 { glm::vec4(1., 1., 1., 1.) }
+
+    fn pts(coords: &[(f32, f32)]) -> Vec<glm::Vec2> {
+        coords.iter().map(|(x, y)| glm::vec2(*x, *y)).collect()
+    }
+
+    fn triangle_areas(t: &Tessellation) -> Vec<f32> {
+        let pos = |i: u32| { let p = t.vertices[i as usize].position; glm::vec2(p.x, p.y) };
+        t.indices.chunks(3).map(|c| cross(pos(c[0]), pos(c[1]), pos(c[2])) * 0.5).collect()
+    }
+
+    /**
+     * Sum of triangle areas, asserting every triangle is wound counter clockwise
+    */
+    fn filled_area(t: &Tessellation) -> f32 {
+        let areas = triangle_areas(t);
+        assert!(areas.iter().all(|a| *a >= -1e-5), "clockwise triangle in {:?}", areas);
+        areas.iter().sum()
+    }
+
+    fn stroked_area(t: &Tessellation) -> f32 {
+        triangle_areas(t).iter().map(|a| a.abs()).sum()
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-3, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn convex_fan() {
+        let square = pts(&[(0., 0.), (2., 0.), (2., 2.), (0., 2.)]);
+        let t = triangulate_convex(&square, &white());
+        assert_eq!(t.vertices.len(), 4);
+        assert_eq!(t.indices, vec![0, 1, 2, 0, 2, 3]);
+        assert_close(filled_area(&t), 4.);
+    }
+
+    #[test]
+    fn concave_ear_clip() {
+        let l_shape = pts(&[(0., 0.), (2., 0.), (2., 1.), (1., 1.), (1., 2.), (0., 2.)]);
+        assert!(!is_convex(&l_shape));
+        let t = triangulate_polygon(&l_shape, &white());
+        assert_eq!(t.indices.len(), 4 * 3);
+        assert_close(filled_area(&t), signed_area(&l_shape));
+    }
+
+    #[test]
+    fn clockwise_input_is_wound_counter_clockwise() {
+        let mut l_shape = pts(&[(0., 0.), (2., 0.), (2., 1.), (1., 1.), (1., 2.), (0., 2.)]);
+        l_shape.reverse();
+        let t = triangulate_polygon(&l_shape, &white());
+        assert_eq!(t.indices.len(), 4 * 3);
+        assert_close(filled_area(&t), 3.);
+    }
+
+    #[test]
+    fn polygon_with_holes() {
+        let outer = pts(&[(0., 0.), (10., 0.), (10., 10.), (0., 10.)]);
+        let holes = vec![
+            pts(&[(2., 2.), (4., 2.), (4., 4.), (2., 4.)]),
+            pts(&[(6., 6.), (6., 8.), (8., 8.), (8., 6.)]),
+        ];
+        let t = triangulate_polygon_with_holes(&outer, &holes, &white());
+        // n + 2h - 2 triangles for n vertices and h holes
+        assert_eq!(t.indices.len(), (12 + 2 * 2 - 2) * 3);
+        assert_close(filled_area(&t), 100. - 4. - 4.);
+    }
+
+    #[test]
+    fn degenerate_input() {
+        assert!(triangulate_polygon(&pts(&[(0., 0.), (1., 1.)]), &white()).is_empty());
+        assert!(triangulate_polygon(&pts(&[(0., 0.), (0., 0.), (0., 0.)]), &white()).is_empty());
+        assert!(triangulate_polygon(&pts(&[(0., 0.), (1., 1.), (2., 2.), (3., 3.)]), &white()).is_empty());
+        assert!(stroke_polyline(&pts(&[(1., 1.)]), false, &StrokeStyle::new(2.), &white()).is_empty());
+    }
+
+    #[test]
+    fn collinear_points_on_edges() {
+        let square = pts(&[(0., 0.), (1., 0.), (2., 0.), (2., 2.), (1., 2.), (0., 2.), (0., 1.)]);
+        let t = triangulate_polygon(&square, &white());
+        assert_close(filled_area(&t), 4.);
+
+        let notched = pts(&[(0., 0.), (1., 0.), (2., 0.), (2., 2.), (1., 1.), (0., 2.)]);
+        let t = triangulate_polygon(&notched, &white());
+        assert_close(filled_area(&t), signed_area(&notched));
+    }
+
+    #[test]
+    fn degenerate_fallback_stays_inside() {
+        // Zero width spikes leave no ear at some point, the fallback must not emit clockwise triangles
+        let spiky = pts(&[(0., 0.), (4., 0.), (4., 4.), (3., 4.), (3., 8.), (3., 4.), (1., 4.), (1., 8.), (1., 4.), (0., 4.)]);
+        let t = triangulate_polygon(&spiky, &white());
+        assert_close(filled_area(&t), 16.);
+
+        let bowtie = pts(&[(0., 0.), (2., 2.), (2., 0.), (0., 2.)]);
+        let t = triangulate_polygon(&bowtie, &white());
+        filled_area(&t);
+    }
+
+    fn corner(join: LineJoin) -> Tessellation {
+        let style = StrokeStyle { join, ..StrokeStyle::new(2.) };
+        stroke_polyline(&pts(&[(0., 0.), (10., 0.), (10., 10.)]), false, &style, &white())
+    }
+
+    #[test]
+    fn bevel_join() {
+        let t = corner(LineJoin::Bevel);
+        assert_eq!(t.indices.len(), 2 * 6 + 3);
+        assert_close(stroked_area(&t), 40. + 0.5);
+    }
+
+    #[test]
+    fn miter_join() {
+        let t = corner(LineJoin::Miter);
+        assert_eq!(t.indices.len(), 2 * 6 + 6);
+        assert_close(stroked_area(&t), 40. + 1.);
+
+        // A hairpin turn is far past the miter limit and falls back to bevel
+        let style = StrokeStyle::new(2.);
+        let t = stroke_polyline(&pts(&[(0., 0.), (10., 0.), (0., 0.5)]), false, &style, &white());
+        assert_eq!(t.indices.len(), 2 * 6 + 3);
+    }
+
+    #[test]
+    fn round_join() {
+        let t = corner(LineJoin::Round);
+        // A quarter turn uses a quarter of round_segments
+        assert_eq!(t.indices.len(), 2 * 6 + 8 * 3);
+        assert!((stroked_area(&t) - (40. + PI / 4.)).abs() < 0.01);
+    }
+
+    #[test]
+    fn straight_joins_are_skipped() {
+        let t = stroke_polyline(&pts(&[(0., 0.), (5., 0.), (10., 0.)]), false, &StrokeStyle::new(2.), &white());
+        assert_eq!(t.indices.len(), 2 * 6);
+        assert_close(stroked_area(&t), 20.);
+    }
+
+    fn segment(cap: LineCap) -> Tessellation {
+        let style = StrokeStyle { cap, ..StrokeStyle::new(2.) };
+        stroke_polyline(&pts(&[(0., 0.), (10., 0.)]), false, &style, &white())
+    }
+
+    #[test]
+    fn butt_cap() {
+        let t = segment(LineCap::Butt);
+        assert_eq!(t.indices.len(), 6);
+        assert_close(stroked_area(&t), 20.);
+    }
+
+    #[test]
+    fn square_cap() {
+        let t = segment(LineCap::Square);
+        assert_eq!(t.indices.len(), 6 + 2 * 6);
+        assert_close(stroked_area(&t), 24.);
+    }
+
+    #[test]
+    fn round_cap() {
+        let t = segment(LineCap::Round);
+        // Each cap is a half circle, half of round_segments
+        assert_eq!(t.indices.len(), 6 + 2 * 16 * 3);
+        assert!((stroked_area(&t) - (20. + PI)).abs() < 0.05);
+    }
+
+    #[test]
+    fn closed_stroke_has_no_caps() {
+        let style = StrokeStyle { cap: LineCap::Square, join: LineJoin::Bevel, ..StrokeStyle::new(2.) };
+        let square = pts(&[(0., 0.), (10., 0.), (10., 10.), (0., 10.)]);
+        let t = stroke_polyline(&square, true, &style, &white());
+        assert_eq!(t.indices.len(), 4 * 6 + 4 * 3);
+    }
+}